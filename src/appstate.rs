@@ -1,10 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex as StdMutex;
 use std::io::Write;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::UnboundedSender;
 use crate::modes::{Target, ScanResult};
-use crate::configuration::{Config, FormatScan};
+use crate::configuration::{Config, FormatScan, Protocol};
 use tokio::task::JoinHandle;
 use log::{info, error};
 use serde::{Serialize, Deserialize};
@@ -37,14 +39,240 @@ impl Port {
     }
 }
 
+/// A worker's part of a distributed scan (`--worker-id`), so the reports of all
+/// workers can be merged and checked for gaps: their `targets` add up to
+/// `plan_targets`, and each is complete when `scanned` reaches `targets`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShardReport {
+    pub worker_id: u64,
+    pub total_workers: u64,
+    pub seed: u64,
+    /// Targets of the first phase over all workers.
+    pub plan_targets: u64,
+    /// This worker's share of them.
+    pub targets: u64,
+    /// How many of its share were scanned before it stopped.
+    pub scanned: u64,
+}
+
+impl std::fmt::Display for ShardReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "worker {} of {}, seed {}, {} of {} targets scanned ({} over all workers)",
+            self.worker_id, self.total_workers, self.seed, self.scanned, self.targets, self.plan_targets)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     results: HashMap<String, Vec<Port>>,
+    /// Port and protocol of each result per host, so a port is reported once.
+    reported: HashMap<String, HashSet<(u16, Protocol)>>,
+    /// Hosts given up on after only timeouts (remaining ports skipped).
+    down_hosts: BTreeSet<String>,
+    /// Hostnames each address was resolved from.
+    hostnames: BTreeMap<String, BTreeSet<String>>,
+    /// PTR names from reverse lookups after the scan (`--reverse-dns`).
+    ptr_names: BTreeMap<String, BTreeSet<String>>,
     port_scanned: u64,
+    /// `--exclude-ports`, in port list syntax, for the report header.
+    excluded_ports: Option<String>,
+    shard: Option<ShardReport>,
     start_time: std::time::SystemTime,
     args: String,
 }
 
+/// Where a scan stopped: every target of `phase` before `position` (in strategy
+/// order) is done. `hosts` are the targets of that phase when it is not the first
+/// (they depend on earlier responses), `responded` the hosts that already answered
+/// in it, and `seed` is the port shuffle seed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResumePoint {
+    pub phase: usize,
+    pub position: u64,
+    pub seed: Option<u64>,
+    pub hosts: Option<Vec<String>>,
+    pub responded: Vec<String>,
+}
+
+/// Contents of a resume file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResumeFile {
+    Position(ResumePoint),
+    /// Older format: one completed host per line.
+    Hosts(Vec<String>),
+}
+
+/// Completed target indices. Targets finish out of order, so everything below
+/// `next` is done and `ahead` holds the finished ones above it.
+#[derive(Debug, Default)]
+struct Watermark {
+    next: u64,
+    ahead: BTreeSet<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    phase: usize,
+    hosts: Option<Vec<String>>,
+    responded: BTreeSet<String>,
+    watermark: Watermark,
+}
+
+/// Live scan counters that are not port results (shown in the progress bar and dashboard).
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    /// Probes delayed by `--ratelimit-per-host` / `--max-concurrent-per-host`.
+    pub throttled_host: AtomicU64,
+    /// Probes delayed by `--subnet-limit`.
+    pub throttled_subnet: AtomicU64,
+    /// Probes finished in the current phase.
+    pub probes_done: AtomicU64,
+    /// Probes the current phase has to scan from where it started.
+    phase_probes: AtomicU64,
+    progress: StdMutex<Progress>,
+    seed: Option<u64>,
+    /// Port frequency tier the scan has reached, for strategies that report one.
+    tier: StdMutex<Option<&'static str>>,
+}
+
+impl ScanStats {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self { seed, ..Default::default() }
+    }
+
+    /// Reset progress tracking for a new phase of `probes` probes starting at `position`.
+    pub fn start_phase(&self, phase: usize, hosts: Option<Vec<String>>, position: u64, probes: u64) {
+        *self.progress.lock().unwrap() = Progress {
+            phase,
+            hosts,
+            responded: BTreeSet::new(),
+            watermark: Watermark { next: position, ahead: BTreeSet::new() },
+        };
+        self.probes_done.store(0, Ordering::Relaxed);
+        self.phase_probes.store(probes, Ordering::Relaxed);
+    }
+
+    /// The current phase, and its probes done and to do in all.
+    pub fn phase_progress(&self) -> (usize, u64, u64) {
+        let phase = self.progress.lock().unwrap().phase;
+        (phase, self.probes_done.load(Ordering::Relaxed), self.phase_probes.load(Ordering::Relaxed))
+    }
+
+    /// Record a host that answered in the current phase (it moves on to the next one).
+    pub fn host_responded(&self, host: &str) {
+        let mut progress = self.progress.lock().unwrap();
+        if !progress.responded.contains(host) {
+            progress.responded.insert(host.to_string());
+        }
+    }
+
+    /// Hosts that answered in the current phase, sorted.
+    pub fn responded_hosts(&self) -> Vec<String> {
+        self.progress.lock().unwrap().responded.iter().cloned().collect()
+    }
+
+    /// Mark the target at `index` (all of its scan types) as finished.
+    pub fn complete_target(&self, index: u64) {
+        let mark = &mut self.progress.lock().unwrap().watermark;
+        if index != mark.next {
+            mark.ahead.insert(index);
+            return;
+        }
+        mark.next += 1;
+        while mark.ahead.remove(&mark.next) {
+            mark.next += 1;
+        }
+    }
+
+    pub fn resume_point(&self) -> ResumePoint {
+        let progress = self.progress.lock().unwrap();
+        ResumePoint {
+            phase: progress.phase,
+            position: progress.watermark.next,
+            seed: self.seed,
+            hosts: progress.hosts.clone(),
+            responded: progress.responded.iter().cloned().collect(),
+        }
+    }
+
+    /// Save resume file: the phase and position to continue from, the shuffle
+    /// seed, and the phase's hosts if they came from an earlier phase.
+    pub fn save_resume_file(&self, path: &str) -> Result<(), String> {
+        let point = self.resume_point();
+        let mut content = String::from("# carescanner resume file: rerun with the same targets and options plus --resume-from\n");
+        if point.phase > 0 {
+            content.push_str(&format!("phase {}\n", point.phase));
+        }
+        content.push_str(&format!("position {}\n", point.position));
+        if let Some(seed) = point.seed {
+            content.push_str(&format!("seed {}\n", seed));
+        }
+        for host in point.hosts.iter().flatten() {
+            content.push_str(&format!("host {}\n", host));
+        }
+        for host in &point.responded {
+            content.push_str(&format!("responded {}\n", host));
+        }
+        let mut file = std::fs::File::create(path)
+            .map_err(|e| format!("Failed to create resume file '{}': {}", path, e))?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write resume file: {}", e))?;
+        info!("Resume state saved to {} (phase {}, {} targets done)", path, point.phase + 1, point.position);
+        Ok(())
+    }
+
+    /// Load resume file. Files without a `position` line are read as the
+    /// older one-host-per-line format.
+    pub fn load_resume_file(path: &str) -> Result<ResumeFile, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read resume file '{}': {}", path, e))?;
+        let lines: Vec<&str> = content
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect();
+
+        if !lines.iter().any(|l| l.starts_with("position ")) {
+            return Ok(ResumeFile::Hosts(lines.into_iter().map(str::to_string).collect()));
+        }
+
+        let mut point = ResumePoint::default();
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            let number = || value.parse::<u64>()
+                .map_err(|e| format!("Invalid '{}' in resume file '{}': {}", line, path, e));
+            match key {
+                "phase" => point.phase = number()? as usize,
+                "position" => point.position = number()?,
+                "seed" => point.seed = Some(number()?),
+                "host" => point.hosts.get_or_insert_with(Vec::new).push(value.to_string()),
+                "responded" => point.responded.push(value.to_string()),
+                _ => return Err(format!("Unknown entry '{}' in resume file '{}'", line, path)),
+            }
+        }
+        Ok(ResumeFile::Position(point))
+    }
+
+    pub fn set_tier(&self, tier: &'static str) {
+        *self.tier.lock().unwrap() = Some(tier);
+    }
+
+    /// Short status line for the progress bar, or None when there is nothing to report.
+    pub fn status_line(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(tier) = *self.tier.lock().unwrap() {
+            parts.push(format!("tier: {}", tier));
+        }
+        let host = self.throttled_host.load(Ordering::Relaxed);
+        let subnet = self.throttled_subnet.load(Ordering::Relaxed);
+        if host > 0 || subnet > 0 {
+            parts.push(format!("throttled: host {}, subnet {}", host, subnet));
+        }
+        (!parts.is_empty()).then(|| parts.join("  "))
+    }
+}
+
 /// Manager for the app state (mpsc channel)
 pub struct AppStateManager {
     app_state: Arc<Mutex<AppState>>,
@@ -52,13 +280,25 @@ pub struct AppStateManager {
     _processor_handle: JoinHandle<()>,
 }
 
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppState {
     pub fn new() -> Self {
         Self {
             results: HashMap::new(),
+            reported: HashMap::new(),
+            down_hosts: BTreeSet::new(),
+            hostnames: BTreeMap::new(),
+            ptr_names: BTreeMap::new(),
             port_scanned: 0,
+            excluded_ports: None,
+            shard: None,
             start_time: std::time::SystemTime::now(),
-            args: std::env::args().collect::<Vec<_>>().join(" "),
+            args: report_args(std::env::args()),
         }
     }
 
@@ -66,6 +306,11 @@ impl AppState {
     pub fn add_result(&mut self, target: Target, result: ScanResult, protocol: String) {
         use crate::modes::PortStatus;
         self.port_scanned += 1;
+        for hostname in target.hostname.iter().chain(&target.aliases) {
+            if !self.hostnames.get(&target.ip).is_some_and(|names| names.contains(hostname)) {
+                self.hostnames.entry(target.ip.clone()).or_default().insert(hostname.clone());
+            }
+        }
 
         let state = match result.status {
             PortStatus::Open => PortState::Open,
@@ -80,13 +325,66 @@ impl AppState {
             banner: result.banner,
         };
 
-        self.results.entry(target.ip).or_default().push(port);
+        // Overlapping targets (a range and an address in it) probe a port twice; report it once.
+        let key = (port.number, if port.protocol == "udp" { Protocol::Udp } else { Protocol::Tcp });
+        if self.reported.entry(target.ip.clone()).or_default().insert(key) {
+            self.results.entry(target.ip).or_default().push(port);
+        }
     }
 
     pub fn get_results(&self) -> &HashMap<String, Vec<Port>> {
         &self.results
     }
 
+    pub fn mark_host_down(&mut self, host: String) {
+        self.down_hosts.insert(host);
+    }
+
+    pub fn down_hosts(&self) -> &BTreeSet<String> {
+        &self.down_hosts
+    }
+
+    pub fn hostnames(&self, host: &str) -> impl Iterator<Item = &String> {
+        self.hostnames.get(host).into_iter().flatten()
+    }
+
+    pub fn set_excluded_ports(&mut self, ports: String) {
+        self.excluded_ports = Some(ports);
+    }
+
+    pub fn set_shard(&mut self, shard: ShardReport) {
+        self.shard = Some(shard);
+    }
+
+    pub fn add_ptr_names(&mut self, host: String, names: Vec<String>) {
+        self.ptr_names.entry(host).or_default().extend(names);
+    }
+
+    pub fn ptr_names(&self, host: &str) -> impl Iterator<Item = &String> {
+        self.ptr_names.get(host).into_iter().flatten()
+    }
+
+    /// Every name known for `host`: the ones it was given as, then PTR names.
+    pub fn all_names(&self, host: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = self.hostnames(host).map(String::as_str).collect();
+        for name in self.ptr_names(host) {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// The address followed by its names, e.g. `45.33.32.156 (scanme.nmap.org)`.
+    fn host_label(&self, host: &str) -> String {
+        let names = self.all_names(host);
+        if names.is_empty() {
+            host.to_string()
+        } else {
+            format!("{} ({})", host, names.join(", "))
+        }
+    }
+
     /// Print a summary table to stdout with ANSI colors.
     pub fn print_summary(&self, show_closed: bool) {
         const BOLD: &str = "\x1b[1m";
@@ -110,8 +408,8 @@ impl AppState {
             }
 
             has_results = true;
-            println!("\n{BOLD}{}{RESET}", host);
-            println!("{:<10} {:<8} {}", "PORT", "STATE", "SERVICE");
+            println!("\n{BOLD}{}{RESET}", self.host_label(host));
+            println!("{:<10} {:<8} SERVICE", "PORT", "STATE");
 
             for port in &open {
                 let svc = port.service_name().unwrap_or_default();
@@ -129,6 +427,14 @@ impl AppState {
         if !has_results {
             println!("\nNo open ports found.");
         }
+        if !self.down_hosts.is_empty() {
+            const SHOWN: usize = 20;
+            let mut list: Vec<String> = self.down_hosts.iter().take(SHOWN).cloned().collect();
+            if self.down_hosts.len() > SHOWN {
+                list.push(format!("and {} more", self.down_hosts.len() - SHOWN));
+            }
+            println!("\n{RED}Skipped {} hosts (down, no replies):{RESET} {}", self.down_hosts.len(), list.join(", "));
+        }
         println!();
     }
 
@@ -137,36 +443,12 @@ impl AppState {
         self.results.keys().cloned().collect()
     }
 
-    /// Save resume file: one completed host IP per line (plain text).
-    pub fn save_resume_file(&self, path: &str) -> Result<(), String> {
-        let hosts = self.hosts_with_results();
-        let content = hosts.join("\n");
-        let mut file = std::fs::File::create(path)
-            .map_err(|e| format!("Failed to create resume file '{}': {}", path, e))?;
-        file.write_all(content.as_bytes())
-            .map_err(|e| format!("Failed to write resume file: {}", e))?;
-        info!("Resume state saved to {} ({} hosts)", path, hosts.len());
-        Ok(())
-    }
-
-    /// Load resume file: returns list of host IPs to skip.
-    pub fn load_resume_file(path: &str) -> Result<Vec<String>, String> {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e| format!("Failed to read resume file '{}': {}", path, e))?;
-        let hosts: Vec<String> = content
-            .lines()
-            .map(|l| l.trim().to_string())
-            .filter(|l| !l.is_empty())
-            .collect();
-        Ok(hosts)
-    }
-
     /// Save current results to file based on config format
     pub fn save_to_file(&self, config: &Config) -> Result<String, String> {
         match config.format {
             FormatScan::Json => {
                 let path = config.output.clone().unwrap_or_else(|| "carescanner_results.json".to_string());
-                self.write_file(&path, &self.format_json())?;
+                self.write_file(&path, &self.format_json(config.json_envelope))?;
                 Ok(path)
             }
             FormatScan::Text => {
@@ -186,7 +468,7 @@ impl AppState {
                     .trim_end_matches(".txt")
                     .trim_end_matches(".xml");
 
-                self.write_file(&format!("{}.json", base), &self.format_json())?;
+                self.write_file(&format!("{}.json", base), &self.format_json(config.json_envelope))?;
                 self.write_file(&format!("{}.txt", base), &self.format_text(config.show_closed_ports))?;
                 self.write_file(&format!("{}.xml", base), &self.format_nmap_xml())?;
 
@@ -211,8 +493,14 @@ impl AppState {
 
     fn format_text(&self, show_closed: bool) -> String {
         let mut output = String::new();
+        if let Some(ports) = &self.excluded_ports {
+            output.push_str(&format!("Excluded ports: {}\n\n", ports));
+        }
+        if let Some(shard) = &self.shard {
+            output.push_str(&format!("Shard: {}\n\n", shard));
+        }
         for (host, ports) in &self.results {
-            output.push_str(&format!("Host: {}\n", host));
+            output.push_str(&format!("Host: {}\n", self.host_label(host)));
             for port in ports {
                 match port.state {
                     PortState::Open => {
@@ -231,16 +519,71 @@ impl AppState {
             }
             output.push('\n');
         }
+        for host in &self.down_hosts {
+            output.push_str(&format!("Host: {}  down (skipped: no replies)\n", self.host_label(host)));
+        }
         output
     }
 
-    fn format_json(&self) -> String {
-        serde_json::to_string_pretty(&self.results).unwrap_or_else(|e| {
+    /// The results by host, as they always were, or wrapped in an object that
+    /// also carries skipped hosts, hostnames and the other metadata whenever
+    /// there is any, or always with `envelope`.
+    fn format_json(&self, envelope: bool) -> String {
+        #[derive(Serialize)]
+        struct JsonReport<'a> {
+            hosts: &'a HashMap<String, Vec<Port>>,
+            skipped_hosts: &'a BTreeSet<String>,
+            hostnames: &'a BTreeMap<String, BTreeSet<String>>,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            ptr_names: &'a BTreeMap<String, BTreeSet<String>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            excluded_ports: &'a Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            shard: &'a Option<ShardReport>,
+        }
+
+        let report = JsonReport {
+            hosts: &self.results,
+            skipped_hosts: &self.down_hosts,
+            hostnames: &self.hostnames,
+            ptr_names: &self.ptr_names,
+            excluded_ports: &self.excluded_ports,
+            shard: &self.shard,
+        };
+        let json = if envelope || self.has_metadata() {
+            serde_json::to_string_pretty(&report)
+        } else {
+            serde_json::to_string_pretty(&self.results)
+        };
+        json.unwrap_or_else(|e| {
             error!("Failed to serialize results to JSON: {}", e);
             "{}".to_string()
         })
     }
 
+    /// Whether the report has more to say than the results by host.
+    fn has_metadata(&self) -> bool {
+        !self.down_hosts.is_empty()
+            || !self.hostnames.is_empty()
+            || !self.ptr_names.is_empty()
+            || self.excluded_ports.is_some()
+            || self.shard.is_some()
+    }
+
+    fn xml_hostnames(&self, host: &str) -> String {
+        let mut xml = String::new();
+        for name in self.hostnames(host) {
+            xml.push_str(&format!("      <hostname name=\"{}\" type=\"user\"/>\n", xml_escape(name)));
+        }
+        for name in self.ptr_names(host) {
+            xml.push_str(&format!("      <hostname name=\"{}\" type=\"PTR\"/>\n", xml_escape(name)));
+        }
+        if xml.is_empty() {
+            return xml;
+        }
+        format!("    <hostnames>\n{}    </hostnames>\n", xml)
+    }
+
     fn format_nmap_xml(&self) -> String {
         let version = env!("CARGO_PKG_VERSION");
         let start_ts = self.start_time
@@ -261,6 +604,13 @@ impl AppState {
             start_ts,
             version,
         ));
+        if let Some(ports) = &self.excluded_ports {
+            // Comments can't hold "--"; port lists never do.
+            xml.push_str(&format!("<!-- Excluded ports: {} -->\n", ports));
+        }
+        if let Some(shard) = &self.shard {
+            xml.push_str(&format!("<!-- Shard: {} -->\n", shard));
+        }
 
         let mut hosts_up = 0u64;
         for (host, ports) in &self.results {
@@ -275,6 +625,7 @@ impl AppState {
                 xml_escape(host),
                 addrtype,
             ));
+            xml.push_str(&self.xml_hostnames(host));
             xml.push_str("    <ports>\n");
 
             for port in ports {
@@ -301,15 +652,29 @@ impl AppState {
             xml.push_str("  </host>\n");
         }
 
+        for host in &self.down_hosts {
+            let addrtype = if host.contains(':') { "ipv6" } else { "ipv4" };
+            xml.push_str("  <host>\n");
+            xml.push_str("    <status state=\"down\" reason=\"no-response\"/>\n");
+            xml.push_str(&format!(
+                "    <address addr=\"{}\" addrtype=\"{}\"/>\n",
+                xml_escape(host),
+                addrtype,
+            ));
+            xml.push_str(&self.xml_hostnames(host));
+            xml.push_str("  </host>\n");
+        }
+
         xml.push_str("  <runstats>\n");
         xml.push_str(&format!(
             "    <finished time=\"{}\" elapsed=\"{}\"/>\n",
             now_ts, elapsed,
         ));
         xml.push_str(&format!(
-            "    <hosts up=\"{}\" total=\"{}\"/>\n",
+            "    <hosts up=\"{}\" down=\"{}\" total=\"{}\"/>\n",
             hosts_up,
-            self.results.len(),
+            self.down_hosts.len(),
+            self.results.len() + self.down_hosts.len(),
         ));
         xml.push_str("  </runstats>\n");
         xml.push_str("</nmaprun>\n");
@@ -318,6 +683,27 @@ impl AppState {
     }
 }
 
+/// The command line as shown in reports, with proxy passwords blanked out.
+/// Options set through `CARESCANNER_*` variables never appear here.
+fn report_args(args: impl Iterator<Item = String>) -> String {
+    let mut hide_next = false;
+    args.map(|arg| {
+            if std::mem::take(&mut hide_next) {
+                return "***".to_string();
+            }
+            match arg.strip_prefix("--proxy-password") {
+                Some("") => {
+                    hide_next = true;
+                    arg
+                }
+                Some(value) if value.starts_with('=') => "--proxy-password=***".to_string(),
+                _ => arg,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn xml_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -326,6 +712,12 @@ fn xml_escape(s: &str) -> String {
         .replace('\'', "&apos;")
 }
 
+impl Default for AppStateManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppStateManager {
     pub fn new() -> Self {
         Self::with_state(AppState::new())
@@ -355,6 +747,25 @@ impl AppStateManager {
         self.results_sender.clone()
     }
 
+    /// Record a result right away, for callers that must see it in the state
+    /// before they go on (the channel may lag behind a burst of results).
+    pub async fn add_result(&self, target: Target, result: ScanResult, protocol: String) {
+        self.app_state.lock().await.add_result(target, result, protocol);
+    }
+
+    /// Record a host that was given up on (rare, so it bypasses the results channel).
+    pub async fn mark_host_down(&self, host: String) {
+        self.app_state.lock().await.mark_host_down(host);
+    }
+
+    /// Store reverse lookup results (after the scan, so outside the results channel).
+    pub async fn add_ptr_names(&self, names: HashMap<std::net::IpAddr, Vec<String>>) {
+        let mut state = self.app_state.lock().await;
+        for (ip, names) in names {
+            state.add_ptr_names(ip.to_string(), names);
+        }
+    }
+
     pub async fn get_current_state(&self) -> AppState {
         self.app_state.lock().await.clone()
     }
@@ -364,3 +775,127 @@ impl AppStateManager {
         tokio::runtime::Handle::current().block_on(self.get_current_state())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watermark_advances_over_out_of_order_completions() {
+        let stats = ScanStats::new(Some(7));
+        stats.start_phase(0, None, 10, 0);
+        stats.complete_target(12);
+        stats.complete_target(11);
+        assert_eq!(stats.resume_point().position, 10);
+        stats.complete_target(10);
+        assert_eq!(stats.resume_point(), ResumePoint { phase: 0, position: 13, seed: Some(7), ..Default::default() });
+    }
+
+    #[test]
+    fn progress_is_per_phase() {
+        let stats = ScanStats::default();
+        stats.start_phase(0, None, 0, 1000);
+        stats.probes_done.store(1000, Ordering::Relaxed);
+        assert_eq!(stats.phase_progress(), (0, 1000, 1000));
+        stats.start_phase(1, Some(vec!["10.0.0.5".to_string()]), 0, 40);
+        stats.probes_done.fetch_add(10, Ordering::Relaxed);
+        assert_eq!(stats.phase_progress(), (1, 10, 40));
+    }
+
+    #[test]
+    fn resume_file_round_trip_and_legacy_format() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("carescanner-test-{}.resume", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let stats = ScanStats::new(Some(99));
+        stats.start_phase(1, Some(vec!["10.0.0.5".to_string(), "10.0.0.6".to_string()]), 42, 0);
+        stats.host_responded("10.0.0.6");
+        stats.save_resume_file(path).unwrap();
+        assert_eq!(
+            ScanStats::load_resume_file(path).unwrap(),
+            ResumeFile::Position(ResumePoint {
+                phase: 1,
+                position: 42,
+                seed: Some(99),
+                hosts: Some(vec!["10.0.0.5".to_string(), "10.0.0.6".to_string()]),
+                responded: vec!["10.0.0.6".to_string()],
+            }),
+        );
+
+        std::fs::write(path, "10.0.0.1\n10.0.0.2\n").unwrap();
+        assert_eq!(
+            ScanStats::load_resume_file(path).unwrap(),
+            ResumeFile::Hosts(vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]),
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_include_hostnames_once_per_port() {
+        let mut state = AppState::new();
+        let target = Target { ip: "10.0.0.1".to_string(), port: 80, hostname: Some("a.example".to_string()), aliases: vec!["b.example".to_string()] };
+        // Also given inside a range: probed twice.
+        state.add_result(target.clone(), ScanResult::open(None), "tcp".to_string());
+        state.add_result(target, ScanResult::open(None), "tcp".to_string());
+        assert_eq!(state.get_results()["10.0.0.1"].len(), 1);
+        assert!(state.format_text(false).starts_with("Host: 10.0.0.1 (a.example, b.example)\n"));
+        assert!(state.format_json(false).contains("\"b.example\""));
+        assert!(state.format_nmap_xml().contains("<hostname name=\"a.example\" type=\"user\"/>"));
+    }
+
+    #[test]
+    fn ptr_names_follow_given_names() {
+        let mut state = AppState::new();
+        let target = Target { ip: "10.0.0.1".to_string(), port: 22, hostname: Some("a.example".to_string()), aliases: Vec::new() };
+        state.add_result(target, ScanResult::open(None), "tcp".to_string());
+        state.add_ptr_names("10.0.0.1".to_string(), vec!["a.example".to_string(), "host1.lan".to_string()]);
+        assert_eq!(state.all_names("10.0.0.1"), ["a.example", "host1.lan"]);
+        assert!(state.format_text(false).starts_with("Host: 10.0.0.1 (a.example, host1.lan)\n"));
+        assert!(state.format_json(false).contains("\"ptr_names\""));
+        assert!(state.format_nmap_xml().contains("<hostname name=\"host1.lan\" type=\"PTR\"/>"));
+    }
+
+    #[test]
+    fn json_keeps_its_shape_without_metadata() {
+        let mut state = AppState::new();
+        let target = Target { ip: "10.0.0.1".to_string(), port: 22, hostname: None, aliases: Vec::new() };
+        state.add_result(target, ScanResult::open(None), "tcp".to_string());
+        let plain: serde_json::Value = serde_json::from_str(&state.format_json(false)).unwrap();
+        assert_eq!(plain["10.0.0.1"][0]["number"], 22);
+        assert!(plain.get("hosts").is_none());
+        let wrapped: serde_json::Value = serde_json::from_str(&state.format_json(true)).unwrap();
+        assert_eq!(wrapped["hosts"], plain);
+
+        // Metadata is never dropped: it brings the envelope along.
+        state.mark_host_down("10.0.0.2".to_string());
+        let wrapped: serde_json::Value = serde_json::from_str(&state.format_json(false)).unwrap();
+        assert_eq!(wrapped["hosts"], plain);
+        assert_eq!(wrapped["skipped_hosts"][0], "10.0.0.2");
+    }
+
+    #[test]
+    fn excluded_ports_head_every_report() {
+        let mut state = AppState::new();
+        state.set_excluded_ports("9100,T:22".to_string());
+        assert!(state.format_text(false).starts_with("Excluded ports: 9100,T:22\n"));
+        assert!(state.format_json(false).contains("\"excluded_ports\": \"9100,T:22\""));
+        assert!(state.format_nmap_xml().contains("<!-- Excluded ports: 9100,T:22 -->"));
+    }
+
+    #[test]
+    fn shard_heads_every_report() {
+        let mut state = AppState::new();
+        state.set_shard(ShardReport { worker_id: 1, total_workers: 4, seed: 42, plan_targets: 1000, targets: 250, scanned: 180 });
+        assert!(state.format_text(false).starts_with("Shard: worker 1 of 4, seed 42, 180 of 250 targets scanned (1000 over all workers)\n"));
+        assert!(state.format_json(false).contains("\"shard\": {\n    \"worker_id\": 1,"));
+        assert!(state.format_nmap_xml().contains("<!-- Shard: worker 1 of 4, seed 42,"));
+    }
+
+    #[test]
+    fn proxy_passwords_stay_out_of_reports() {
+        let args = ["carescanner", "--proxy-username", "scan", "--proxy-password", "hunter2", "--proxy-password=hunter3", "-t", "10.0.0.1"];
+        assert_eq!(report_args(args.iter().map(|a| a.to_string())),
+            "carescanner --proxy-username scan --proxy-password *** --proxy-password=*** -t 10.0.0.1");
+    }
+}
@@ -3,13 +3,25 @@ mod port_parsing;
 mod proxy;
 pub mod top_ports;
 pub mod config_file;
+mod subnet_limit;
+mod phases;
+mod exclusion;
+mod services;
+mod layers;
+mod scope;
 
 
-pub use target_parsing::TargetList;
-pub use port_parsing::PortList;
+pub use target_parsing::{OwnPorts, TargetList};
+pub use port_parsing::{PortList, PortSpec, Protocol};
 use clap::{Parser, builder::ArgPredicate};
+use crate::distributed::CoordinatorToken;
 use crate::modes::ScanTypeName;
-pub use proxy::{ProxyStrategy, ProxyList};
+pub use proxy::{ProxyAuth, ProxyList, ProxyPassword, ProxyStrategy};
+pub use subnet_limit::SubnetLimit;
+pub use phases::ScanPhase;
+pub use exclusion::ExclusionSet;
+pub use scope::Scope;
+use top_ports::PortFrequencies;
 
 #[derive(Debug, Clone, clap::ValueEnum)]
 pub enum FormatScan { 
@@ -29,9 +41,9 @@ pub enum LoggingLevel {
     Error,
 }
 
-impl Into<log::LevelFilter> for LoggingLevel {
-    fn into(self) -> log::LevelFilter {
-        match self {
+impl From<LoggingLevel> for log::LevelFilter {
+    fn from(level: LoggingLevel) -> Self {
+        match level {
             LoggingLevel::Off => log::LevelFilter::Off,
             LoggingLevel::Trace => log::LevelFilter::Trace,
             LoggingLevel::Debug => log::LevelFilter::Debug,
@@ -46,6 +58,10 @@ impl Into<log::LevelFilter> for LoggingLevel {
 pub enum ScanStrategy {
     HostFirst,
     RoundRobin,
+    /// Seeded pseudo-random order over every host and port
+    Random,
+    /// Most common ports first (nmap frequency) across all hosts, then the rest
+    PortPriority,
 }
 
 #[derive(Debug, Clone)]
@@ -60,30 +76,69 @@ pub struct Config {
     #[arg(short, long, default_value = "info", help_heading = "Logging options", help = "Logging level")]
     pub logging_level: LoggingLevel,
 
+    // Config options
+    #[arg(long, help_heading = "Config options", help = "Use the options of [profile.NAME] in carescanner.toml for everything not given on the command line", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    #[arg(long, help_heading = "Config options", help = "Print the effective configuration (as config file TOML, with where each value came from) and exit")]
+    pub show_config: bool,
+
+    /// The config file the options were read from, if any.
+    #[arg(skip)]
+    pub config_file: Option<String>,
+
     // Scan options
-    #[arg(short, long, help_heading = "Scan options", alias = "target", help = "Targets to scan (e.g., 192.168.1.0/24, scanme.nmap.org), comma-separated, or from a file (e.g., file:targets.txt)", value_name = "TARGETS_LIST", value_parser = target_parsing::parse_target_input)]
+    #[arg(short, long, help_heading = "Scan options", alias = "target", help = "Targets to scan (e.g., 192.168.1.0/24, 10.0.1-3.1-254, 192.168.1.*, 2001:db8::1-ff, scanme.nmap.org), comma-separated, or from a file (e.g., file:targets.txt)", value_name = "TARGETS_LIST", value_parser = target_parsing::parse_target_input)]
     pub targets: TargetList,
 
-    #[arg(short, long, help_heading = "Scan options", alias = "port", help = "Ports to scan (e.g., 80,443, 22-25, file:ports.txt), comma-separated, or from a file", value_name = "PORTS_LIST", default_value = "1-65535")]
-    pub ports: PortList,
+    #[arg(short, long, help_heading = "Scan options", alias = "port", help = "Ports to scan (e.g., 80,443, 22-25, ssh,https, T:22,80,U:53,161, - for all), comma-separated, or from a file (e.g., file:ports.txt)", value_name = "PORTS_LIST", default_value = "1-65535", allow_hyphen_values = true)]
+    pub ports: PortSpec,
 
-    #[arg(long, help_heading = "Scan options", help = "Scan only the top N most common ports (from nmap)", value_name = "N")]
+    #[arg(long, help_heading = "Scan options", help = "Scan only the top N most common ports of each protocol (from nmap: up to 1000 TCP, 100 UDP built in)", value_name = "N")]
     pub top_ports: Option<usize>,
 
+    #[arg(long, help_heading = "Scan options", help = "nmap-services file to rank ports by (for --top-ports, top:N phases, --ping and the port-priority strategy), e.g. /usr/share/nmap/nmap-services", value_name = "FILE")]
+    pub services_file: Option<String>,
+
+    /// TCP and UDP port rankings: built in, or loaded from `--services-file`.
+    #[arg(skip)]
+    pub port_frequencies: PortFrequencies,
+
+    #[arg(long, help_heading = "Scan options", help = "Ports never to scan, same syntax as --ports (e.g. 9100, T:22, U:53,161, file:ports.txt); applied after --top-ports, to every phase and per-host list", value_name = "PORTS_LIST", allow_hyphen_values = true)]
+    pub exclude_ports: Option<PortSpec>,
+
+    #[arg(long, help_heading = "Scan options", help = "Phased scan (e.g. top:100,full): each phase only scans hosts that answered in the previous one. Phases: top:N, full, ports (the -p list)", value_name = "PHASES", value_delimiter = ',')]
+    pub phases: Vec<ScanPhase>,
+
     #[arg(long, help_heading = "Scan options", help = "Shuffle ports", value_name = "SHUFFLE_PORTS", default_value = "false")]
     pub shuffle_ports: bool,
 
     #[arg(long, help_heading = "Scan options", help = "Ping hosts before scanning (skip dead hosts)")]
     pub ping: bool,
 
+    #[arg(long, help_heading = "Scan options", help = "Mark a host down after N consecutive TCP timeouts with no reply, and skip its remaining ports (off by default: with interleaved orderings a host's timeouts come in between other hosts' replies; e.g. 100 suits host-first)", value_name = "N")]
+    pub host_abort_after: Option<u32>,
+
     #[arg(long, help_heading = "Scan options", help = "socks5 proxies to use for the scan. Order of connecting it will be as in argument. Separate by comma (e.g., socks5://localhost:9050, socks5://192.168.1.1:9050)", value_name = "PROXY", value_parser = proxy::parse_proxy_input)]
     pub proxies: Option<ProxyList>,
 
     #[arg(long, help_heading = "Scan options", help = "Proxy strategy", value_name = "PROXY_STRATEGY", default_value_if("proxies", ArgPredicate::IsPresent, "sequential"))]
     pub proxy_strategy: Option<ProxyStrategy>,
 
-    #[arg(long, help_heading = "Scan options", help = "Exclude hosts (e.g., 192.168.1.1,10.0.0.0/24), comma-separated or file:excludes.txt", value_name = "EXCLUDE_LIST", value_parser = target_parsing::parse_target_input)]
-    pub exclude: Option<TargetList>,
+    #[arg(long, help_heading = "Scan options", help = "Username for SOCKS5 proxies that require authentication", value_name = "USER")]
+    pub proxy_username: Option<String>,
+
+    #[arg(long, help_heading = "Scan options", help = "Password for SOCKS5 proxies; prefer the CARESCANNER_PROXY_PASSWORD variable so it stays off the command line", value_name = "PASSWORD", hide_env_values = true)]
+    pub proxy_password: Option<ProxyPassword>,
+
+    #[arg(long, help_heading = "Scan options", help = "Exclude hosts (e.g., 192.168.1.1,10.0.0.0/24,host.example), comma-separated or file:excludes.txt (# comments allowed); checked again for every address before probing", value_name = "EXCLUDE_LIST", value_parser = exclusion::parse_exclusions)]
+    pub exclude: Option<ExclusionSet>,
+
+    #[arg(long, help_heading = "Scan options", help = "File of the addresses, CIDRs and hostnames the scan is authorised to touch (# comments allowed); every other target is refused and logged, and every address is checked again before probing", value_name = "FILE", value_parser = scope::parse_scope_file)]
+    pub scope_file: Option<Scope>,
+
+    #[arg(long, help_heading = "Scan options", help = "Refuse to run without --scope-file (set it in the config file or a profile for client engagements)")]
+    pub require_scope: bool,
 
     #[arg(short='x', long, help_heading = "Scan options", help = "Don't start a new scan, resume from a previous scan", value_name = "FILE_RESUME_FROM")]
     pub resume_from: Option<String>,
@@ -91,29 +146,57 @@ pub struct Config {
     #[arg(long, help_heading = "Scan options", help = "Grab service banners from open ports (adds latency)")]
     pub banner: bool,
 
-    #[arg(long, help_heading = "Scan options", help = "Total number of workers for distributed scanning", value_name = "N")]
+    #[arg(long, help_heading = "Scan options", help = "Resolve and expand the targets, print per-protocol totals and a time estimate, and exit without sending any probe (--ping is skipped)")]
+    pub dry_run: bool,
+
+    #[arg(long, help_heading = "Scan options", help = "With --dry-run, write every planned target as CSV (phase,address,port,protocol,hostname) to FILE, or - for stdout", value_name = "FILE", requires = "dry_run")]
+    pub plan_output: Option<String>,
+
+    #[arg(long, help_heading = "Scan options", help = "Total number of workers for distributed scanning: each scans an even share of every host and port", value_name = "N")]
     pub total_workers: Option<usize>,
 
     #[arg(long, help_heading = "Scan options", help = "This worker's ID (0-based) for distributed scanning", value_name = "ID")]
     pub worker_id: Option<usize>,
 
+    #[arg(long, help_heading = "Scan options", help = "Hand the scan out to `carescanner worker --coordinator ADDR` processes connecting to LISTEN_ADDR (e.g., 0.0.0.0:7700) instead of scanning from here", value_name = "LISTEN_ADDR")]
+    pub coordinator: Option<String>,
+
+    #[arg(long, help_heading = "Scan options", help = "With --coordinator, only let in workers giving this token (their --coordinator-token); prefer the CARESCANNER_COORDINATOR_TOKEN variable so it stays off the command line", value_name = "TOKEN", hide_env_values = true)]
+    pub coordinator_token: Option<CoordinatorToken>,
+
+    #[arg(long, help_heading = "Scan options", help = "With --coordinator, targets handed to a worker at a time", value_name = "N", default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    pub chunk_size: u64,
+
+    /// Options sent to the workers of a `--coordinator`: how to probe, not what.
+    #[arg(skip)]
+    pub worker_args: Vec<String>,
+
     #[arg(long, help_heading = "Scan options", help = "Scan strategy", value_name = "SCAN_STRATEGY", default_value = "round-robin")]
     pub scan_strategy: ScanStrategy,
 
+    #[arg(long, help_heading = "Scan options", help = "Seed for the random strategy, --shuffle-ports and the split between workers (printed at start; reuse it to repeat a scan's order, and give every worker the same one)", value_name = "SEED")]
+    pub seed: Option<u64>,
+
     #[arg(short, long, help_heading = "Scan options", help = "Scan options", default_value = "tcp", value_delimiter = ',')]
     pub scan_type: Vec<ScanTypeName>,
 
     // Speed options
-    #[arg(short, long, help_heading = "Speed options", help = "Overall packet rate limit", conflicts_with_all = ["ratelimit_per_host", "maximum_scan_time"])]
+    #[arg(short, long, help_heading = "Speed options", help = "Overall packet rate limit (also the ceiling for --maximum-scan-time speedups)")]
     pub ratelimit: Option<u64>,
 
-    #[arg(long, help_heading = "Speed options", help = "Packet rate limit per host", conflicts_with_all = ["ratelimit", "maximum_scan_time"])]
+    #[arg(long, help_heading = "Speed options", help = "Packet rate limit per target host, enforced for every host individually")]
     pub ratelimit_per_host: Option<u64>,
 
-    #[arg(short='M', long, help_heading = "Speed options", help = "Maximum scan time", conflicts_with_all = ["ratelimit", "ratelimit_per_host"])]
-    pub maximum_scan_time: Option<String>,
+    #[arg(long, help_heading = "Speed options", help = "Maximum probes in flight against a single host", value_name = "N")]
+    pub max_concurrent_per_host: Option<usize>,
+
+    #[arg(long, help_heading = "Speed options", help = "Rate/concurrency limit per subnet of a prefix length (e.g. /24:200pps, /24:200pps:16c, v6/64:100pps), comma-separated or repeated", value_name = "PREFIX:LIMITS", value_delimiter = ',')]
+    pub subnet_limit: Vec<SubnetLimit>,
 
-    #[arg(long, help_heading = "Speed options", help = "Adaptive rate limiting: auto-adjusts speed based on timeout ratio")]
+    #[arg(short='M', long, help_heading = "Speed options", help = "Maximum scan time (e.g. 5min): paces the scan to finish in time and stops at the deadline, saving results and a resume file", value_parser = parse_duration::parse)]
+    pub maximum_scan_time: Option<std::time::Duration>,
+
+    #[arg(long, help_heading = "Speed options", help = "Adaptive rate limiting: AIMD congestion control driven by probe timeouts and RTT")]
     pub adaptive: bool,
 
     #[arg(long, help_heading = "Speed options", help = "Maximum ports scanning at a time", default_value = "1000")]
@@ -122,6 +205,31 @@ pub struct Config {
     #[arg(long, help_heading = "Speed options", help = "Timeout for the scan", default_value = "3")]
     pub timeout: u64,
 
+    // DNS options
+    #[arg(long, help_heading = "DNS options", help = "DNS servers for resolving target hostnames instead of the system ones (e.g. 127.0.0.1:5353,9.9.9.9), comma-separated", value_name = "IP[:PORT]", value_delimiter = ',', value_parser = crate::dns::parse_resolver)]
+    pub resolvers: Vec<std::net::SocketAddr>,
+
+    #[arg(short = '4', long, help_heading = "DNS options", help = "Resolve hostnames to IPv4 addresses only (A records)", conflicts_with = "ipv6_only")]
+    pub ipv4_only: bool,
+
+    #[arg(short = '6', long, help_heading = "DNS options", help = "Resolve hostnames to IPv6 addresses only (AAAA records)")]
+    pub ipv6_only: bool,
+
+    #[arg(long, help_heading = "DNS options", help = "Seconds to wait for a DNS answer", value_name = "SECONDS", default_value = "5")]
+    pub dns_timeout: u64,
+
+    #[arg(long, help_heading = "DNS options", help = "DNS retries per name before it is reported as unresolvable", value_name = "N", default_value = "2")]
+    pub dns_retries: usize,
+
+    #[arg(long, help_heading = "DNS options", help = "Hostnames resolved at a time (also used for reverse lookups)", value_name = "N", default_value = "100")]
+    pub dns_concurrency: usize,
+
+    #[arg(short = 'R', long, help_heading = "DNS options", help = "Look up PTR names for every host with results once the scan is done")]
+    pub reverse_dns: bool,
+
+    #[arg(long, help_heading = "DNS options", help = "Reverse lookups started per second", value_name = "N", default_value = "50")]
+    pub reverse_dns_rate: u32,
+
     // TUI options
     #[arg(short='q', long, help_heading = "TUI options", help = "Quiet mode: no banner, no progress bar, no live output. Only final results.")]
     pub quiet: bool,
@@ -142,6 +250,9 @@ pub struct Config {
     #[arg(short, long, help_heading = "Output options", default_value = "text")]
     pub format: FormatScan,
 
+    #[arg(long, help_heading = "Output options", help = "JSON: always wrap the results as {hosts, skipped_hosts, hostnames, ...}, as is done anyway when the scan has such metadata")]
+    pub json_envelope: bool,
+
     #[arg(short='c', long, help_heading = "Output options", help = "Show closed ports")]
     pub show_closed_ports: bool,
 
@@ -160,4 +271,11 @@ pub struct Config {
 
     #[arg(last = true, help_heading = "Nmap options", help = "Arguments passed to nmap (separated by --). Implies --nmap. Default: -sV -sC")]
     pub nmap_args: Vec<String>,
-}
\ No newline at end of file
+}
+
+impl Config {
+    /// Credentials for the SOCKS5 proxies, if a username is configured.
+    pub fn proxy_auth(&self) -> Option<ProxyAuth> {
+        ProxyAuth::new(self.proxy_username.as_ref(), self.proxy_password.as_ref())
+    }
+}
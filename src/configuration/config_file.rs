@@ -1,50 +1,131 @@
+use std::collections::HashMap;
 use serde::Deserialize;
-use log::info;
 
 const CONFIG_PATHS: &[&str] = &[
     "carescanner.toml",
     "~/.config/carescanner/config.toml",
 ];
 
+/// A config file: options for every scan at the top level, and named profiles.
+/// Keys are `Config` option names (`max_concurrent_ports` or `max-concurrent-ports`)
+/// with the values they take on the command line.
 #[derive(Debug, Deserialize, Default)]
 pub struct FileConfig {
-    pub ratelimit: Option<u64>,
-    pub timeout: Option<u64>,
-    pub max_concurrent_ports: Option<u64>,
-    pub format: Option<String>,
-    pub output: Option<String>,
-    pub banner: Option<bool>,
-    pub adaptive: Option<bool>,
-    pub shuffle_ports: Option<bool>,
-    pub ping: Option<bool>,
-    pub nmap_path: Option<String>,
-    pub nmap_args: Option<Vec<String>>,
+    /// `[profile.<name>]` sections: options as at the top level, plus
+    /// `inherits = "<other profile>"`.
+    #[serde(default)]
+    pub profile: HashMap<String, toml::Table>,
+    #[serde(flatten)]
+    options: toml::Table,
 }
 
-fn expand_tilde(path: &str) -> String {
-    if let Some(rest) = path.strip_prefix("~/") {
-        if let Ok(home) = std::env::var("HOME") {
-            return format!("{}/{}", home, rest);
+/// Profile key naming the profile it builds on.
+const INHERITS: &str = "inherits";
+
+impl FileConfig {
+    /// The top-level options, keys normalised to field names.
+    pub fn options(&self) -> toml::Table {
+        self.options.iter().map(|(key, value)| (key.replace('-', "_"), value.clone())).collect()
+    }
+
+    /// Options of profile `name` on top of those of the profiles it inherits
+    /// from, with keys normalised to field names (`scan-type` -> `scan_type`).
+    pub fn profile(&self, name: &str) -> Result<toml::Table, String> {
+        let mut chain: Vec<&str> = Vec::new();
+        let mut current = name;
+        loop {
+            if chain.contains(&current) {
+                return Err(format!("Profile '{}' inherits from itself: {} -> {}", name, chain.join(" -> "), current));
+            }
+            let table = match (self.profile.get(current), chain.last()) {
+                (Some(table), _) => table,
+                (None, Some(child)) => return Err(format!("Profile '{}' inherits from unknown profile '{}'", child, current)),
+                (None, None) => {
+                    let mut known: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+                    known.sort_unstable();
+                    return Err(format!("Unknown profile '{}' (defined: {})", name, if known.is_empty() { "none".to_string() } else { known.join(", ") }));
+                }
+            };
+            chain.push(current);
+            match table.get(INHERITS) {
+                None => break,
+                Some(toml::Value::String(parent)) => current = parent,
+                Some(other) => return Err(format!("Profile '{}': inherits must be a profile name, got {}", current, other)),
+            }
         }
+
+        let mut options = toml::Table::new();
+        for profile in chain.iter().rev() {
+            for (key, value) in &self.profile[*profile] {
+                if key != INHERITS {
+                    options.insert(key.replace('-', "_"), value.clone());
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn expand_tilde(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("~/")
+        && let Ok(home) = std::env::var("HOME")
+    {
+        return format!("{}/{}", home, rest);
     }
     path.to_string()
 }
 
-/// Try to load config from known paths. Returns defaults if none found.
-pub fn load_config_file() -> FileConfig {
+/// Try to load config from known paths, with the path it came from.
+pub fn load_config_file() -> Option<(String, FileConfig)> {
     for path in CONFIG_PATHS {
         let expanded = expand_tilde(path);
         if let Ok(content) = std::fs::read_to_string(&expanded) {
             match toml::from_str::<FileConfig>(&content) {
-                Ok(cfg) => {
-                    info!("Loaded config from {}", expanded);
-                    return cfg;
-                }
+                Ok(cfg) => return Some((expanded, cfg)),
                 Err(e) => {
                     eprintln!("Warning: failed to parse {}: {}", expanded, e);
                 }
             }
         }
     }
-    FileConfig::default()
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_inherit_and_override() {
+        let file: FileConfig = toml::from_str(r#"
+            timeout = 4
+            max-concurrent-ports = 200
+
+            [profile.stealth]
+            timeout = 10
+            scan-type = ["tcp"]
+            ratelimit = 50
+
+            [profile.client]
+            inherits = "stealth"
+            ratelimit = 20
+
+            [profile.loop-a]
+            inherits = "loop-b"
+
+            [profile.loop-b]
+            inherits = "loop-a"
+        "#).unwrap();
+
+        assert_eq!(file.options()["max_concurrent_ports"].as_integer(), Some(200));
+        assert!(!file.options().contains_key("profile"));
+
+        let client = file.profile("client").unwrap();
+        assert_eq!(client["ratelimit"].as_integer(), Some(20));
+        assert_eq!(client["timeout"].as_integer(), Some(10));
+        assert!(client.contains_key("scan_type") && !client.contains_key(INHERITS));
+
+        assert!(file.profile("loop-a").unwrap_err().contains("inherits from itself"));
+        assert!(file.profile("fast").unwrap_err().contains("defined: client, loop-a, loop-b, stealth"));
+    }
 }
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::configuration::target_parsing::{self, IpRange, TargetList, to_int};
+
+/// Excluded addresses as sorted, merged intervals per address family, so a /8
+/// is one entry and lookups are a binary search. Octet patterns that step over
+/// addresses (`*.*.*.1`) are kept as the ranges they parse to.
+#[derive(Debug, Clone, Default)]
+pub struct ExclusionSet {
+    v4: Vec<(u128, u128)>,
+    v6: Vec<(u128, u128)>,
+    /// Ranges stepping over addresses, by start.
+    stepping: Vec<IpRange>,
+    /// Hostnames still to resolve; their addresses are added by `add_resolved`.
+    unresolved: Vec<Arc<str>>,
+}
+
+impl std::str::FromStr for ExclusionSet {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_exclusions(s)
+    }
+}
+
+impl From<&TargetList> for ExclusionSet {
+    fn from(list: &TargetList) -> Self {
+        let mut v4 = Vec::new();
+        let mut v6 = Vec::new();
+        let mut stepping = Vec::new();
+        for range in list.ranges() {
+            if !range.is_contiguous() {
+                stepping.push(*range);
+                continue;
+            }
+            let intervals = if range.is_ipv6() { &mut v6 } else { &mut v4 };
+            intervals.push((range.start(), range.last()));
+        }
+        stepping.sort_unstable_by_key(|range| (range.is_ipv6(), range.start()));
+        Self { v4: merge(v4), v6: merge(v6), stepping, unresolved: list.unresolved() }
+    }
+}
+
+fn merge(mut intervals: Vec<(u128, u128)>) -> Vec<(u128, u128)> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(intervals.len());
+    for (first, last) in intervals {
+        match merged.last_mut() {
+            Some(prev) if first <= prev.1.saturating_add(1) => prev.1 = prev.1.max(last),
+            _ => merged.push((first, last)),
+        }
+    }
+    merged
+}
+
+impl ExclusionSet {
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty() && self.stepping.is_empty()
+    }
+
+    pub fn unresolved(&self) -> &[Arc<str>] {
+        &self.unresolved
+    }
+
+    /// Exclude the addresses the pending hostnames resolved to.
+    pub fn add_resolved(&mut self, resolved: &HashMap<Arc<str>, Vec<IpAddr>>) {
+        for ip in self.unresolved.iter().filter_map(|name| resolved.get(name)).flatten() {
+            let (ipv6, value) = to_int(*ip);
+            if ipv6 { &mut self.v6 } else { &mut self.v4 }.push((value, value));
+        }
+        self.v4 = merge(std::mem::take(&mut self.v4));
+        self.v6 = merge(std::mem::take(&mut self.v6));
+        self.unresolved.clear();
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (ipv6, value) = to_int(ip);
+        let intervals = if ipv6 { &self.v6 } else { &self.v4 };
+        let slot = intervals.partition_point(|&(first, _)| first <= value);
+        (slot > 0 && intervals[slot - 1].1 >= value) || self.stepping(ipv6, value, value).any(|range| range.contains(ip))
+    }
+
+    /// Same as `contains`, for addresses kept as strings; anything that is not an IP never matches.
+    pub fn contains_str(&self, ip: &str) -> bool {
+        ip.parse().is_ok_and(|ip| self.contains(ip))
+    }
+
+    /// The intervals of one family that overlap `first..=last`.
+    pub(super) fn overlapping(&self, ipv6: bool, first: u128, last: u128) -> &[(u128, u128)] {
+        let intervals = if ipv6 { &self.v6 } else { &self.v4 };
+        let start = intervals.partition_point(|&(_, end)| end < first);
+        let end = intervals.partition_point(|&(begin, _)| begin <= last);
+        &intervals[start..end.max(start)]
+    }
+
+    /// The stepping ranges of one family that span part of `first..=last`.
+    pub(super) fn stepping(&self, ipv6: bool, first: u128, last: u128) -> impl Iterator<Item = &IpRange> {
+        let end = self.stepping.partition_point(|range| (range.is_ipv6(), range.start()) <= (ipv6, last));
+        self.stepping[..end].iter().filter(move |range| range.is_ipv6() == ipv6 && range.last() >= first)
+    }
+}
+
+/// Parse `--exclude`: the same addresses, CIDRs and hostnames (resolved before the scan) as
+/// `--targets`, comma-separated or from `file:` with `#` comments.
+pub fn parse_exclusions(s: &str) -> Result<ExclusionSet, String> {
+    Ok(ExclusionSet::from(&target_parsing::parse_address_set(s)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_cidrs_without_expanding() {
+        let set = parse_exclusions("10.0.0.0/8,10.1.0.0/16,192.168.1.7,2001:db8::/32").unwrap();
+        assert_eq!(set.v4.len(), 2);
+        assert!(set.contains("10.200.3.4".parse().unwrap()));
+        assert!(set.contains_str("192.168.1.7"));
+        assert!(!set.contains_str("192.168.1.8"));
+        assert!(!set.contains_str("11.0.0.0"));
+        assert!(set.contains_str("2001:db8:ffff::1"));
+        assert!(!set.contains_str("2001:db9::1"));
+        assert!(!set.contains_str("example.com"));
+
+        let mut set = parse_exclusions("10.0.0.1,printer.lan").unwrap();
+        assert_eq!(set.unresolved(), [Arc::from("printer.lan")]);
+        set.add_resolved(&HashMap::from([(Arc::from("printer.lan"), vec!["10.0.0.2".parse().unwrap()])]));
+        assert_eq!(set.v4, [(0x0a00_0001, 0x0a00_0002)]);
+        assert!(set.unresolved().is_empty());
+    }
+
+    #[test]
+    fn stepping_patterns_stay_ranges() {
+        let set = parse_exclusions("*.*.*.1,10.0.0.0/8").unwrap();
+        assert_eq!((set.v4.len(), set.stepping.len()), (1, 1));
+        assert!(set.contains_str("192.168.7.1") && set.contains_str("10.1.2.3"));
+        assert!(!set.contains_str("192.168.7.2"));
+    }
+
+    #[test]
+    fn exclusion_file_accepts_comments() {
+        let path = std::env::temp_dir().join(format!("carescanner-exclude-{}.txt", std::process::id()));
+        std::fs::write(&path, "# lab networks\n10.0.0.0/24  # printers\n\n  # 10.0.1.0/24\n").unwrap();
+        let set = parse_exclusions(&format!("file:{}", path.display())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(set.contains_str("10.0.0.99"));
+        assert!(!set.contains_str("10.0.1.1"));
+    }
+}
@@ -0,0 +1,402 @@
+//! Building the effective `Config`: options given on the command line win,
+//! then `CARESCANNER_*` environment variables, the `--profile` chosen from the
+//! config file, the file's top-level options, and clap's defaults. Every layer
+//! goes through the same clap parser, so the file can set any option with the
+//! syntax it has on the command line.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+
+use super::Config;
+use super::config_file::{load_config_file, FileConfig};
+
+/// Where the effective value of an option came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    CommandLine,
+    Environment,
+    Profile,
+    File,
+    Default,
+}
+
+impl Source {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Source::CommandLine => "command line",
+            Source::Environment => "environment",
+            Source::Profile => "profile",
+            Source::File => "config file",
+            Source::Default => "default",
+        }
+    }
+}
+
+/// Options that select or print the configuration rather than being part of it.
+const META_OPTIONS: &[&str] = &["profile", "show_config", "help", "version"];
+
+/// Prefix of the environment variable of every option: `CARESCANNER_TIMEOUT`.
+const ENV_PREFIX: &str = "CARESCANNER_";
+
+/// Options whose values are never printed.
+const SECRET_OPTIONS: &[&str] = &["proxy_password", "coordinator_token"];
+
+/// Options a coordinator passes on to its workers: how targets are probed.
+/// What is scanned and what happens to the results stays with the coordinator,
+/// and secrets stay with each worker's own `CARESCANNER_*` variables. The rate
+/// is shared out by the coordinator itself; `--max-concurrent-ports` bounds
+/// each worker's own sockets.
+const WORKER_OPTIONS: &[&str] = &[
+    "scan_type", "timeout", "banner", "host_abort_after",
+    "proxies", "proxy_strategy", "proxy_username", "adaptive", "max_concurrent_ports",
+];
+
+/// The `Config` parser, reading every option (and `--profile`) from its
+/// `CARESCANNER_*` variable when the command line does not give it. The nmap
+/// arguments after `--` are left to the command line and the config file.
+fn config_command() -> Command {
+    Config::command().mut_args(|arg: Arg| {
+        let id = arg.get_id().as_str();
+        if arg.is_positional() || (META_OPTIONS.contains(&id) && id != "profile") {
+            return arg;
+        }
+        let name = format!("{}{}", ENV_PREFIX, id.to_ascii_uppercase());
+        arg.env(name)
+    })
+}
+
+impl Config {
+    /// Parse the command line and fill in the options it leaves out from the
+    /// selected profile and the config file. Exits like clap on errors, and
+    /// after printing the configuration with `--show-config`.
+    pub fn load() -> Self {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let (path, file) = load_config_file().unzip();
+        let result = layered_matches(&args, &file.unwrap_or_default()).and_then(|(matches, sources)| {
+            if matches.get_flag("show_config") {
+                print!("{}", effective_config(&matches, &sources, path.as_deref()));
+                std::process::exit(0);
+            }
+            Ok(Config { worker_args: worker_args(&matches), ..Config::from_arg_matches(&matches)? })
+        });
+        match result {
+            Ok(config) => Config { config_file: path, ..config },
+            Err(e) => e.format(&mut config_command()).exit(),
+        }
+    }
+
+    /// The configuration of a `carescanner worker`: the options its coordinator
+    /// sent, then the worker's own `CARESCANNER_*` variables. Targets come with
+    /// each chunk, the placeholder only satisfies the parser.
+    pub fn for_worker(args: &[String]) -> Result<Self, String> {
+        let args = ["carescanner", "--targets=127.0.0.1"].into_iter().map(String::from).chain(args.iter().cloned());
+        config_command().try_get_matches_from(args)
+            .and_then(|matches| Config::from_arg_matches(&matches))
+            .map_err(|e| format!("Invalid options from the coordinator: {}", e.kind()))
+    }
+}
+
+/// `WORKER_OPTIONS` that have a value other than the default, as command-line arguments.
+fn worker_args(matches: &ArgMatches) -> Vec<String> {
+    let command = config_command();
+    let mut args = Vec::new();
+    for arg in command.get_arguments().filter(|arg| WORKER_OPTIONS.contains(&arg.get_id().as_str())) {
+        let id = arg.get_id().as_str();
+        let long = arg.get_long().unwrap_or(id);
+        if matches!(matches.value_source(id), None | Some(ValueSource::DefaultValue)) {
+            continue;
+        }
+        if !arg.get_action().takes_values() {
+            if matches.get_flag(id) {
+                args.push(format!("--{}", long));
+            }
+            continue;
+        }
+        let raw: Vec<String> = matches.get_raw(id).into_iter().flatten().map(|v| v.to_string_lossy().into_owned()).collect();
+        if arg.get_value_delimiter().is_some() {
+            args.push(format!("--{}={}", long, raw.join(",")));
+        } else {
+            args.extend(raw.iter().map(|v| format!("--{}={}", long, v)));
+        }
+    }
+    args
+}
+
+/// Matches of the command line with the options of the profile and the config
+/// file added where neither the command line nor the environment set them, and
+/// the source of each option given by one of those layers.
+fn layered_matches(args: &[OsString], file: &FileConfig) -> Result<(ArgMatches, HashMap<String, Source>), clap::Error> {
+    let mut command = config_command();
+    // The file may provide required options such as the targets, so look at
+    // the command line alone without requiring anything.
+    let relaxed = config_command().mut_args(|arg| arg.required(false)).try_get_matches_from(args);
+    let cli = match relaxed {
+        Ok(cli) => cli,
+        // Help, version and invalid values: let the real parser report them.
+        Err(_) => return Ok((command.try_get_matches_from(args)?, HashMap::new())),
+    };
+
+    // Later layers win: the profile over the top-level options.
+    let mut layered: BTreeMap<String, (toml::Value, Source)> = BTreeMap::new();
+    layered.extend(file.options().into_iter().map(|(key, value)| (key, (value, Source::File))));
+    let profile = cli.get_one::<String>("profile");
+    if let Some(name) = profile {
+        let options = file.profile(name).map_err(|e| command.error(ErrorKind::InvalidValue, e))?;
+        layered.extend(options.into_iter().map(|(key, value)| (key, (value, Source::Profile))));
+    }
+
+    let mut sources = HashMap::new();
+    let mut options: Vec<String> = Vec::new();
+    let mut trailing: Vec<String> = Vec::new();
+    for (key, (value, source)) in &layered {
+        let invalid = |message: String| {
+            let layer = match (source, profile) {
+                (Source::Profile, Some(name)) => format!("Profile '{}'", name),
+                _ => "Config file".to_string(),
+            };
+            command.clone().error(ErrorKind::InvalidValue, format!("{}: {}", layer, message))
+        };
+        let Some(arg) = command.get_arguments().find(|arg| arg.get_id() == key.as_str()) else {
+            return Err(invalid(format!("unknown option '{}'", key)));
+        };
+        if META_OPTIONS.contains(&key.as_str()) {
+            return Err(invalid(format!("'{}' can only be given on the command line", key)));
+        }
+        if matches!(cli.value_source(key), Some(ValueSource::CommandLine | ValueSource::EnvVariable)) {
+            continue;
+        }
+        sources.insert(key.clone(), *source);
+
+        if !arg.get_action().takes_values() {
+            match value {
+                toml::Value::Boolean(true) => options.push(format!("--{}", arg.get_long().unwrap_or(key))),
+                toml::Value::Boolean(false) => {}
+                other => return Err(invalid(format!("'{}' expects true or false, got {}", key, other))),
+            }
+            continue;
+        }
+        let values = option_values(value).map_err(|e| invalid(format!("'{}' {}", key, e)))?;
+        if arg.is_positional() {
+            trailing = values;
+        } else if arg.get_value_delimiter().is_some() {
+            options.push(format!("--{}={}", arg.get_long().unwrap_or(key), values.join(",")));
+        } else {
+            options.extend(values.iter().map(|v| format!("--{}={}", arg.get_long().unwrap_or(key), v)));
+        }
+    }
+
+    let mut full: Vec<OsString> = args.iter().take(1).cloned().collect();
+    full.extend(options.into_iter().map(OsString::from));
+    full.extend(args.iter().skip(1).cloned());
+    if !trailing.is_empty() {
+        if !args.iter().skip(1).any(|arg| arg == "--") {
+            full.push("--".into());
+        }
+        full.extend(trailing.into_iter().map(OsString::from));
+    }
+    // Printing the configuration needs no targets, so check it without them.
+    if cli.get_flag("show_config") {
+        command = command.mut_args(|arg| arg.required(false));
+    }
+    Ok((command.try_get_matches_from(full)?, sources))
+}
+
+/// Command-line values of a config file entry: strings, numbers, or a list of them.
+fn option_values(value: &toml::Value) -> Result<Vec<String>, String> {
+    match value {
+        toml::Value::String(s) => Ok(vec![s.clone()]),
+        toml::Value::Integer(n) => Ok(vec![n.to_string()]),
+        toml::Value::Float(n) => Ok(vec![n.to_string()]),
+        toml::Value::Array(items) => items.iter()
+            .map(|item| match item {
+                toml::Value::Array(_) | toml::Value::Table(_) => Err(format!("cannot hold nested lists or tables, got {}", item)),
+                item => option_values(item).map(|mut v| v.remove(0)),
+            })
+            .collect(),
+        other => Err(format!("expects a string, number or list, got {}", other)),
+    }
+}
+
+/// Every option that has a value, as TOML a profile could hold, with where the
+/// value came from.
+fn effective_config(matches: &ArgMatches, sources: &HashMap<String, Source>, path: Option<&str>) -> String {
+    let mut out = String::from("# Effective configuration");
+    if let Some(path) = path {
+        out.push_str(&format!(" (config file: {})", path));
+    }
+    if let Some(profile) = matches.get_one::<String>("profile") {
+        out.push_str(&format!(" (profile: {})", profile));
+    }
+    out.push('\n');
+    for arg in config_command().get_arguments() {
+        let id = arg.get_id().as_str();
+        if META_OPTIONS.contains(&id) {
+            continue;
+        }
+        let source = match (sources.get(id), matches.value_source(id)) {
+            (Some(source), _) => *source,
+            (None, Some(ValueSource::DefaultValue)) => Source::Default,
+            (None, Some(ValueSource::EnvVariable)) => Source::Environment,
+            (None, Some(_)) => Source::CommandLine,
+            (None, None) => continue,
+        };
+        let raw: Vec<String> = matches.get_raw(id)
+            .map(|values| values.map(|v| v.to_string_lossy().into_owned()).collect())
+            .unwrap_or_default();
+        let value = if SECRET_OPTIONS.contains(&id) {
+            toml::Value::String("***".to_string())
+        } else if !arg.get_action().takes_values() {
+            toml::Value::Boolean(raw.first().is_some_and(|v| v == "true"))
+        } else if matches!(arg.get_action(), ArgAction::Append) {
+            toml::Value::Array(raw.into_iter().map(toml::Value::String).collect())
+        } else {
+            toml::Value::String(raw.join(","))
+        };
+        out.push_str(&format!("{} = {}  # {}\n", id, value, source.as_str()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(args: &[&str], file: &str) -> Result<(Config, HashMap<String, Source>), clap::Error> {
+        let args: Vec<OsString> = std::iter::once("carescanner").chain(args.iter().copied()).map(OsString::from).collect();
+        let file: FileConfig = toml::from_str(file).unwrap();
+        let (matches, sources) = layered_matches(&args, &file)?;
+        Ok((Config::from_arg_matches(&matches)?, sources))
+    }
+
+    const PROFILES: &str = r#"
+        [profile.internal-fast]
+        targets = "10.0.0.0/30"
+        max-concurrent-ports = 5000
+        scan_strategy = "host-first"
+        timeout = 1
+
+        [profile.tor]
+        inherits = "internal-fast"
+        proxies = "socks5://127.0.0.1:9050"
+        scan-type = ["socks5-tcp"]
+        banner = true
+        nmap_args = ["-sV"]
+    "#;
+
+    #[test]
+    fn profiles_fill_what_the_command_line_leaves_out() {
+        let (config, sources) = load(&["--profile", "tor", "--timeout", "7"], PROFILES).unwrap();
+        assert_eq!(config.targets.len(), 4);
+        assert_eq!((config.timeout, config.max_concurrent_ports), (7, 5000));
+        assert!(config.banner && config.proxies.is_some());
+        assert_eq!(config.nmap_args, ["-sV"]);
+        assert_eq!(sources.get("max_concurrent_ports"), Some(&Source::Profile));
+        assert_eq!(sources.get("timeout"), None);
+
+        let (config, _) = load(&["-t", "127.0.0.1", "--", "-A"], PROFILES).unwrap();
+        assert_eq!((config.timeout, config.nmap_args), (3, vec!["-A".to_string()]));
+    }
+
+    #[test]
+    fn command_line_then_profile_then_file() {
+        let file = r#"
+            targets = "10.0.0.1"
+            timeout = 5
+            ratelimit = 100
+            format = "json"
+
+            [profile.slow]
+            ratelimit = 50
+        "#;
+        // Passing the default value explicitly still beats the file.
+        let (config, sources) = load(&["--timeout", "3"], file).unwrap();
+        assert_eq!((config.timeout, config.ratelimit), (3, Some(100)));
+        assert!(matches!(config.format, crate::configuration::FormatScan::Json));
+        assert_eq!(sources.get("ratelimit"), Some(&Source::File));
+
+        let (config, sources) = load(&["--profile", "slow", "-f", "text"], file).unwrap();
+        assert_eq!((config.timeout, config.ratelimit), (5, Some(50)));
+        assert!(matches!(config.format, crate::configuration::FormatScan::Text));
+        assert_eq!(sources.get("ratelimit"), Some(&Source::Profile));
+        assert!(load(&[], "turbo = true\n").unwrap_err().to_string().contains("Config file: unknown option 'turbo'"));
+    }
+
+    #[test]
+    fn environment_sits_between_command_line_and_file() {
+        // SAFETY: std serialises environment access, and no other test depends
+        // on these two options.
+        unsafe {
+            std::env::set_var("CARESCANNER_DNS_RETRIES", "7");
+            std::env::set_var("CARESCANNER_PROXY_PASSWORD", "hunter2");
+        }
+        let file = "targets = \"10.0.0.1\"\ndns_retries = 4\nproxy_username = \"scan\"\n";
+        let (config, sources) = load(&[], file).unwrap();
+        assert_eq!(config.dns_retries, 7);
+        assert_eq!(config.proxy_auth().map(|auth| auth.password().to_string()), Some("hunter2".to_string()));
+        assert_eq!(sources.get("dns_retries"), None);
+        assert_eq!(load(&["--dns-retries", "9"], file).unwrap().0.dns_retries, 9);
+
+        let args: Vec<OsString> = ["carescanner"].map(OsString::from).to_vec();
+        let (matches, sources) = layered_matches(&args, &toml::from_str(file).unwrap()).unwrap();
+        let printed = effective_config(&matches, &sources, None);
+        assert!(printed.contains("dns_retries = \"7\"  # environment\n"));
+        assert!(printed.contains("proxy_password = \"***\"  # environment\n") && !printed.contains("hunter2"));
+        assert!(!format!("{:?}", config).contains("hunter2"));
+        unsafe {
+            std::env::remove_var("CARESCANNER_DNS_RETRIES");
+            std::env::remove_var("CARESCANNER_PROXY_PASSWORD");
+        }
+    }
+
+    #[test]
+    fn profile_mistakes_are_reported() {
+        let unknown = load(&["--profile", "bad"], "[profile.bad]\ntimeout = 1\nturbo = true\n").unwrap_err();
+        assert!(unknown.to_string().contains("unknown option 'turbo'"));
+        let flag = load(&["--profile", "bad"], "[profile.bad]\ntargets = \"10.0.0.1\"\nbanner = \"yes\"\n").unwrap_err();
+        assert!(flag.to_string().contains("expects true or false"));
+        assert!(load(&["--profile", "missing"], PROFILES).is_err());
+    }
+
+    #[test]
+    fn workers_get_how_to_probe_not_what() {
+        let args: Vec<OsString> = ["carescanner", "-t", "10.0.0.0/24", "-s", "tcp,udp", "--banner", "--timeout", "2", "-p", "80",
+            "--max-concurrent-ports", "800", "--proxy-password", "hunter2"]
+            .map(OsString::from).to_vec();
+        let (matches, _) = layered_matches(&args, &toml::from_str("ratelimit = 300\n").unwrap()).unwrap();
+        let forwarded = worker_args(&matches);
+        // The rate is the coordinator's to share out.
+        assert_eq!(forwarded, ["--banner", "--scan-type=tcp,udp", "--max-concurrent-ports=800", "--timeout=2"]);
+
+        let config = Config::for_worker(&forwarded).unwrap();
+        assert_eq!((config.timeout, config.ratelimit, config.max_concurrent_ports), (2, None, 800));
+        assert!(config.banner);
+        assert!(Config::for_worker(&["--timeout=soon".to_string()]).is_err());
+    }
+
+    #[test]
+    fn effective_config_is_profile_toml() {
+        let args: Vec<OsString> = ["carescanner", "--profile", "tor", "-s", "tcp"].map(OsString::from).to_vec();
+        let (matches, sources) = layered_matches(&args, &toml::from_str(PROFILES).unwrap()).unwrap();
+        let printed = effective_config(&matches, &sources, None);
+        assert!(printed.starts_with("# Effective configuration (profile: tor)\n"));
+        assert!(printed.contains("scan_type = [\"tcp\"]  # command line\n"));
+        assert!(printed.contains("banner = true  # profile\n"));
+        assert!(printed.contains("dns_timeout = \"5\"  # default\n"));
+
+        let reparsed: toml::Table = toml::from_str(&printed).unwrap();
+        assert_eq!(reparsed["max_concurrent_ports"].as_str(), Some("5000"));
+    }
+
+    #[test]
+    fn show_config_needs_no_targets() {
+        let args = |list: &[&str]| list.iter().map(OsString::from).collect::<Vec<_>>();
+        let (matches, sources) = layered_matches(&args(&["carescanner", "--show-config", "-r", "50"]), &FileConfig::default()).unwrap();
+        let printed = effective_config(&matches, &sources, None);
+        assert!(printed.contains("ratelimit = \"50\"  # command line\n") && !printed.contains("targets ="));
+        let missing = layered_matches(&args(&["carescanner", "-r", "50"]), &FileConfig::default()).unwrap_err();
+        assert_eq!(missing.kind(), ErrorKind::MissingRequiredArgument);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::configuration::PortList;
+
+/// One step of a phased scan. Every phase after the first only scans hosts
+/// that answered in the phase before it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanPhase {
+    /// The N most common ports of each protocol.
+    Top(usize),
+    /// All 65535 ports.
+    Full,
+    /// The port lists given with `-p` / `--top-ports`.
+    Ports,
+}
+
+impl std::str::FromStr for ScanPhase {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_phase(s)
+    }
+}
+
+impl std::fmt::Display for ScanPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanPhase::Top(n) => write!(f, "top:{}", n),
+            ScanPhase::Full => write!(f, "full"),
+            ScanPhase::Ports => write!(f, "ports"),
+        }
+    }
+}
+
+impl ScanPhase {
+    /// Ports this phase covers, before removing ports of earlier phases.
+    /// `ranked` is the frequency table of the protocol being planned.
+    pub fn ports(&self, configured: &PortList, ranked: &[u16]) -> PortList {
+        match self {
+            ScanPhase::Top(n) => PortList { ports: ranked[..(*n).min(ranked.len())].to_vec() },
+            ScanPhase::Full => PortList { ports: (1..=65535).collect() },
+            ScanPhase::Ports => configured.clone(),
+        }
+    }
+}
+
+pub fn parse_phase(s: &str) -> Result<ScanPhase, String> {
+    let input = s.trim();
+    match input {
+        "full" => Ok(ScanPhase::Full),
+        "ports" => Ok(ScanPhase::Ports),
+        _ => match input.strip_prefix("top:") {
+            Some(n) => {
+                let n: usize = n.trim().parse().map_err(|e| format!("Invalid phase '{}': {}", input, e))?;
+                if n == 0 {
+                    return Err(format!("Invalid phase '{}': top needs at least one port", input));
+                }
+                Ok(ScanPhase::Top(n))
+            }
+            None => Err(format!("Invalid phase '{}': expected top:N, full or ports", input)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_phase_specs() {
+        assert_eq!(parse_phase("top:100").unwrap(), ScanPhase::Top(100));
+        assert_eq!(parse_phase(" full ").unwrap(), ScanPhase::Full);
+        assert_eq!(parse_phase("ports").unwrap(), ScanPhase::Ports);
+        assert!(parse_phase("top:0").is_err());
+        assert!(parse_phase("top:x").is_err());
+        assert!(parse_phase("deep").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
+use super::services::SERVICES;
 
 fn get_ports_from_file(filename: &str) -> Result<Vec<String>, String> {
     let actual_file_path = filename.trim();
@@ -8,86 +9,136 @@ fn get_ports_from_file(filename: &str) -> Result<Vec<String>, String> {
         return Err("Empty file path for ports specified with \'file:\' prefix.".to_string());
     }
 
-    let path = Path::new(filename);
+    let path = Path::new(actual_file_path);
     let file = File::open(path).map_err(|e| format!("Failed to open file '{}': {}", actual_file_path, e))?;
     let reader = BufReader::new(file);
     let lines: Vec<String> = reader.lines().collect::<Result<_, std::io::Error>>().map_err(|e| e.to_string())?;
 
-    let mut ports = Vec::new();
-    
-    for part in lines.iter() {
-        let trimmed_part = part.trim();
-        if !trimmed_part.is_empty() {
-            ports.push(trimmed_part.parse().map_err(|e| format!("Failed to parse port \'{}\': {}", trimmed_part, e))?);
+    // One or more comma-separated entries per line.
+    Ok(lines.iter()
+        .flat_map(|line| line.split(','))
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect())
+}
+
+/// The protocol a `T:` / `U:` prefixed port is limited to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    /// Name as reported by the scan types (`ScanTypeTrait::protocol`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
         }
     }
+}
 
-    Ok(ports)
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-pub fn parse_ports_string_to_vec(s: &str) -> Result<Vec<u16>, String> {
-    let mut ports_flat_vec = Vec::with_capacity(65535);
+/// Ports of one entry: a number, a range (`N-M`, `N-`, `-M`, `-`) or a service
+/// name from the bundled table, with the protocol the service is known on.
+fn parse_port_entry(part: &str) -> Result<Vec<(u16, Option<Protocol>)>, String> {
+    if part.contains('-') && part.chars().all(|c| c.is_ascii_digit() || c == '-' || c.is_whitespace()) {
+        let (start_str, end_str) = part.split_once('-').ok_or_else(|| format!("Invalid range format: {}", part))?;
+        let start: u16 = if start_str.trim().is_empty() { 1 } else {
+            start_str.trim().parse().map_err(|e| format!("Failed to parse start of range '{}': {}", start_str, e))?
+        };
+        let end: u16 = if end_str.trim().is_empty() { 65535 } else {
+            end_str.trim().parse().map_err(|e| format!("Failed to parse end of range '{}': {}", end_str, e))?
+        };
+
+        if start > end {
+            return Err(format!("Invalid range: start ({}) > end ({}) in {}", start, end, part));
+        }
+        return Ok((start..=end).map(|port| (port, None)).collect());
+    }
+    if let Ok(port) = part.parse::<u16>() {
+        return Ok(vec![(port, None)]);
+    }
 
+    let name = part.to_ascii_lowercase();
+    let known: Vec<(u16, Protocol)> = SERVICES.iter()
+        .filter(|(service, _, _)| *service == name)
+        .map(|(_, port, protocol)| (*port, *protocol))
+        .collect();
+    if known.is_empty() {
+        return Err(format!("Failed to parse port '{}': not a number, range or known service name", part));
+    }
+    // A service known on both protocols is scanned with every scan type.
+    Ok(known.iter()
+        .map(|(port, protocol)| {
+            let both = known.iter().any(|(p, other)| p == port && other != protocol);
+            (*port, (!both).then_some(*protocol))
+        })
+        .collect())
+}
+
+/// Parse a port list like nmap's `-p`: `22,80,8000-8100`, `-` for all ports,
+/// service names (`ssh,https`), and `T:` / `U:` prefixes that limit the entries
+/// after them to one protocol (`T:22,80,U:53,161`).
+pub fn parse_port_spec(s: &str) -> Result<PortSpec, String> {
     let parts_to_process = match s.trim().strip_prefix("file:") {
         Some(file_path) => get_ports_from_file(file_path)?,
         None => s.split(',').map(|part| part.trim().to_string()).collect(),
     };
 
-
+    let mut spec = PortSpec::default();
+    let mut seen = vec![vec![false; 65_536]; 3];
+    let mut prefix: Option<Protocol> = None;
     for part in parts_to_process {
-        if part.contains('-') {
-            let mut iter = part.splitn(2, '-'); 
-            let start_str = iter.next().ok_or_else(|| format!("Invalid range format: {}", part))?;
-            let end_str = iter.next().ok_or_else(|| format!("Invalid range format: {}", part))?;
-            
-            let start: u16 = start_str.trim().parse().map_err(|e| format!("Failed to parse start of range '{}': {}", start_str, e))?;
-            let end: u16 = end_str.trim().parse().map_err(|e| format!("Failed to parse end of range '{}': {}", end_str, e))?;
-
-            if start > end {
-                return Err(format!("Invalid range: start ({}) > end ({}) in {}", start, end, part));
-            }
-
-            for port in start..=end {
-                ports_flat_vec.push(port);
-            }
-        } else {
-            let port: u16 = part.trim().parse().map_err(|e| format!("Failed to parse port '{}': {}", part, e))?;
-            ports_flat_vec.push(port);
+        let mut entry = part.as_str();
+        if let Some((tag, rest)) = entry.split_once(':') {
+            prefix = match tag.trim().to_ascii_uppercase().as_str() {
+                "T" => Some(Protocol::Tcp),
+                "U" => Some(Protocol::Udp),
+                _ => return Err(format!("Invalid protocol prefix '{}:' in '{}': expected T: or U:", tag.trim(), part)),
+            };
+            entry = rest.trim();
         }
-    }
-    
-    // Deduplicate ports
-    let mut seen = [false; 65_536];
-    let mut out = Vec::with_capacity(ports_flat_vec.len());
-
-    for port in ports_flat_vec {
-        if !seen[port as usize] {
-            seen[port as usize] = true;
-            out.push(port);
+        if entry.is_empty() {
+            return Err(format!("Empty port entry in '{}'", s.trim()));
+        }
+        for (port, known_on) in parse_port_entry(entry)? {
+            // An explicit prefix wins over the protocol a service name is known on.
+            let protocol = prefix.or(known_on);
+            let (list, slot) = match protocol {
+                None => (&mut spec.all, 0),
+                Some(Protocol::Tcp) => (&mut spec.tcp, 1),
+                Some(Protocol::Udp) => (&mut spec.udp, 2),
+            };
+            if !seen[slot][port as usize] {
+                seen[slot][port as usize] = true;
+                list.ports.push(port);
+            }
         }
     }
 
-    out.shrink_to_fit();
-
-    Ok(out)
+    // Ports already scanned with every protocol need no protocol-only entry.
+    spec.tcp.ports.retain(|port| !seen[0][*port as usize]);
+    spec.udp.ports.retain(|port| !seen[0][*port as usize]);
+    Ok(spec)
 }
 
-#[derive(Debug, Clone)]
+/// Ports of one protocol, or of every protocol, in scanning order.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct PortList {
     pub ports: Vec<u16>
 }
 
-impl std::str::FromStr for PortList {
-    type Err = String;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let ports = parse_ports_string_to_vec(s)?;
-        Ok(PortList { ports })
-    }
-}
-
-impl Into<Vec<u16>> for PortList {
-    fn into(self) -> Vec<u16> {
-        self.ports
+impl From<PortList> for Vec<u16> {
+    fn from(list: PortList) -> Self {
+        list.ports
     }
 }
 
@@ -104,7 +155,156 @@ impl PortList {
         self.ports.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.ports.is_empty()
+    }
+
     pub fn vec(&self) -> Vec<u16> {
         self.ports.clone()
     }
-}
\ No newline at end of file
+}
+
+/// A `-p` value: ports scanned with every scan type, and ports limited to
+/// TCP or UDP scan types with an nmap-style `T:` / `U:` prefix.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PortSpec {
+    pub all: PortList,
+    pub tcp: PortList,
+    pub udp: PortList,
+}
+
+impl std::str::FromStr for PortSpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_port_spec(s)
+    }
+}
+
+impl From<PortList> for PortSpec {
+    fn from(all: PortList) -> Self {
+        PortSpec { all, ..Default::default() }
+    }
+}
+
+impl PortSpec {
+    /// The lists with the protocol they are limited to (`None`: every protocol).
+    pub fn parts(&self) -> [(Option<Protocol>, &PortList); 3] {
+        [(None, &self.all), (Some(Protocol::Tcp), &self.tcp), (Some(Protocol::Udp), &self.udp)]
+    }
+
+    /// Number of distinct ports over all protocols.
+    pub fn len(&self) -> usize {
+        let mut seen = vec![false; 65_536];
+        self.parts().iter()
+            .flat_map(|(_, list)| list.ports.iter())
+            .filter(|port| !std::mem::replace(&mut seen[**port as usize], true))
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts().iter().all(|(_, list)| list.is_empty())
+    }
+
+    /// The ports scanned with `protocol`: the unprefixed ones, then its own.
+    pub fn for_protocol(&self, protocol: Protocol) -> PortList {
+        let only = match protocol {
+            Protocol::Tcp => &self.tcp,
+            Protocol::Udp => &self.udp,
+        };
+        PortList { ports: self.all.ports.iter().chain(&only.ports).copied().collect() }
+    }
+
+    /// Whether each port is listed for `protocol`, with or without a prefix.
+    pub fn mask(&self, protocol: Protocol) -> Vec<bool> {
+        let mut mask = vec![false; 65_536];
+        for (only, list) in self.parts() {
+            if only.is_none_or(|p| p == protocol) {
+                for port in &list.ports {
+                    mask[*port as usize] = true;
+                }
+            }
+        }
+        mask
+    }
+}
+
+/// Ports sorted, with consecutive ones joined into ranges: `22,80-90`.
+fn compact(ports: &[u16]) -> String {
+    let mut sorted = ports.to_vec();
+    sorted.sort_unstable();
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+    for port in sorted {
+        match ranges.last_mut() {
+            Some((_, end)) if port <= end.saturating_add(1) => *end = (*end).max(port),
+            _ => ranges.push((port, port)),
+        }
+    }
+    ranges.iter()
+        .map(|(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The same syntax as `-p`, e.g. `9100,T:22,U:53-60`.
+impl std::fmt::Display for PortSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = self.parts().iter()
+            .filter(|(_, list)| !list.is_empty())
+            .map(|(protocol, list)| match protocol {
+                None => compact(&list.ports),
+                Some(Protocol::Tcp) => format!("T:{}", compact(&list.ports)),
+                Some(Protocol::Udp) => format!("U:{}", compact(&list.ports)),
+            })
+            .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(s: &str) -> PortSpec {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn protocol_prefixes_split_the_lists() {
+        let ports = spec("T:22,80,U:53,161,t:8000-8002");
+        assert_eq!(ports.all.ports, Vec::<u16>::new());
+        assert_eq!(ports.tcp.ports, [22, 80, 8000, 8001, 8002]);
+        assert_eq!(ports.udp.ports, [53, 161]);
+
+        let ports = spec("443,U:443,53");
+        assert_eq!((ports.all.ports, ports.udp.ports), (vec![443], vec![53]));
+        assert!(parse_port_spec("S:80").unwrap_err().contains("expected T: or U:"));
+    }
+
+    #[test]
+    fn dash_means_every_port() {
+        assert_eq!(spec("-").all.len(), 65535);
+        assert_eq!(spec("60000-").all.ports.last(), Some(&65535));
+        assert_eq!(spec("-3").all.ports, [1, 2, 3]);
+        assert_eq!(spec("U:-").udp.len(), 65535);
+    }
+
+    #[test]
+    fn service_names_use_their_protocol() {
+        let ports = spec("ssh,domain,ntp,HTTPS");
+        assert_eq!(ports.all.ports, [53, 443]);
+        assert_eq!(ports.tcp.ports, [22]);
+        assert_eq!(ports.udp.ports, [123]);
+        assert_eq!(spec("U:snmp").udp.ports, [161]);
+        assert!(parse_port_spec("nosuchservice").unwrap_err().contains("known service name"));
+    }
+
+    #[test]
+    fn displays_in_port_list_syntax() {
+        let ports = spec("9100,9101,9102,22,U:53,T:8080");
+        assert_eq!(ports.to_string(), "22,9100-9102,T:8080,U:53");
+        assert_eq!(spec(&ports.to_string()).to_string(), ports.to_string());
+        assert!(ports.mask(Protocol::Udp)[53] && !ports.mask(Protocol::Tcp)[53]);
+        assert!(ports.mask(Protocol::Tcp)[9101] && ports.mask(Protocol::Udp)[9101]);
+        assert_eq!(ports.for_protocol(Protocol::Udp).ports, [9100, 9101, 9102, 22, 53]);
+    }
+}
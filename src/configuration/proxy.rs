@@ -12,9 +12,9 @@ impl std::str::FromStr for ProxyList {
     }
 }
 
-impl Into<Vec<String>> for ProxyList {
-    fn into(self) -> Vec<String> {
-        self.proxies
+impl From<ProxyList> for Vec<String> {
+    fn from(list: ProxyList) -> Self {
+        list.proxies
     }
 }
 
@@ -32,6 +32,10 @@ impl ProxyList {
         self.proxies.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.proxies.is_empty()
+    }
+
     pub fn vec(&self) -> Vec<String> {
         self.proxies.clone()
     }
@@ -58,4 +62,42 @@ pub enum ProxyStrategy {
     Sequential,
     Random,
     Chain
-}
\ No newline at end of file
+}
+
+/// A SOCKS5 proxy password. Kept out of `Debug` output so it never reaches logs.
+#[derive(Clone)]
+pub struct ProxyPassword(String);
+
+impl std::str::FromStr for ProxyPassword {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ProxyPassword(s.to_string()))
+    }
+}
+
+impl std::fmt::Debug for ProxyPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProxyPassword(***)")
+    }
+}
+
+/// Username/password authentication (RFC 1929), used with every proxy.
+#[derive(Debug, Clone)]
+pub struct ProxyAuth {
+    pub username: String,
+    password: ProxyPassword,
+}
+
+impl ProxyAuth {
+    /// Credentials if a username is set; a missing password is sent empty.
+    pub fn new(username: Option<&String>, password: Option<&ProxyPassword>) -> Option<Self> {
+        Some(ProxyAuth {
+            username: username?.clone(),
+            password: password.cloned().unwrap_or(ProxyPassword(String::new())),
+        })
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password.0
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::configuration::exclusion::{parse_exclusions, ExclusionSet};
+use crate::configuration::TargetList;
+
+/// The addresses a scan is authorised to touch (`--scope-file`): addresses,
+/// CIDRs, ranges and hostnames, in the syntax of `--targets`. A hostname puts
+/// the addresses it resolves to in scope.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    allowed: ExclusionSet,
+    pub path: String,
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_scope_file(s)
+    }
+}
+
+impl Scope {
+    pub fn unresolved(&self) -> &[Arc<str>] {
+        self.allowed.unresolved()
+    }
+
+    /// Put the addresses of the scope's hostnames in scope.
+    pub fn add_resolved(&mut self, resolved: &HashMap<Arc<str>, Vec<IpAddr>>) {
+        self.allowed.add_resolved(resolved);
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.allowed.contains(ip)
+    }
+
+    /// Same as `allows`, for addresses kept as strings; anything that is not an IP is refused.
+    pub fn allows_str(&self, ip: &str) -> bool {
+        self.allowed.contains_str(ip)
+    }
+
+    /// The targets in scope, and those refused.
+    pub fn split(&self, targets: &TargetList) -> (TargetList, TargetList) {
+        let refused = targets.exclude(&self.allowed);
+        (targets.exclude(&ExclusionSet::from(&refused)), refused)
+    }
+}
+
+/// Parse `--scope-file`: one or more entries per line, `#` comments allowed.
+pub fn parse_scope_file(path: &str) -> Result<Scope, String> {
+    let allowed = parse_exclusions(&format!("file:{}", path.trim()))?;
+    if allowed.is_empty() && allowed.unresolved().is_empty() {
+        return Err(format!("Scope file '{}' lists no addresses", path.trim()));
+    }
+    Ok(Scope { allowed, path: path.trim().to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::target_parsing::parse_target_input;
+
+    #[test]
+    fn only_scoped_addresses_are_kept() {
+        let path = std::env::temp_dir().join(format!("carescanner-scope-{}.txt", std::process::id()));
+        std::fs::write(&path, "# engagement 42\n10.0.0.0/30\nportal.client.example\n").unwrap();
+        let mut scope = parse_scope_file(&path.display().to_string()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        scope.add_resolved(&HashMap::from([(Arc::from("portal.client.example"), vec!["192.0.2.10".parse().unwrap()])]));
+
+        let targets = parse_target_input("10.0.0.2-6,192.0.2.10,192.0.2.11").unwrap();
+        let (allowed, refused) = scope.split(&targets);
+        assert_eq!(allowed.iter().map(|ip| ip.to_string()).collect::<Vec<_>>(), ["10.0.0.2", "10.0.0.3", "192.0.2.10"]);
+        assert_eq!(refused.labels(), ["10.0.0.4-10.0.0.6 (3 addresses)", "192.0.2.11"]);
+        assert!(scope.allows_str("10.0.0.1") && !scope.allows_str("10.0.0.4") && !scope.allows_str("portal.client.example"));
+
+        let empty = std::env::temp_dir().join(format!("carescanner-scope-empty-{}.txt", std::process::id()));
+        std::fs::write(&empty, "# nothing yet\n").unwrap();
+        let error = parse_scope_file(&empty.display().to_string()).unwrap_err();
+        std::fs::remove_file(&empty).unwrap();
+        assert!(error.contains("lists no addresses"));
+    }
+
+    #[test]
+    fn stepping_scope_keeps_targets_as_ranges() {
+        let path = std::env::temp_dir().join(format!("carescanner-scope-step-{}.txt", std::process::id()));
+        std::fs::write(&path, "*.*.*.1\n").unwrap();
+        let scope = parse_scope_file(&path.display().to_string()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (allowed, refused) = scope.split(&parse_target_input("10.0.0.0/8").unwrap());
+        assert_eq!((allowed.ranges().len(), allowed.len()), (3, 1 << 16));
+        assert!(allowed.contains("10.9.8.1".parse().unwrap()));
+        assert_eq!((refused.ranges().len(), refused.len()), (257, (1 << 24) - (1 << 16)));
+    }
+}
@@ -0,0 +1,411 @@
+use super::port_parsing::Protocol;
+
+/// Service names accepted in port lists (`-p ssh,https`), with their ports.
+/// Source: the IANA-derived /etc/services table from Debian netbase, plus common
+/// names nmap and admins use for services IANA lists differently (rdp, vnc, smb, ...).
+pub const SERVICES: &[(&str, u16, Protocol)] = &[
+    ("acr-nema", 104, Protocol::Tcp),
+    ("afpovertcp", 548, Protocol::Tcp),
+    ("afs3-bos", 7007, Protocol::Udp),
+    ("afs3-callback", 7001, Protocol::Udp),
+    ("afs3-fileserver", 7000, Protocol::Udp),
+    ("afs3-kaserver", 7004, Protocol::Udp),
+    ("afs3-prserver", 7002, Protocol::Udp),
+    ("afs3-rmtsys", 7009, Protocol::Udp),
+    ("afs3-update", 7008, Protocol::Udp),
+    ("afs3-vlserver", 7003, Protocol::Udp),
+    ("afs3-volser", 7005, Protocol::Udp),
+    ("amanda", 10080, Protocol::Tcp),
+    ("amandaidx", 10082, Protocol::Tcp),
+    ("amidxtape", 10083, Protocol::Tcp),
+    ("amqp", 5672, Protocol::Tcp),
+    ("amqps", 5671, Protocol::Tcp),
+    ("asf-rmcp", 623, Protocol::Udp),
+    ("asp", 27374, Protocol::Tcp),
+    ("asp", 27374, Protocol::Udp),
+    ("auth", 113, Protocol::Tcp),
+    ("authentication", 113, Protocol::Tcp),
+    ("babel", 6696, Protocol::Udp),
+    ("bacula-dir", 9101, Protocol::Tcp),
+    ("bacula-fd", 9102, Protocol::Tcp),
+    ("bacula-sd", 9103, Protocol::Tcp),
+    ("bbs", 7000, Protocol::Tcp),
+    ("bgp", 179, Protocol::Tcp),
+    ("bgpd", 2605, Protocol::Tcp),
+    ("biff", 512, Protocol::Udp),
+    ("binkp", 24554, Protocol::Tcp),
+    ("bootpc", 68, Protocol::Udp),
+    ("bootps", 67, Protocol::Udp),
+    ("canna", 5680, Protocol::Tcp),
+    ("cfengine", 5308, Protocol::Tcp),
+    ("chargen", 19, Protocol::Tcp),
+    ("chargen", 19, Protocol::Udp),
+    ("cisco-sccp", 2000, Protocol::Tcp),
+    ("clc-build-daemon", 8990, Protocol::Tcp),
+    ("clearcase", 371, Protocol::Udp),
+    ("cmd", 514, Protocol::Tcp),
+    ("cmip-agent", 164, Protocol::Tcp),
+    ("cmip-agent", 164, Protocol::Udp),
+    ("cmip-man", 163, Protocol::Tcp),
+    ("cmip-man", 163, Protocol::Udp),
+    ("codaauth2", 370, Protocol::Tcp),
+    ("codaauth2", 370, Protocol::Udp),
+    ("codasrv", 2432, Protocol::Tcp),
+    ("codasrv", 2432, Protocol::Udp),
+    ("codasrv-se", 2433, Protocol::Tcp),
+    ("codasrv-se", 2433, Protocol::Udp),
+    ("comsat", 512, Protocol::Udp),
+    ("csync2", 30865, Protocol::Tcp),
+    ("cvspserver", 2401, Protocol::Tcp),
+    ("daap", 3689, Protocol::Tcp),
+    ("datametrics", 1645, Protocol::Tcp),
+    ("datametrics", 1645, Protocol::Udp),
+    ("daytime", 13, Protocol::Tcp),
+    ("daytime", 13, Protocol::Udp),
+    ("db-lsp", 17500, Protocol::Tcp),
+    ("dcap", 22125, Protocol::Tcp),
+    ("dhcpv6-client", 546, Protocol::Udp),
+    ("dhcpv6-server", 547, Protocol::Udp),
+    ("dicom", 104, Protocol::Tcp),
+    ("dicom", 11112, Protocol::Tcp),
+    ("dict", 2628, Protocol::Tcp),
+    ("dircproxy", 57000, Protocol::Tcp),
+    ("discard", 9, Protocol::Tcp),
+    ("discard", 9, Protocol::Udp),
+    ("distcc", 3632, Protocol::Tcp),
+    ("docker", 2375, Protocol::Tcp),
+    ("domain", 53, Protocol::Tcp),
+    ("domain", 53, Protocol::Udp),
+    ("domain-s", 853, Protocol::Tcp),
+    ("domain-s", 853, Protocol::Udp),
+    ("echo", 7, Protocol::Tcp),
+    ("echo", 7, Protocol::Udp),
+    ("elasticsearch", 9200, Protocol::Tcp),
+    ("epmap", 135, Protocol::Tcp),
+    ("epmd", 4369, Protocol::Tcp),
+    ("exec", 512, Protocol::Tcp),
+    ("f5-globalsite", 2792, Protocol::Tcp),
+    ("f5-iquery", 4353, Protocol::Tcp),
+    ("fax", 4557, Protocol::Tcp),
+    ("fido", 60179, Protocol::Tcp),
+    ("finger", 79, Protocol::Tcp),
+    ("font-service", 7100, Protocol::Tcp),
+    ("freeciv", 5556, Protocol::Tcp),
+    ("fsp", 21, Protocol::Udp),
+    ("fspd", 21, Protocol::Udp),
+    ("ftp", 21, Protocol::Tcp),
+    ("ftp-data", 20, Protocol::Tcp),
+    ("ftps", 990, Protocol::Tcp),
+    ("ftps-data", 989, Protocol::Tcp),
+    ("gdomap", 538, Protocol::Tcp),
+    ("gdomap", 538, Protocol::Udp),
+    ("gds-db", 3050, Protocol::Tcp),
+    ("git", 9418, Protocol::Tcp),
+    ("gnunet", 2086, Protocol::Tcp),
+    ("gnunet", 2086, Protocol::Udp),
+    ("gnutella-rtr", 6347, Protocol::Tcp),
+    ("gnutella-rtr", 6347, Protocol::Udp),
+    ("gnutella-svc", 6346, Protocol::Tcp),
+    ("gnutella-svc", 6346, Protocol::Udp),
+    ("gopher", 70, Protocol::Tcp),
+    ("gpsd", 2947, Protocol::Tcp),
+    ("gris", 2135, Protocol::Tcp),
+    ("groupwise", 1677, Protocol::Tcp),
+    ("gsidcap", 22128, Protocol::Tcp),
+    ("gsiftp", 2811, Protocol::Tcp),
+    ("gsigatekeeper", 2119, Protocol::Tcp),
+    ("hkp", 11371, Protocol::Tcp),
+    ("hprop", 754, Protocol::Tcp),
+    ("http", 80, Protocol::Tcp),
+    ("http-alt", 8080, Protocol::Tcp),
+    ("http-proxy", 8080, Protocol::Tcp),
+    ("https", 443, Protocol::Tcp),
+    ("https", 443, Protocol::Udp),
+    ("https-alt", 8443, Protocol::Tcp),
+    ("hylafax", 4559, Protocol::Tcp),
+    ("iax", 4569, Protocol::Udp),
+    ("icp", 3130, Protocol::Udp),
+    ("icpv2", 3130, Protocol::Udp),
+    ("ident", 113, Protocol::Tcp),
+    ("imap", 143, Protocol::Tcp),
+    ("imap2", 143, Protocol::Tcp),
+    ("imaps", 993, Protocol::Tcp),
+    ("ingreslock", 1524, Protocol::Tcp),
+    ("ipp", 631, Protocol::Tcp),
+    ("iprop", 2121, Protocol::Tcp),
+    ("ipsec-nat-t", 4500, Protocol::Udp),
+    ("ipx", 213, Protocol::Udp),
+    ("ircd", 6667, Protocol::Tcp),
+    ("ircs-u", 6697, Protocol::Tcp),
+    ("isakmp", 500, Protocol::Udp),
+    ("iscsi-target", 3260, Protocol::Tcp),
+    ("isisd", 2608, Protocol::Tcp),
+    ("isns", 3205, Protocol::Tcp),
+    ("isns", 3205, Protocol::Udp),
+    ("iso-tsap", 102, Protocol::Tcp),
+    ("jabber-client", 5222, Protocol::Tcp),
+    ("jabber-server", 5269, Protocol::Tcp),
+    ("kamanda", 10081, Protocol::Tcp),
+    ("kdc", 750, Protocol::Tcp),
+    ("kdc", 750, Protocol::Udp),
+    ("kerberos", 88, Protocol::Tcp),
+    ("kerberos", 88, Protocol::Udp),
+    ("kerberos-adm", 749, Protocol::Tcp),
+    ("kerberos-iv", 750, Protocol::Tcp),
+    ("kerberos-iv", 750, Protocol::Udp),
+    ("kerberos-master", 751, Protocol::Tcp),
+    ("kerberos-master", 751, Protocol::Udp),
+    ("kerberos-sec", 88, Protocol::Tcp),
+    ("kerberos-sec", 88, Protocol::Udp),
+    ("kerberos4", 750, Protocol::Tcp),
+    ("kerberos4", 750, Protocol::Udp),
+    ("kerberos5", 88, Protocol::Tcp),
+    ("kerberos5", 88, Protocol::Udp),
+    ("kermit", 1649, Protocol::Tcp),
+    ("klogin", 543, Protocol::Tcp),
+    ("kpasswd", 464, Protocol::Tcp),
+    ("kpasswd", 464, Protocol::Udp),
+    ("krb-prop", 754, Protocol::Tcp),
+    ("krb5", 88, Protocol::Tcp),
+    ("krb5", 88, Protocol::Udp),
+    ("krcmd", 544, Protocol::Tcp),
+    ("kshell", 544, Protocol::Tcp),
+    ("l2f", 1701, Protocol::Udp),
+    ("l2tp", 1701, Protocol::Udp),
+    ("ldap", 389, Protocol::Tcp),
+    ("ldap", 389, Protocol::Udp),
+    ("ldaps", 636, Protocol::Tcp),
+    ("ldaps", 636, Protocol::Udp),
+    ("ldp", 646, Protocol::Tcp),
+    ("ldp", 646, Protocol::Udp),
+    ("loc-srv", 135, Protocol::Tcp),
+    ("login", 513, Protocol::Tcp),
+    ("lotusnote", 1352, Protocol::Tcp),
+    ("lotusnotes", 1352, Protocol::Tcp),
+    ("lrrd", 4949, Protocol::Tcp),
+    ("mail", 25, Protocol::Tcp),
+    ("mailq", 174, Protocol::Tcp),
+    ("mdns", 5353, Protocol::Udp),
+    ("memcached", 11211, Protocol::Tcp),
+    ("memcached", 11211, Protocol::Udp),
+    ("microsoft-ds", 445, Protocol::Tcp),
+    ("moira-db", 775, Protocol::Tcp),
+    ("moira-update", 777, Protocol::Tcp),
+    ("moira-ureg", 779, Protocol::Udp),
+    ("mon", 2583, Protocol::Tcp),
+    ("mon", 2583, Protocol::Udp),
+    ("mongodb", 27017, Protocol::Tcp),
+    ("ms-sql-m", 1434, Protocol::Udp),
+    ("ms-sql-s", 1433, Protocol::Tcp),
+    ("ms-wbt-server", 3389, Protocol::Tcp),
+    ("mssql", 1433, Protocol::Tcp),
+    ("mtn", 4691, Protocol::Tcp),
+    ("munin", 4949, Protocol::Tcp),
+    ("mysql", 3306, Protocol::Tcp),
+    ("mysql-proxy", 6446, Protocol::Tcp),
+    ("nbd", 10809, Protocol::Tcp),
+    ("netbios-dgm", 138, Protocol::Udp),
+    ("netbios-ns", 137, Protocol::Udp),
+    ("netbios-ssn", 139, Protocol::Tcp),
+    ("netstat", 15, Protocol::Tcp),
+    ("nfs", 2049, Protocol::Tcp),
+    ("nfs", 2049, Protocol::Udp),
+    ("nicname", 43, Protocol::Tcp),
+    ("nntp", 119, Protocol::Tcp),
+    ("nntps", 563, Protocol::Tcp),
+    ("nqs", 607, Protocol::Tcp),
+    ("nrpe", 5666, Protocol::Tcp),
+    ("nsca", 5667, Protocol::Tcp),
+    ("ntalk", 518, Protocol::Udp),
+    ("ntp", 123, Protocol::Udp),
+    ("ntske", 4460, Protocol::Tcp),
+    ("null", 9, Protocol::Tcp),
+    ("null", 9, Protocol::Udp),
+    ("nut", 3493, Protocol::Tcp),
+    ("nut", 3493, Protocol::Udp),
+    ("old-radacct", 1646, Protocol::Tcp),
+    ("old-radacct", 1646, Protocol::Udp),
+    ("old-radius", 1645, Protocol::Tcp),
+    ("old-radius", 1645, Protocol::Udp),
+    ("omniorb", 8088, Protocol::Tcp),
+    ("openvpn", 1194, Protocol::Tcp),
+    ("openvpn", 1194, Protocol::Udp),
+    ("ospf6d", 2606, Protocol::Tcp),
+    ("ospfapi", 2607, Protocol::Tcp),
+    ("ospfd", 2604, Protocol::Tcp),
+    ("passwd-server", 752, Protocol::Udp),
+    ("pawserv", 345, Protocol::Tcp),
+    ("pop-3", 110, Protocol::Tcp),
+    ("pop3", 110, Protocol::Tcp),
+    ("pop3s", 995, Protocol::Tcp),
+    ("poppassd", 106, Protocol::Tcp),
+    ("portmapper", 111, Protocol::Tcp),
+    ("portmapper", 111, Protocol::Udp),
+    ("postgres", 5432, Protocol::Tcp),
+    ("postgresql", 5432, Protocol::Tcp),
+    ("predict", 1210, Protocol::Udp),
+    ("printer", 515, Protocol::Tcp),
+    ("proofd", 1093, Protocol::Tcp),
+    ("ptp-event", 319, Protocol::Udp),
+    ("ptp-general", 320, Protocol::Udp),
+    ("puppet", 8140, Protocol::Tcp),
+    ("qmqp", 628, Protocol::Tcp),
+    ("qmtp", 209, Protocol::Tcp),
+    ("qotd", 17, Protocol::Tcp),
+    ("quote", 17, Protocol::Tcp),
+    ("radacct", 1813, Protocol::Tcp),
+    ("radacct", 1813, Protocol::Udp),
+    ("radius", 1812, Protocol::Tcp),
+    ("radius", 1812, Protocol::Udp),
+    ("radius-acct", 1813, Protocol::Tcp),
+    ("radius-acct", 1813, Protocol::Udp),
+    ("radmin-port", 4899, Protocol::Tcp),
+    ("rdp", 3389, Protocol::Tcp),
+    ("readnews", 119, Protocol::Tcp),
+    ("redis", 6379, Protocol::Tcp),
+    ("remctl", 4373, Protocol::Tcp),
+    ("ripd", 2602, Protocol::Tcp),
+    ("ripngd", 2603, Protocol::Tcp),
+    ("rmiregistry", 1099, Protocol::Tcp),
+    ("rmtcfg", 1236, Protocol::Tcp),
+    ("rootd", 1094, Protocol::Tcp),
+    ("route", 520, Protocol::Udp),
+    ("routed", 520, Protocol::Udp),
+    ("router", 520, Protocol::Udp),
+    ("rpc2portmap", 369, Protocol::Tcp),
+    ("rpc2portmap", 369, Protocol::Udp),
+    ("rpcbind", 111, Protocol::Tcp),
+    ("rpcbind", 111, Protocol::Udp),
+    ("rplay", 5555, Protocol::Udp),
+    ("rptp", 5556, Protocol::Tcp),
+    ("rsync", 873, Protocol::Tcp),
+    ("rtcm-sc104", 2101, Protocol::Tcp),
+    ("rtcm-sc104", 2101, Protocol::Udp),
+    ("rtsp", 554, Protocol::Tcp),
+    ("rtsp", 554, Protocol::Udp),
+    ("sa-msg-port", 1646, Protocol::Tcp),
+    ("sa-msg-port", 1646, Protocol::Udp),
+    ("saft", 487, Protocol::Tcp),
+    ("sane", 6566, Protocol::Tcp),
+    ("sane-port", 6566, Protocol::Tcp),
+    ("saned", 6566, Protocol::Tcp),
+    ("sge-execd", 6445, Protocol::Tcp),
+    ("sge-qmaster", 6444, Protocol::Tcp),
+    ("sgi-cad", 17004, Protocol::Tcp),
+    ("sgi-cmsd", 17001, Protocol::Udp),
+    ("sgi-crsd", 17002, Protocol::Udp),
+    ("sgi-gcd", 17003, Protocol::Udp),
+    ("shell", 514, Protocol::Tcp),
+    ("sieve", 4190, Protocol::Tcp),
+    ("silc", 706, Protocol::Tcp),
+    ("sink", 9, Protocol::Tcp),
+    ("sink", 9, Protocol::Udp),
+    ("sip", 5060, Protocol::Tcp),
+    ("sip", 5060, Protocol::Udp),
+    ("sip-tls", 5061, Protocol::Tcp),
+    ("sip-tls", 5061, Protocol::Udp),
+    ("skkserv", 1178, Protocol::Tcp),
+    ("smb", 445, Protocol::Tcp),
+    ("smtp", 25, Protocol::Tcp),
+    ("smtps", 465, Protocol::Tcp),
+    ("smux", 199, Protocol::Tcp),
+    ("snmp", 161, Protocol::Tcp),
+    ("snmp", 161, Protocol::Udp),
+    ("snmp-trap", 162, Protocol::Tcp),
+    ("snmp-trap", 162, Protocol::Udp),
+    ("snmptrap", 162, Protocol::Tcp),
+    ("snmptrap", 162, Protocol::Udp),
+    ("snntp", 563, Protocol::Tcp),
+    ("snpp", 444, Protocol::Tcp),
+    ("socks", 1080, Protocol::Tcp),
+    ("source", 19, Protocol::Tcp),
+    ("source", 19, Protocol::Udp),
+    ("spamd", 783, Protocol::Tcp),
+    ("spooler", 515, Protocol::Tcp),
+    ("ssdp", 1900, Protocol::Udp),
+    ("ssh", 22, Protocol::Tcp),
+    ("ssmtp", 465, Protocol::Tcp),
+    ("submission", 587, Protocol::Tcp),
+    ("submissions", 465, Protocol::Tcp),
+    ("subversion", 3690, Protocol::Tcp),
+    ("sunrpc", 111, Protocol::Tcp),
+    ("sunrpc", 111, Protocol::Udp),
+    ("supfiledbg", 1127, Protocol::Tcp),
+    ("supfilesrv", 871, Protocol::Tcp),
+    ("suucp", 4031, Protocol::Tcp),
+    ("svn", 3690, Protocol::Tcp),
+    ("svrloc", 427, Protocol::Tcp),
+    ("svrloc", 427, Protocol::Udp),
+    ("syslog", 514, Protocol::Tcp),
+    ("syslog", 514, Protocol::Udp),
+    ("syslog-tls", 6514, Protocol::Tcp),
+    ("sysrqd", 4094, Protocol::Tcp),
+    ("systat", 11, Protocol::Tcp),
+    ("tacacs", 49, Protocol::Tcp),
+    ("tacacs", 49, Protocol::Udp),
+    ("talk", 517, Protocol::Udp),
+    ("tap", 113, Protocol::Tcp),
+    ("tcpmux", 1, Protocol::Tcp),
+    ("telnet", 23, Protocol::Tcp),
+    ("telnets", 992, Protocol::Tcp),
+    ("tfido", 60177, Protocol::Tcp),
+    ("tftp", 69, Protocol::Udp),
+    ("time", 37, Protocol::Tcp),
+    ("time", 37, Protocol::Udp),
+    ("timserver", 37, Protocol::Tcp),
+    ("timserver", 37, Protocol::Udp),
+    ("tinc", 655, Protocol::Tcp),
+    ("tinc", 655, Protocol::Udp),
+    ("tproxy", 8081, Protocol::Tcp),
+    ("tsap", 102, Protocol::Tcp),
+    ("ttytst", 19, Protocol::Tcp),
+    ("ttytst", 19, Protocol::Udp),
+    ("untp", 119, Protocol::Tcp),
+    ("upnp", 1900, Protocol::Udp),
+    ("urd", 465, Protocol::Tcp),
+    ("users", 11, Protocol::Tcp),
+    ("uucp", 540, Protocol::Tcp),
+    ("uucpd", 540, Protocol::Tcp),
+    ("venus", 2430, Protocol::Tcp),
+    ("venus", 2430, Protocol::Udp),
+    ("venus-se", 2431, Protocol::Tcp),
+    ("venus-se", 2431, Protocol::Udp),
+    ("vnc", 5900, Protocol::Tcp),
+    ("wais", 210, Protocol::Tcp),
+    ("webcache", 8080, Protocol::Tcp),
+    ("webmin", 10000, Protocol::Tcp),
+    ("who", 513, Protocol::Udp),
+    ("whod", 513, Protocol::Udp),
+    ("whois", 43, Protocol::Tcp),
+    ("winrm", 5985, Protocol::Tcp),
+    ("wnn6", 22273, Protocol::Tcp),
+    ("www", 80, Protocol::Tcp),
+    ("x11", 6000, Protocol::Tcp),
+    ("x11-0", 6000, Protocol::Tcp),
+    ("x11-1", 6001, Protocol::Tcp),
+    ("x11-2", 6002, Protocol::Tcp),
+    ("x11-3", 6003, Protocol::Tcp),
+    ("x11-4", 6004, Protocol::Tcp),
+    ("x11-5", 6005, Protocol::Tcp),
+    ("x11-6", 6006, Protocol::Tcp),
+    ("x11-7", 6007, Protocol::Tcp),
+    ("xdmcp", 177, Protocol::Udp),
+    ("xfs", 7100, Protocol::Tcp),
+    ("xinetd", 9098, Protocol::Tcp),
+    ("xmms2", 9667, Protocol::Tcp),
+    ("xmpp-client", 5222, Protocol::Tcp),
+    ("xmpp-server", 5269, Protocol::Tcp),
+    ("xtel", 1313, Protocol::Tcp),
+    ("xtelw", 1314, Protocol::Tcp),
+    ("z3950", 210, Protocol::Tcp),
+    ("zabbix-agent", 10050, Protocol::Tcp),
+    ("zabbix-trapper", 10051, Protocol::Tcp),
+    ("zebra", 2601, Protocol::Tcp),
+    ("zebrasrv", 2600, Protocol::Tcp),
+    ("zephyr-clt", 2103, Protocol::Udp),
+    ("zephyr-hm", 2104, Protocol::Udp),
+    ("zephyr-srv", 2102, Protocol::Udp),
+    ("zope", 9673, Protocol::Tcp),
+    ("zope-ftp", 8021, Protocol::Tcp),
+    ("zserv", 346, Protocol::Tcp),
+];
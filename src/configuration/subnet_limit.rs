@@ -0,0 +1,111 @@
+use std::net::IpAddr;
+use ipnetwork::IpNetwork;
+
+/// Politeness limit applied to every subnet of a given prefix length,
+/// e.g. `/24:200pps` or `v6/64:100pps:20c`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubnetLimit {
+    pub ipv6: bool,
+    pub prefix: u8,
+    pub rate: Option<u64>,
+    pub max_concurrent: Option<usize>,
+}
+
+impl std::str::FromStr for SubnetLimit {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_subnet_limit(s)
+    }
+}
+
+impl std::fmt::Display for SubnetLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", if self.ipv6 { "v6" } else { "v4" }, self.prefix)?;
+        if let Some(rate) = self.rate {
+            write!(f, ":{}pps", rate)?;
+        }
+        if let Some(max) = self.max_concurrent {
+            write!(f, ":{}c", max)?;
+        }
+        Ok(())
+    }
+}
+
+impl SubnetLimit {
+    /// Key of the subnet containing `ip`, or None if the limit is for the other address family.
+    pub fn subnet_key(&self, ip: &str) -> Option<String> {
+        let addr: IpAddr = ip.parse().ok()?;
+        if addr.is_ipv6() != self.ipv6 {
+            return None;
+        }
+        let network = IpNetwork::new(addr, self.prefix).ok()?;
+        Some(format!("{}/{}", network.network(), self.prefix))
+    }
+}
+
+/// Parse `[v4|v6]/PREFIX:LIMIT[:LIMIT]` where LIMIT is `<N>pps` (rate) or `<N>c` (concurrency).
+/// Without a family, prefixes up to 32 apply to IPv4 and longer ones to IPv6.
+pub fn parse_subnet_limit(s: &str) -> Result<SubnetLimit, String> {
+    let input = s.trim();
+    let mut parts = input.split(':');
+    let subnet = parts.next().unwrap_or_default();
+
+    let (family, prefix_str) = subnet
+        .split_once('/')
+        .ok_or_else(|| format!("Invalid subnet limit '{}': expected a prefix like /24", input))?;
+    let prefix: u8 = prefix_str
+        .parse()
+        .map_err(|e| format!("Invalid prefix length '{}' in '{}': {}", prefix_str, input, e))?;
+    let ipv6 = match family {
+        "" => prefix > 32,
+        "v4" => false,
+        "v6" => true,
+        other => return Err(format!("Invalid address family '{}' in '{}': expected v4 or v6", other, input)),
+    };
+    if prefix > if ipv6 { 128 } else { 32 } {
+        return Err(format!("Prefix length /{} is too long for {}", prefix, if ipv6 { "IPv6" } else { "IPv4" }));
+    }
+
+    let mut limit = SubnetLimit { ipv6, prefix, rate: None, max_concurrent: None };
+    for part in parts {
+        let part = part.trim().to_ascii_lowercase();
+        if let Some(n) = part.strip_suffix("pps") {
+            limit.rate = Some(n.parse().map_err(|e| format!("Invalid rate '{}' in '{}': {}", part, input, e))?);
+        } else if let Some(n) = part.strip_suffix('c') {
+            limit.max_concurrent = Some(n.parse().map_err(|e| format!("Invalid concurrency '{}' in '{}': {}", part, input, e))?);
+        } else {
+            return Err(format!("Invalid limit '{}' in '{}': expected <N>pps or <N>c", part, input));
+        }
+    }
+    if limit.rate.is_none() && limit.max_concurrent.is_none() {
+        return Err(format!("Subnet limit '{}' sets neither a rate (<N>pps) nor a concurrency (<N>c)", input));
+    }
+
+    Ok(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rate_and_concurrency() {
+        let limit = parse_subnet_limit("/24:200pps:16c").unwrap();
+        assert_eq!(limit, SubnetLimit { ipv6: false, prefix: 24, rate: Some(200), max_concurrent: Some(16) });
+        assert!(parse_subnet_limit("v6/48:500pps").unwrap().ipv6);
+        assert!(parse_subnet_limit("/64:10c").unwrap().ipv6);
+        assert!(parse_subnet_limit("/24").is_err());
+        assert!(parse_subnet_limit("v4/33:1pps").is_err());
+        assert!(parse_subnet_limit("/24:fast").is_err());
+    }
+
+    #[test]
+    fn subnet_key_masks_host_bits() {
+        let limit = parse_subnet_limit("/24:200pps").unwrap();
+        assert_eq!(limit.subnet_key("10.1.2.77").as_deref(), Some("10.1.2.0/24"));
+        assert_eq!(limit.subnet_key("2001:db8::1"), None);
+
+        let limit = parse_subnet_limit("v6/64:200pps").unwrap();
+        assert_eq!(limit.subnet_key("2001:db8::1:2").as_deref(), Some("2001:db8::/64"));
+    }
+}
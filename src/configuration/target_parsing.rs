@@ -1,12 +1,227 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::Path;
+use std::sync::Arc;
 use ipnetwork::IpNetwork;
-use dns_lookup::lookup_host;
+use crate::configuration::{ExclusionSet, PortSpec};
+use crate::modes::Target;
 
-#[derive(Debug, Clone)]
+/// Addresses `start`, `start + step`, ... (`count` of them) of one address family,
+/// kept as integers so a /8 or an IPv6 /72 costs the same as a single host.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpRange {
+    ipv6: bool,
+    start: u128,
+    step: u128,
+    count: u128,
+}
+
+pub(super) fn to_int(ip: IpAddr) -> (bool, u128) {
+    match ip {
+        IpAddr::V4(v4) => (false, u32::from(v4) as u128),
+        IpAddr::V6(v6) => (true, u128::from(v6)),
+    }
+}
+
+fn from_int(ipv6: bool, value: u128) -> IpAddr {
+    if ipv6 {
+        IpAddr::V6(Ipv6Addr::from(value))
+    } else {
+        IpAddr::V4(Ipv4Addr::from(value as u32))
+    }
+}
+
+impl IpRange {
+    pub fn single(ip: IpAddr) -> Self {
+        let (ipv6, start) = to_int(ip);
+        Self { ipv6, start, step: 1, count: 1 }
+    }
+
+    /// Every address of the network, network and broadcast addresses included.
+    /// An IPv6 /0 is one address short, as its size does not fit in a u128.
+    pub fn from_network(network: IpNetwork) -> Self {
+        let (ipv6, start) = to_int(network.network());
+        let host_bits = if ipv6 { 128 } else { 32 } - network.prefix() as u32;
+        let count = 1u128.checked_shl(host_bits).unwrap_or(u128::MAX);
+        Self { ipv6, start, step: 1, count }
+    }
+
+    /// Addresses from `first` to `last` inclusive, or None if they are of different
+    /// families or `last` comes before `first`.
+    pub fn between(first: IpAddr, last: IpAddr) -> Option<Self> {
+        let (ipv6, start) = to_int(first);
+        let (last_ipv6, end) = to_int(last);
+        if ipv6 != last_ipv6 || end < start {
+            return None;
+        }
+        Some(Self { ipv6, start, step: 1, count: (end - start).saturating_add(1) })
+    }
+
+    pub fn len(&self) -> u128 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        self.ipv6
+    }
+
+    pub fn get(&self, index: u128) -> Option<IpAddr> {
+        (index < self.count).then(|| from_int(self.ipv6, self.start + index * self.step))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        (0..self.count).map(|i| from_int(self.ipv6, self.start + i * self.step))
+    }
+
+    /// The first address as an integer.
+    pub(super) fn start(&self) -> u128 {
+        self.start
+    }
+
+    /// The last address as an integer.
+    pub(super) fn last(&self) -> u128 {
+        self.start + (self.count - 1) * self.step
+    }
+
+    /// Whether every address of the range lies next to the previous one.
+    pub(super) fn is_contiguous(&self) -> bool {
+        self.step == 1 || self.count == 1
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (ipv6, value) = to_int(ip);
+        ipv6 == self.ipv6
+            && value >= self.start
+            && (value - self.start).is_multiple_of(self.step)
+            && (value - self.start) / self.step < self.count
+    }
+
+    /// What is left after removing the addresses from `first` to `last` (same family).
+    fn subtract(&self, first: u128, last: u128) -> Vec<IpRange> {
+        let lo = if first <= self.start { 0 } else { (first - self.start).div_ceil(self.step) };
+        let hi = if last < self.start { return vec![*self] } else { (last - self.start) / self.step };
+        if lo >= self.count || lo > hi {
+            return vec![*self];
+        }
+        let mut left = Vec::new();
+        if lo > 0 {
+            left.push(IpRange { count: lo, ..*self });
+        }
+        if hi + 1 < self.count {
+            left.push(IpRange { start: self.start + (hi + 1) * self.step, count: self.count - hi - 1, ..*self });
+        }
+        left
+    }
+
+    /// What is left after removing the addresses of `other` (same family), worked
+    /// out from both progressions so a stepping pattern is never expanded. The
+    /// addresses of this range that `other` hits are themselves a progression,
+    /// every `period`-th one; what lies between them is kept as whichever is
+    /// fewer: the `period - 1` progressions beside it, or the runs between hits.
+    pub(super) fn without(&self, other: &IpRange) -> Vec<IpRange> {
+        if other.is_contiguous() {
+            return self.subtract(other.start, other.last());
+        }
+        let (a, r, b, s) = (self.start, self.step, other.start, other.step);
+        // Indices of this range within other's span.
+        let lo = if b <= a { 0 } else { (b - a).div_ceil(r) };
+        let hi = match other.last().checked_sub(a) {
+            Some(offset) => (offset / r).min(self.count - 1),
+            None => return vec![*self],
+        };
+        // Index i is hit when a + i*r = b (mod s), that is i = i0 (mod period).
+        let g = gcd(r, s);
+        let gap = if b % s >= a % s { b % s - a % s } else { s - (a % s - b % s) };
+        if lo > hi || !gap.is_multiple_of(g) {
+            return vec![*self];
+        }
+        let period = s / g;
+        let i0 = mul_mod(gap / g, inverse((r / g) % period, period), period);
+        let first_hit = lo + (i0 + period - lo % period) % period;
+        if first_hit > hi {
+            return vec![*self];
+        }
+        let hits = (hi - first_hit) / period + 1;
+        let last_hit = first_hit + (hits - 1) * period;
+
+        let mut left = vec![IpRange { count: first_hit, ..*self }];
+        if period - 1 < hits - 1 {
+            left.extend((1..period).map(|o| IpRange { start: a + (first_hit + o) * r, step: r * period, count: hits - 1, ..*self }));
+        } else {
+            left.extend((0..hits - 1).map(|h| IpRange { start: a + (first_hit + h * period + 1) * r, count: period - 1, ..*self }));
+        }
+        left.push(IpRange { start: a + (last_hit + 1) * r, count: self.count - last_hit - 1, ..*self });
+        left.retain(|range| !range.is_empty());
+        left
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// `a * b % m` without overflowing.
+fn mul_mod(mut a: u128, mut b: u128, m: u128) -> u128 {
+    let mut product = 0u128;
+    a %= m;
+    while b > 0 {
+        if b & 1 == 1 {
+            product = add_mod(product, a, m);
+        }
+        a = add_mod(a, a, m);
+        b >>= 1;
+    }
+    product
+}
+
+fn add_mod(a: u128, b: u128, m: u128) -> u128 {
+    if a >= m - b { a - (m - b) } else { a + b }
+}
+
+/// The inverse of `x` modulo `m`, for `x` and `m` with no common factor.
+fn inverse(x: u128, m: u128) -> u128 {
+    let (mut old_r, mut r) = (x, m);
+    let (mut old_s, mut s) = (1 % m, 0u128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, add_mod(old_s, m - mul_mod(q, s, m), m));
+    }
+    old_s
+}
+
+/// Ports a target file gave a host of its own, instead of the global `-p`.
+pub type OwnPorts = Option<Arc<PortSpec>>;
+
+/// Hostnames an address was resolved from, in input order.
+pub type Names = Option<Arc<[Arc<str>]>>;
+
+/// A range, the hostnames it was resolved from and its own ports.
+pub type TargetEntry = (IpRange, Names, OwnPorts);
+
+/// Scan targets as address ranges, expanded lazily by the scan strategies.
+/// Addresses resolved from hostnames keep their names, and ranges from target file
+/// lines like `10.0.0.0/24:22,80` keep their ports.
+#[derive(Debug, Clone, Default)]
 pub struct TargetList {
-    pub targets: Vec<String>,
+    ranges: Vec<IpRange>,
+    /// Hostnames each range was resolved from, if any.
+    hostnames: Vec<Names>,
+    /// Ports of each range, if they differ from the global list.
+    ports: Vec<OwnPorts>,
+    /// Index of the first address of each range.
+    offsets: Vec<u128>,
+    /// Hostnames (with their ports) not resolved yet; they add no addresses until `with_resolved`.
+    unresolved: Vec<(Arc<str>, OwnPorts)>,
 }
 
 impl std::str::FromStr for TargetList {
@@ -16,31 +231,273 @@ impl std::str::FromStr for TargetList {
     }
 }
 
-impl Into<Vec<String>> for TargetList {
-    fn into(self) -> Vec<String> {
-        self.targets
+/// Collect addresses into ranges, merging consecutive ones.
+impl FromIterator<IpAddr> for TargetList {
+    fn from_iter<I: IntoIterator<Item = IpAddr>>(iter: I) -> Self {
+        TargetList::named(iter.into_iter().map(|ip| (IpRange::single(ip), None, None)).collect())
     }
 }
 
-impl IntoIterator for TargetList {
-    type Item = String;
-    type IntoIter = std::vec::IntoIter<String>;
-    fn into_iter(self) -> Self::IntoIter {
-        self.targets.into_iter()
+impl TargetList {
+    pub fn new(ranges: Vec<IpRange>) -> Self {
+        Self::named(ranges.into_iter().map(|r| (r, None, None)).collect())
     }
-}
 
-impl TargetList {
-    pub fn len(&self) -> usize {
-        self.targets.len()
+    /// Build from entries, merging consecutive unnamed addresses with the same ports.
+    pub fn named(entries: Vec<TargetEntry>) -> Self {
+        let mut list = TargetList::default();
+        let mut next = 0u128;
+        for (range, hostname, ports) in entries {
+            if range.is_empty() {
+                continue;
+            }
+            if hostname.is_none()
+                && range.step == 1
+                && let (Some(last), Some(None), Some(last_ports)) = (list.ranges.last_mut(), list.hostnames.last(), list.ports.last())
+                && *last_ports == ports
+                && last.ipv6 == range.ipv6
+                && last.step == 1
+                && last.start.checked_add(last.count) == Some(range.start)
+            {
+                last.count = last.count.saturating_add(range.count);
+                next = next.saturating_add(range.count);
+                continue;
+            }
+            list.offsets.push(next);
+            next = next.saturating_add(range.count);
+            list.ranges.push(range);
+            list.hostnames.push(hostname);
+            list.ports.push(ports);
+        }
+        list
+    }
+
+    fn entries(&self) -> impl Iterator<Item = TargetEntry> + '_ {
+        self.ranges.iter().zip(&self.hostnames).zip(&self.ports)
+            .map(|((range, hostname), ports)| (*range, hostname.clone(), ports.clone()))
+    }
+
+    /// Build from address strings, skipping any that are not IP addresses.
+    pub fn from_hosts<S: AsRef<str>>(hosts: &[S]) -> Self {
+        hosts.iter().filter_map(|h| h.as_ref().parse::<IpAddr>().ok()).collect()
+    }
+
+    /// Hostnames still to resolve, without duplicates.
+    pub fn unresolved(&self) -> Vec<Arc<str>> {
+        let mut names: Vec<Arc<str>> = Vec::new();
+        for (name, _) in &self.unresolved {
+            if !names.contains(name) {
+                names.push(Arc::clone(name));
+            }
+        }
+        names
+    }
+
+    /// Add the addresses of resolved hostnames (after the other targets, in input order),
+    /// dropping names that did not resolve. An address already listed with the same
+    /// ports, or resolved from several names, is kept once with every name, so each
+    /// of its ports is probed once.
+    pub fn with_resolved(&self, resolved: &HashMap<Arc<str>, Vec<IpAddr>>) -> TargetList {
+        // (address, name, ports) in input order, then sorted for lookups by address.
+        let mut named: Vec<((bool, u128), Arc<str>, OwnPorts)> = Vec::new();
+        for (name, ports) in &self.unresolved {
+            for ip in resolved.get(name).into_iter().flatten() {
+                named.push((to_int(*ip), Arc::clone(name), ports.clone()));
+            }
+        }
+        let mut by_address: Vec<usize> = (0..named.len()).collect();
+        by_address.sort_by_key(|&i| named[i].0);
+        let mut taken = vec![false; named.len()];
+
+        let mut entries = Vec::with_capacity(self.ranges.len());
+        for (range, names, ports) in self.entries() {
+            let last = range.start + (range.count - 1) * range.step;
+            let first = by_address.partition_point(|&i| named[i].0 < (range.ipv6, range.start));
+            // Addresses of this range that names resolved to, each with all its names.
+            let mut split: Vec<(u128, Vec<Arc<str>>)> = Vec::new();
+            for &i in by_address[first..].iter().take_while(|&&i| named[i].0 <= (range.ipv6, last)) {
+                let ((_, value), name, own) = &named[i];
+                if own != &ports || !(value - range.start).is_multiple_of(range.step) {
+                    continue;
+                }
+                taken[i] = true;
+                match split.last_mut() {
+                    Some((v, names)) if v == value => add_name(names, name),
+                    _ => split.push((*value, names.iter().flat_map(|n| n.iter().cloned()).chain([Arc::clone(name)]).collect())),
+                }
+            }
+            if split.is_empty() || range.count == 1 {
+                let names = split.pop().map(|(_, n)| Arc::from(n)).or(names);
+                entries.push((range, names, ports));
+                continue;
+            }
+            let mut left = vec![range];
+            for (value, _) in &split {
+                left = left.into_iter().flat_map(|r| r.subtract(*value, *value)).collect();
+            }
+            entries.extend(left.into_iter().map(|r| (r, names.clone(), ports.clone())));
+            entries.extend(split.into_iter().map(|(value, n)| (IpRange::single(from_int(range.ipv6, value)), Some(Arc::from(n)), ports.clone())));
+        }
+
+        // The rest, once per address and port set.
+        let mut added: Vec<(IpRange, Vec<Arc<str>>, OwnPorts)> = Vec::new();
+        let mut seen: HashMap<(bool, u128), Vec<usize>> = HashMap::new();
+        for (i, (address, name, ports)) in named.into_iter().enumerate() {
+            if taken[i] {
+                continue;
+            }
+            let same = seen.entry(address).or_default();
+            match same.iter().find(|&&j| added[j].2 == ports) {
+                Some(&j) => add_name(&mut added[j].1, &name),
+                None => {
+                    same.push(added.len());
+                    added.push((IpRange::single(from_int(address.0, address.1)), vec![name], ports));
+                }
+            }
+        }
+        entries.extend(added.into_iter().map(|(range, names, ports)| (range, Some(Arc::from(names)), ports)));
+        TargetList::named(entries)
+    }
+
+    /// Only the given addresses, each with every hostname and port set it has here.
+    /// Used to carry names and ports over to the hosts left after ping or a phase.
+    pub fn restrict_to(&self, hosts: &[IpAddr]) -> TargetList {
+        let mut wanted: Vec<(bool, u128)> = hosts.iter().map(|ip| to_int(*ip)).collect();
+        wanted.sort_unstable();
+        wanted.dedup();
+        let mut entries = Vec::new();
+        for (range, hostname, ports) in self.entries() {
+            let last = range.start + (range.count - 1) * range.step;
+            let first = wanted.partition_point(|&w| w < (range.ipv6, range.start));
+            for &(ipv6, value) in wanted[first..].iter().take_while(|&&w| w <= (range.ipv6, last)) {
+                if (value - range.start).is_multiple_of(range.step) {
+                    entries.push((IpRange::single(from_int(ipv6, value)), hostname.clone(), ports.clone()));
+                }
+            }
+        }
+        TargetList::named(entries)
+    }
+
+    /// The targets split by port set, in order of first appearance: `None` for
+    /// hosts that use the global list.
+    pub fn port_groups(&self) -> Vec<(OwnPorts, TargetList)> {
+        let mut groups: Vec<(OwnPorts, Vec<TargetEntry>)> = Vec::new();
+        for entry in self.entries() {
+            match groups.iter_mut().find(|(ports, _)| *ports == entry.2) {
+                Some((_, entries)) => entries.push(entry),
+                None => groups.push((entry.2.clone(), vec![entry])),
+            }
+        }
+        groups.into_iter().map(|(ports, entries)| (ports, TargetList::named(entries))).collect()
+    }
+
+    pub fn ranges(&self) -> &[IpRange] {
+        &self.ranges
+    }
+
+    /// Number of addresses. Lists from `parse_target_input` and those passing
+    /// `check_size` always fit.
+    pub fn len(&self) -> u64 {
+        u64::try_from(self.size()).unwrap_or(u64::MAX)
+    }
+
+    fn size(&self) -> u128 {
+        match (self.offsets.last(), self.ranges.last()) {
+            (Some(offset), Some(range)) => offset.saturating_add(range.count),
+            _ => 0,
+        }
+    }
+
+    /// Refuse more addresses than plans, progress and resume positions can count.
+    pub fn check_size(&self) -> Result<(), String> {
+        if self.size() <= u64::MAX as u128 {
+            return Ok(());
+        }
+        Err(format!(
+            "Targets add up to {} addresses, more than the {} a scan can count: split them into smaller blocks (an IPv6 /64 is one address too many)",
+            if self.size() == u128::MAX { "about 2^128".to_string() } else { self.size().to_string() },
+            u64::MAX,
+        ))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    fn slot(&self, index: u64) -> Option<(usize, u128)> {
+        let index = index as u128;
+        let slot = self.offsets.partition_point(|&offset| offset <= index).checked_sub(1)?;
+        Some((slot, index - self.offsets[slot]))
+    }
+
+    /// The address at `index`, in input order.
+    pub fn get(&self, index: u64) -> Option<IpAddr> {
+        let (slot, offset) = self.slot(index)?;
+        self.ranges[slot].get(offset)
     }
 
-    pub fn vec(&self) -> Vec<String> {
-        self.targets.clone()
+    /// The scan target for the address at `index` and `port`, with its hostnames.
+    pub fn target(&self, index: u64, port: u16) -> Option<Target> {
+        let (slot, offset) = self.slot(index)?;
+        let names = self.hostnames[slot].as_deref().unwrap_or_default();
+        Some(Target {
+            ip: self.ranges[slot].get(offset)?.to_string(),
+            port,
+            hostname: names.first().map(|name| name.to_string()),
+            aliases: names.iter().skip(1).map(|name| name.to_string()).collect(),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.ranges.iter().flat_map(|r| r.iter())
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|r| r.contains(ip))
+    }
+
+    /// Remove every excluded address.
+    pub fn exclude(&self, excluded: &ExclusionSet) -> TargetList {
+        let mut entries = Vec::with_capacity(self.ranges.len());
+        for (range, hostname, ports) in self.entries() {
+            let mut left = vec![range];
+            for &(first, end) in excluded.overlapping(range.ipv6, range.start, range.last()) {
+                left = left.into_iter().flat_map(|r| r.subtract(first, end)).collect();
+            }
+            for stepping in excluded.stepping(range.ipv6, range.start, range.last()) {
+                left = left.into_iter().flat_map(|r| r.without(stepping)).collect();
+            }
+            entries.extend(left.into_iter().map(|r| (r, hostname.clone(), ports.clone())));
+        }
+        TargetList::named(entries)
+    }
+
+    /// Each range as `address` or `first-last (N addresses)`, with the hostname
+    /// it was resolved from, for log messages.
+    pub fn labels(&self) -> Vec<String> {
+        self.entries()
+            .map(|(range, hostname, _)| {
+                let mut label = match (range.get(0), range.get(range.count - 1)) {
+                    (Some(first), Some(last)) if range.count > 1 => format!("{}-{} ({} addresses)", first, last, range.count),
+                    (Some(first), _) => first.to_string(),
+                    _ => String::new(),
+                };
+                if let Some(names) = hostname {
+                    label.push_str(&format!(" from {}", names.join(", ")));
+                }
+                label
+            })
+            .collect()
     }
 }
 
 pub fn read_addresses_from_file(filepath: &str) -> Result<Vec<String>, String> {
+    Ok(read_numbered_lines(filepath)?.into_iter().map(|(_, line)| line).collect())
+}
+
+/// Non-empty lines of a `file:` input with their 1-based line numbers.
+/// `#` starts a comment, on its own line or after an entry.
+pub fn read_numbered_lines(filepath: &str) -> Result<Vec<(usize, String)>, String> {
     let filepath = filepath.trim();
     if filepath.is_empty() {
         return Err("Empty file path for targets specified with 'file:' prefix.".into());
@@ -49,44 +506,443 @@ pub fn read_addresses_from_file(filepath: &str) -> Result<Vec<String>, String> {
     let file = File::open(path).map_err(|e| format!("Failed to open file '{}': {}", filepath, e))?;
     let reader = BufReader::new(file);
     let lines = reader.lines().collect::<Result<Vec<String>, std::io::Error>>().map_err(|e| format!("Failed to read lines from file '{}': {}", filepath, e))?;
-    Ok(lines.into_iter().filter(|line| !line.trim().is_empty()).collect())
+    Ok(lines.into_iter()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or_default().trim().to_string()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect())
 }
 
+/// Parse targets to scan: like `parse_address_set`, refusing more than a scan can count.
 pub fn parse_target_input(s: &str) -> Result<TargetList, String> {
+    let list = parse_address_set(s)?;
+    list.check_size()?;
+    Ok(list)
+}
+
+/// Parse a set of addresses (targets, exclusions or a scope), of any size.
+pub fn parse_address_set(s: &str) -> Result<TargetList, String> {
     let input = s.trim();
-    let addresses: Vec<String> = match input.strip_prefix("file:") {
-        Some(filepath) => read_addresses_from_file(filepath)?,
+    // CIDR blocks and ranges stay ranges; they are expanded while scanning.
+    // Hostnames are resolved later, all at once (see `crate::dns`).
+    let mut entries = Vec::new();
+    let mut unresolved: Vec<(Arc<str>, OwnPorts)> = Vec::new();
+    let mut add = |expanded, ports: OwnPorts| match expanded {
+        Expanded::Ranges(r) => entries.extend(r.into_iter().map(|r| (r, None, ports.clone()))),
+        Expanded::Hostname(name) => {
+            let entry = (name, ports);
+            if !unresolved.contains(&entry) {
+                unresolved.push(entry);
+            }
+        }
+    };
+    match input.strip_prefix("file:") {
+        Some(filepath) => {
+            for (line_number, line) in read_numbered_lines(filepath)? {
+                let at_line = |e| format!("{}:{}: {}", filepath.trim(), line_number, e);
+                let (target, ports) = split_port_spec(&line);
+                let ports = match ports {
+                    Some(spec) => Some(Arc::new(parse_own_ports(spec, target).map_err(at_line)?)),
+                    None => None,
+                };
+                add(expand_target(target).map_err(at_line)?, ports);
+            }
+        }
         None => {
             if input.is_empty() {
                 return Err("No target specified".into());
-            } else {
-                input.split(',').map(|s| s.trim().to_string()).collect()
+            }
+            for target in input.split(',') {
+                add(expand_target(target.trim())?, None);
             }
         }
+    }
+
+    let mut list = TargetList::named(entries);
+    list.unresolved = unresolved;
+    Ok(list)
+}
+
+/// Split a target file line into the target and its own ports: `host:ports`,
+/// `host,ports` (CSV) or `[IPv6]:ports`. An unbracketed IPv6 target can only
+/// take the CSV form.
+fn split_port_spec(line: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = line.strip_prefix('[')
+        && let Some((target, after)) = rest.split_once(']')
+    {
+        let ports = after.strip_prefix(':').or_else(|| after.strip_prefix(','));
+        return (target.trim(), ports.map(str::trim));
+    }
+    let (head, csv) = match line.split_once(',') {
+        Some((head, ports)) => (head.trim(), Some(ports.trim())),
+        None => (line.trim(), None),
     };
+    let ipv6 = head.matches(':').count() >= 2 && expand_target(head).is_ok();
+    if !head.contains(':') || ipv6 {
+        return (head, csv);
+    }
+    match line.split_once(':') {
+        Some((target, ports)) => (target.trim(), Some(ports.trim())),
+        None => (line.trim(), None),
+    }
+}
 
-    // Expand CIDR notation to individual IP addresses
-    let mut expanded_addresses = Vec::new();
-    for addr in addresses {
-        expanded_addresses.extend(expand_target(&addr)?);
+fn parse_own_ports(spec: &str, target: &str) -> Result<PortSpec, String> {
+    if spec.is_empty() {
+        return Err(format!("Empty port list for '{}'", target));
     }
-    
-    Ok(TargetList { targets: expanded_addresses })
-} 
+    spec.parse::<PortSpec>().map_err(|e| format!("Invalid ports for '{}': {}", target, e))
+}
 
-fn expand_target(target: &str) -> Result<Vec<String>, String> {
-    let mut expanded_addresses = Vec::new();
+enum Expanded {
+    Ranges(Vec<IpRange>),
+    Hostname(Arc<str>),
+}
+
+fn expand_target(target: &str) -> Result<Expanded, String> {
+    if target.is_empty() {
+        return Err("Empty target".into());
+    }
     // CIDR notation
     if target.contains('/') {
         let cidr = target.parse::<IpNetwork>().map_err(|e| format!("Invalid CIDR notation '{}': {}", target, e))?;
-        for ip in cidr.iter() {
-            expanded_addresses.push(ip.to_string());
+        return Ok(Expanded::Ranges(vec![IpRange::from_network(cidr)]));
+    }
+    if let Ok(ip) = target.parse::<IpAddr>() {
+        return Ok(Expanded::Ranges(vec![IpRange::single(ip)]));
+    }
+    if target.contains(':') {
+        return parse_ipv6_range(target).map(Expanded::Ranges);
+    }
+    if is_octet_pattern(target) {
+        return parse_octet_pattern(target).map(Expanded::Ranges);
+    }
+    if let Some((first, last)) = target.split_once('-')
+        && let (Ok(first), Ok(last)) = (first.trim().parse::<IpAddr>(), last.trim().parse::<IpAddr>())
+    {
+        return IpRange::between(first, last)
+            .map(|range| Expanded::Ranges(vec![range]))
+            .ok_or_else(|| format!("Invalid range '{}': the end comes before the start", target));
+    }
+    if !is_hostname(target) {
+        return Err(format!("Invalid target '{}': not an address, range, CIDR or hostname", target));
+    }
+    Ok(Expanded::Hostname(Arc::from(target.to_ascii_lowercase())))
+}
+
+fn add_name(names: &mut Vec<Arc<str>>, name: &Arc<str>) {
+    if !names.contains(name) {
+        names.push(Arc::clone(name));
+    }
+}
+
+/// Dot-separated labels of letters, digits, `-` and `_`, 1 to 63 characters each.
+fn is_hostname(target: &str) -> bool {
+    let name = target.strip_suffix('.').unwrap_or(target);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+/// Four dot-separated parts made only of digits, `-` and `*`, like `10.0.1-3.*`.
+fn is_octet_pattern(target: &str) -> bool {
+    let parts: Vec<&str> = target.split('.').collect();
+    parts.len() == 4
+        && parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit() || c == '-' || c == '*'))
+}
+
+fn parse_octet(value: &str, target: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(n) if n <= 255 => Ok(n),
+        _ => Err(format!("Invalid octet '{}' in '{}': must be a number from 0 to 255", value, target)),
+    }
+}
+
+/// Most ranges an octet pattern may take (`1-254.1-254.1-254.1` has 64516).
+const MAX_PATTERN_RANGES: u128 = 1 << 16;
+
+/// nmap octet syntax: each octet is `N`, `N-M`, `N-`, `-M`, `-` or `*`.
+fn parse_octet_pattern(target: &str) -> Result<Vec<IpRange>, String> {
+    let mut octets = [(0u32, 0u32); 4];
+    for (slot, part) in octets.iter_mut().zip(target.split('.')) {
+        *slot = match part {
+            "*" | "-" => (0, 255),
+            _ => match part.split_once('-') {
+                Some((low, high)) => {
+                    let low = if low.is_empty() { 0 } else { parse_octet(low, target)? };
+                    let high = if high.is_empty() { 255 } else { parse_octet(high, target)? };
+                    if low > high {
+                        return Err(format!("Invalid octet range '{}' in '{}': {} is greater than {}", part, target, low, high));
+                    }
+                    (low, high)
+                }
+                None => {
+                    let n = parse_octet(part, target)?;
+                    (n, n)
+                }
+            },
+        };
+    }
+
+    // The axis of each range is the largest run of octets that form one arithmetic
+    // progression: any octet followed by full ones (`*.*.*.1` steps by 256). Every
+    // combination of the other octets starts a range of its own.
+    let size = |(low, high): (u32, u32)| u128::from(high - low + 1);
+    let (mut axis, mut count) = ((3, 3), 0);
+    for last in 0..4 {
+        for first in (0..=last).rev() {
+            let run = octets[first..=last].iter().map(|&o| size(o)).product::<u128>();
+            if run >= count {
+                (axis, count) = ((first, last), run);
+            }
+            if octets[first] != (0, 255) {
+                break;
+            }
         }
+    }
+    let (first, last) = axis;
+    let others: Vec<usize> = (0..4).filter(|i| !(first..=last).contains(i)).collect();
+    let needed = others.iter().map(|&i| size(octets[i])).product::<u128>();
+    if needed > MAX_PATTERN_RANGES {
+        return Err(format!(
+            "Octet pattern '{}' splits into {} ranges (at most {}): vary fewer octets partially, or use a CIDR",
+            target, needed, MAX_PATTERN_RANGES,
+        ));
+    }
+    let shift = |octet: usize| 8 * (3 - octet as u32);
+    let mut starts = vec![u128::from(octets[first].0) << shift(first)];
+    for &octet in &others {
+        let (low, high) = octets[octet];
+        starts = starts.iter().flat_map(|&start| (low..=high).map(move |o| start | u128::from(o) << shift(octet))).collect();
+    }
+    let step = 1u128 << shift(last);
+    Ok(starts.into_iter().map(|start| IpRange { ipv6: false, start, step, count }).collect())
+}
+
+/// IPv6 dash range: `2001:db8::1-2001:db8::ff`, or `2001:db8::1-ff` to vary the last group.
+fn parse_ipv6_range(target: &str) -> Result<Vec<IpRange>, String> {
+    let (first, last) = target.split_once('-')
+        .ok_or_else(|| format!("Invalid IPv6 address '{}'", target))?;
+    let first: Ipv6Addr = first.trim().parse()
+        .map_err(|e| format!("Invalid IPv6 address '{}' in range '{}': {}", first.trim(), target, e))?;
+    let last = last.trim();
+    let last: Ipv6Addr = if last.contains(':') {
+        last.parse().map_err(|e| format!("Invalid IPv6 address '{}' in range '{}': {}", last, target, e))?
     } else {
-        // Lookup host
-        let hosts = lookup_host(target).map_err(|e| format!("Failed to lookup host '{}': {}", target, e))?;
-        expanded_addresses.extend(hosts.iter().map(|host| host.to_string()));
+        let group = u16::from_str_radix(last, 16)
+            .map_err(|_| format!("Invalid IPv6 range end '{}' in '{}': expected an address or a hex group", last, target))?;
+        let mut segments = first.segments();
+        segments[7] = group;
+        Ipv6Addr::from(segments)
+    };
+    IpRange::between(IpAddr::V6(first), IpAddr::V6(last))
+        .map(|range| vec![range])
+        .ok_or_else(|| format!("Invalid range '{}': the end comes before the start", target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_stays_a_lazy_range() {
+        let list = parse_target_input("10.0.0.0/8,2001:db8::/100,192.168.1.1").unwrap();
+        assert_eq!(list.ranges().len(), 3);
+        assert_eq!(list.len(), (1 << 24) + (1 << 28) + 1);
+        assert_eq!(list.get(list.len() - 1), Some(ip("192.168.1.1")));
+        assert_eq!(parse_target_input("2001:db8::/65").unwrap().len(), 1 << 63);
+        let too_many = parse_target_input("2001:db8::/64").unwrap_err();
+        assert!(too_many.contains("18446744073709551616 addresses"), "{}", too_many);
+        assert!(parse_target_input("2001:db8::/65,2001:db9::/65").is_err());
+        assert!(parse_target_input("::/0").unwrap_err().contains("about 2^128"));
+        // Only what is scanned is counted: exclusions and scopes may be any size.
+        assert!(parse_address_set("2001:db8::/32").is_ok());
+        assert_eq!(list.get(0), Some(ip("10.0.0.0")));
+        assert_eq!(list.get(70_000), Some(ip("10.1.17.112")));
+        assert_eq!(list.get(1 << 24), Some(ip("2001:db8::")));
+        assert!(list.contains(ip("10.255.255.255")));
+        assert!(!list.contains(ip("11.0.0.0")));
     }
 
-    Ok(expanded_addresses)
-}
\ No newline at end of file
+    #[test]
+    fn exclude_without_expanding() {
+        let list = parse_target_input("10.0.0.0/24").unwrap();
+        let left = list.exclude(&"10.0.0.0/25,10.0.0.200".parse().unwrap());
+        assert_eq!(left.len(), 127);
+        assert_eq!(left.get(0), Some(ip("10.0.0.128")));
+        assert!(!left.contains(ip("10.0.0.200")));
+        let cut = left.exclude(&ExclusionSet::from(&TargetList::from_hosts(&["10.0.0.131"])));
+        assert_eq!(cut.len(), 126);
+    }
+
+    #[test]
+    fn stepping_exclusions_are_worked_out_not_expanded() {
+        let left = parse_target_input("10.0.0.0/8").unwrap().exclude(&"*.*.*.1".parse().unwrap());
+        assert_eq!((left.ranges().len(), left.len()), (257, (1 << 24) - (1 << 16)));
+        assert!(!left.contains(ip("10.20.30.1")) && left.contains(ip("10.20.30.2")));
+
+        for (targets, excluded) in [
+            ("10.0.0.0/22", "10.0.*.3"),
+            ("10.0.0.0/22", "10.0.1-2.1-3,*.*.*.200"),
+            ("10.0.*.1-9", "10.0.0-255.1"),
+            ("10.0.*.1-9", "10.0.*.*"),
+            ("10.0.0-3.*", "10.0.1.*,*.*.*.7"),
+            ("10.0.0.5-250", "*.*.*.4"),
+        ] {
+            let list = parse_target_input(targets).unwrap();
+            let set: ExclusionSet = excluded.parse().unwrap();
+            let mut left: Vec<IpAddr> = list.exclude(&set).iter().collect();
+            let mut expected: Vec<IpAddr> = list.iter().filter(|a| !set.contains(*a)).collect();
+            left.sort();
+            expected.sort();
+            assert_eq!(left, expected, "{} without {}", targets, excluded);
+        }
+
+        // Any two progressions, not only the octet patterns' powers of two.
+        let range = |start, step, count| IpRange { ipv6: false, start, step, count };
+        for (kept, removed) in [(range(3, 6, 50), range(7, 10, 40)), (range(0, 4, 90), range(2, 6, 30)), (range(5, 3, 7), range(1, 9, 3))] {
+            let mut left: Vec<IpAddr> = kept.without(&removed).iter().flat_map(|r| r.iter().collect::<Vec<_>>()).collect();
+            left.sort();
+            let expected: Vec<IpAddr> = kept.iter().filter(|a| !removed.contains(*a)).collect();
+            assert_eq!(left, expected, "{:?} without {:?}", kept, removed);
+        }
+    }
+
+    #[test]
+    fn collecting_merges_consecutive_addresses() {
+        let list: TargetList = ["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.9"].iter().map(|s| ip(s)).collect();
+        assert_eq!(list.ranges().len(), 2);
+        assert_eq!(list.iter().map(|a| a.to_string()).collect::<Vec<_>>(), ["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.9"]);
+    }
+
+    fn addresses(input: &str) -> Vec<String> {
+        parse_target_input(input).unwrap().iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn octet_patterns_step_instead_of_expanding() {
+        let list = parse_target_input("*.*.*.1").unwrap();
+        assert_eq!((list.ranges().len(), list.len()), (1, 1 << 24));
+        assert_eq!(list.get(1), Some(ip("0.0.1.1")));
+        assert!(list.contains(ip("10.20.30.1")) && !list.contains(ip("10.20.30.2")));
+        let list = parse_target_input("10.*.5.1-3").unwrap();
+        assert_eq!((list.ranges().len(), list.len()), (3, 3 * 256));
+        assert_eq!(list.get(256), Some(ip("10.0.5.2")));
+        let list = parse_target_input("1-254.1-254.*.1").unwrap();
+        assert_eq!((list.ranges().len(), list.len()), (254, 254 * 254 * 256));
+    }
+
+    #[test]
+    fn nmap_octet_and_dash_ranges() {
+        assert_eq!(addresses("10.0.0.1-3"), ["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+        let list = parse_target_input("10.0.1-3.1-254").unwrap();
+        assert_eq!((list.ranges().len(), list.len()), (3, 3 * 254));
+        assert_eq!(list.get(254), Some(ip("10.0.2.1")));
+        let list = parse_target_input("192.168.1.*,10.*.*.*").unwrap();
+        assert_eq!((list.ranges().len(), list.len()), (2, 256 + (1 << 24)));
+        assert_eq!(parse_target_input("10.0.-.7").unwrap().get(255), Some(ip("10.0.255.7")));
+        assert_eq!(addresses("10.0.0.254-10.0.1.1"), ["10.0.0.254", "10.0.0.255", "10.0.1.0", "10.0.1.1"]);
+        assert_eq!(addresses("2001:db8::fe-101"), ["2001:db8::fe", "2001:db8::ff", "2001:db8::100", "2001:db8::101"]);
+        assert_eq!(parse_target_input("2001:db8::1-2001:db8::1:0").unwrap().len(), 65536);
+
+        assert!(parse_target_input("1-254.1-254.1-254.1-254").unwrap_err().contains("splits into 16387064 ranges"));
+        assert!(parse_target_input("10.0.0.300").unwrap_err().contains("Invalid octet '300'"));
+        assert!(parse_target_input("10.0.0.50-10").unwrap_err().contains("50 is greater than 10"));
+        assert!(parse_target_input("2001:db8::9-1").unwrap_err().contains("end comes before the start"));
+    }
+
+    #[test]
+    fn file_errors_name_the_line() {
+        let path = std::env::temp_dir().join(format!("carescanner-targets-{}.txt", std::process::id()));
+        std::fs::write(&path, "# scope
+10.0.0.1
+
+10.0.0.1-300
+").unwrap();
+        let err = parse_target_input(&format!("file:{}", path.display())).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err, format!("{}:4: Invalid octet '300' in '10.0.0.1-300': must be a number from 0 to 255", path.display()));
+    }
+
+    #[test]
+    fn hostnames_stay_with_their_addresses() {
+        let list = parse_target_input("Web.example,10.0.0.1,web.example").unwrap();
+        assert_eq!(list.unresolved(), [Arc::from("web.example")]);
+        assert_eq!(list.len(), 1);
+        assert!(parse_target_input("web example").unwrap_err().contains("Invalid target 'web example'"));
+
+        let resolved = HashMap::from([(Arc::from("web.example"), vec![ip("10.0.0.5"), ip("10.0.0.6")])]);
+        let list = list.with_resolved(&resolved);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.target(0, 80).unwrap().hostname, None);
+        assert_eq!(list.target(2, 80).unwrap().hostname.as_deref(), Some("web.example"));
+
+        let again = list.restrict_to(&[ip("10.0.0.6"), ip("10.0.0.1")]);
+        assert_eq!(again.len(), 2);
+        assert_eq!(again.target(0, 443).unwrap().hostname, None);
+        assert_eq!(again.target(1, 443).unwrap().hostname.as_deref(), Some("web.example"));
+    }
+
+    #[test]
+    fn addresses_are_probed_once_whatever_resolves_to_them() {
+        let list = parse_target_input("10.0.0.0/30,a.example,b.example,c.example").unwrap();
+        let resolved = HashMap::from([
+            (Arc::from("a.example"), vec![ip("10.0.0.2"), ip("10.0.1.1")]),
+            (Arc::from("b.example"), vec![ip("10.0.1.1"), ip("10.0.0.2")]),
+            (Arc::from("c.example"), vec![ip("10.0.1.1")]),
+        ]);
+        let list = list.with_resolved(&resolved);
+        assert_eq!(list.len(), 5);
+        let names = |address: &str| {
+            let index = list.iter().position(|a| a == ip(address)).unwrap() as u64;
+            let target = list.target(index, 443).unwrap();
+            target.hostname.into_iter().chain(target.aliases).collect::<Vec<_>>()
+        };
+        assert_eq!(names("10.0.0.1"), Vec::<String>::new());
+        assert_eq!(names("10.0.0.2"), ["a.example", "b.example"]);
+        assert_eq!(names("10.0.1.1"), ["a.example", "b.example", "c.example"]);
+        assert_eq!(list.restrict_to(&[ip("10.0.0.2")]).target(0, 22).unwrap().aliases, ["b.example"]);
+
+        // Different ports are different targets.
+        let path = std::env::temp_dir().join(format!("carescanner-shared-addresses-{}.txt", std::process::id()));
+        std::fs::write(&path, "10.0.0.0/30:22\nweb.example\n").unwrap();
+        let list = parse_target_input(&format!("file:{}", path.display())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let list = list.with_resolved(&HashMap::from([(Arc::from("web.example"), vec![ip("10.0.0.1")])]));
+        assert_eq!(list.len(), 5);
+    }
+
+    #[test]
+    fn target_files_give_hosts_their_own_ports() {
+        let path = std::env::temp_dir().join(format!("carescanner-port-specs-{}.txt", std::process::id()));
+        std::fs::write(&path, "10.0.0.0/30:22,80\n10.0.1.1,443\n[2001:db8::1]:8443\n2001:db8::2,53\nweb.example:25\n10.0.2.1\n").unwrap();
+        let list = parse_target_input(&format!("file:{}", path.display())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let groups = list.port_groups();
+        let sizes: Vec<(Option<Vec<u16>>, u64)> = groups.iter()
+            .map(|(ports, hosts)| (ports.as_ref().map(|p| p.all.vec()), hosts.len()))
+            .collect();
+        assert_eq!(sizes, [
+            (Some(vec![22, 80]), 4),
+            (Some(vec![443]), 1),
+            (Some(vec![8443]), 1),
+            (Some(vec![53]), 1),
+            (None, 1),
+        ]);
+        assert_eq!(list.unresolved(), [Arc::from("web.example")]);
+        let list = list.with_resolved(&HashMap::from([(Arc::from("web.example"), vec![ip("10.0.3.1")])]));
+        assert_eq!(list.port_groups()[5].0.as_ref().map(|p| p.all.vec()), Some(vec![25]));
+
+        assert_eq!(list.restrict_to(&[ip("10.0.0.2")]).port_groups()[0].0.as_ref().map(|p| p.all.vec()), Some(vec![22, 80]));
+
+        assert_eq!(split_port_spec("2001:db8::1-ff"), ("2001:db8::1-ff", None));
+    }
+}
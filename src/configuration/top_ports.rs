@@ -1,3 +1,5 @@
+use crate::configuration::Protocol;
+
 /// Nmap top 1000 TCP ports, sorted by frequency (most common first).
 /// Source: nmap-services from the Nmap project (https://nmap.org).
 pub const TOP_PORTS: &[u16] = &[
@@ -68,3 +70,121 @@ pub const TOP_PORTS: &[u16] = &[
     7050, 6896, 6732, 6711, 65310, 6520, 6504, 6247, 6203, 61613, 60642, 60146, 60123, 5981, 5940,
     59202, 59201, 59200, 5918, 59110, 5899, 58838,
 ];
+
+/// Nmap top 100 UDP ports, sorted by frequency (most common first).
+/// Source: nmap-services from the Nmap project (https://nmap.org). Nmap ranks
+/// far more UDP ports than this; `--services-file` reads them all.
+pub const TOP_UDP_PORTS: &[u16] = &[
+    631, 161, 137, 123, 138, 1434, 445, 135, 67, 53, 139, 500, 68, 520, 1900, 4500, 514, 49152, 162,
+    69, 5353, 111, 49154, 1701, 998, 996, 997, 999, 3283, 49153, 1812, 136, 2222, 2049, 32768, 5060,
+    1025, 1433, 3456, 80, 20031, 1026, 7, 1646, 1645, 593, 518, 2048, 626, 1027, 177, 1719, 427,
+    497, 4444, 1023, 65024, 19, 9, 49193, 1029, 49, 88, 1028, 17185, 1718, 49186, 2000, 31337,
+    49201, 49192, 515, 2223, 443, 49181, 1813, 120, 158, 49200, 3703, 32815, 17, 5000, 32771, 33281,
+    1030, 1022, 623, 32769, 5632, 10000, 49156, 49182, 49191, 49194, 9200, 30718, 49185, 49188,
+    49190,
+];
+
+/// Ports of each protocol from most to least common, for `--top-ports`, `top:N`
+/// phases, ping discovery and the port-priority strategy. Built in, or read
+/// from an nmap-services file with `--services-file`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortFrequencies {
+    pub tcp: Vec<u16>,
+    pub udp: Vec<u16>,
+}
+
+impl Default for PortFrequencies {
+    fn default() -> Self {
+        Self { tcp: TOP_PORTS.to_vec(), udp: TOP_UDP_PORTS.to_vec() }
+    }
+}
+
+impl PortFrequencies {
+    pub fn ranked(&self, protocol: Protocol) -> &[u16] {
+        match protocol {
+            Protocol::Tcp => &self.tcp,
+            Protocol::Udp => &self.udp,
+        }
+    }
+
+    /// The `n` most common ports of `protocol` (all of them if there are fewer).
+    pub fn top(&self, protocol: Protocol, n: usize) -> &[u16] {
+        let ranked = self.ranked(protocol);
+        &ranked[..n.min(ranked.len())]
+    }
+
+    /// Read an nmap-services file (`name port/proto frequency`, `#` comments).
+    /// Ports without a frequency are left out; a protocol with none keeps the
+    /// built-in table.
+    pub fn from_nmap_services(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read services file '{}': {}", path, e))?;
+        Self::parse_nmap_services(&content).map_err(|e| format!("{}:{}", path, e))
+    }
+
+    fn parse_nmap_services(content: &str) -> Result<Self, String> {
+        let mut tcp: Vec<(f64, u16)> = Vec::new();
+        let mut udp: Vec<(f64, u16)> = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let fields: Vec<&str> = line.split('#').next().unwrap_or_default().split_whitespace().collect();
+            let (port_proto, frequency) = match fields.as_slice() {
+                [] => continue,
+                [_, port_proto, frequency, ..] => (*port_proto, *frequency),
+                _ => continue,
+            };
+            let parse_error = || format!("{}: expected 'name port/protocol frequency', got '{}'", number + 1, line.trim());
+            let (port, proto) = port_proto.split_once('/').ok_or_else(parse_error)?;
+            let port: u16 = port.parse().map_err(|_| parse_error())?;
+            let frequency: f64 = frequency.parse().map_err(|_| parse_error())?;
+            match proto {
+                "tcp" => tcp.push((frequency, port)),
+                "udp" => udp.push((frequency, port)),
+                _ => {}
+            }
+        }
+        let rank = |mut entries: Vec<(f64, u16)>, builtin: &[u16]| {
+            if entries.is_empty() {
+                return builtin.to_vec();
+            }
+            // Stable, so equally common ports keep the file's order.
+            entries.sort_by(|a, b| b.0.total_cmp(&a.0));
+            let mut seen = vec![false; 65_536];
+            entries.into_iter()
+                .map(|(_, port)| port)
+                .filter(|port| !std::mem::replace(&mut seen[*port as usize], true))
+                .collect()
+        };
+        Ok(Self { tcp: rank(tcp, TOP_PORTS), udp: rank(udp, TOP_UDP_PORTS) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_per_protocol() {
+        let builtin = PortFrequencies::default();
+        assert_eq!(builtin.top(Protocol::Tcp, 3), [80, 23, 443]);
+        assert_eq!(builtin.top(Protocol::Udp, 3), [631, 161, 137]);
+        assert_eq!(builtin.top(Protocol::Udp, 25), [631, 161, 137, 123, 138, 1434, 445, 135, 67, 53, 139, 500, 68, 520, 1900, 4500, 514, 49152, 162, 69, 5353, 111, 49154, 1701, 998]);
+        assert_eq!(builtin.top(Protocol::Udp, 5000).len(), TOP_UDP_PORTS.len());
+    }
+
+    #[test]
+    fn reads_nmap_services() {
+        let file = "# Fields: name port/proto freq\n\
+            http\t80/tcp\t0.484143\t# World Wide Web HTTP\n\
+            ssh\t22/tcp\t0.182286\n\
+            snmp\t161/udp\t0.433467\n\
+            ntp\t123/udp\t0.330879\n\
+            https\t443/tcp\t0.208669\n";
+        let frequencies = PortFrequencies::parse_nmap_services(file).unwrap();
+        assert_eq!(frequencies.tcp, [80, 443, 22]);
+        assert_eq!(frequencies.udp, [161, 123]);
+
+        let tcp_only = PortFrequencies::parse_nmap_services("http 80/tcp 0.5\n").unwrap();
+        assert_eq!(tcp_only.udp, TOP_UDP_PORTS);
+        assert!(PortFrequencies::parse_nmap_services("http 80/tcp often\n").unwrap_err().starts_with("1: expected"));
+    }
+}
@@ -0,0 +1,121 @@
+//! Coordinator/worker scanning. `--coordinator ADDR` splits the plan of each
+//! phase into chunks of targets and hands them out over TCP to
+//! `carescanner worker` processes, one JSON message per line. Workers stream
+//! every probe result back as it comes in, and the chunks of a worker that
+//! disconnects or goes quiet are handed to the others. The scan's rate is
+//! split evenly among the workers connected. With `--coordinator-token`,
+//! only workers giving the same token are let in, and a worker's results only
+//! count for targets of the chunks it holds.
+
+pub mod coordinator;
+pub mod worker;
+
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::configuration::Protocol;
+use crate::modes::{PortStatus, Target};
+
+/// Workers send a heartbeat this often, so a quiet one is known to be alive.
+pub const HEARTBEAT: Duration = Duration::from_secs(5);
+/// A worker that sends nothing for this long is dropped and its chunks re-queued.
+pub const WORKER_TIMEOUT: Duration = Duration::from_secs(30);
+/// Chunks a worker holds at once, so it never waits for the next one.
+pub const CHUNKS_IN_FLIGHT: usize = 2;
+
+/// Coordinator and workers must speak the same protocol.
+const PROTOCOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A target of a chunk, the protocol its scan types are limited to, and whether
+/// it is the last its host gets in the plan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkTarget {
+    #[serde(flatten)]
+    pub target: Target,
+    pub protocol: Option<Protocol>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub last: bool,
+}
+
+/// The shared secret workers give in their hello (`--coordinator-token`).
+/// Kept out of `Debug` output so it never reaches logs.
+#[derive(Clone, PartialEq)]
+pub struct CoordinatorToken(String);
+
+impl std::str::FromStr for CoordinatorToken {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("the coordinator token can't be empty".to_string());
+        }
+        Ok(CoordinatorToken(s.to_string()))
+    }
+}
+
+impl std::fmt::Debug for CoordinatorToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CoordinatorToken(***)")
+    }
+}
+
+impl CoordinatorToken {
+    /// Whether a worker offered this token, comparing every byte so the time
+    /// taken doesn't tell how much of a guess was right.
+    fn accepts(&self, offered: Option<&str>) -> bool {
+        let (expected, offered) = (self.0.as_bytes(), offered.unwrap_or_default().as_bytes());
+        expected.len() == offered.len() && expected.iter().zip(offered).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+/// Messages from a worker to the coordinator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorkerMessage {
+    Hello {
+        name: String,
+        version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    /// One probe of a chunk's target.
+    Result {
+        #[serde(flatten)]
+        target: Target,
+        protocol: String,
+        status: PortStatus,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        banner: Option<String>,
+    },
+    /// A host given up on after only timeouts.
+    Down { host: String },
+    /// Every target of the chunk starting at `start` of `phase` is scanned.
+    Done { phase: usize, start: u64 },
+    Heartbeat,
+}
+
+/// Messages from the coordinator to a worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoordinatorMessage {
+    /// The options to probe with, as command-line arguments, and the worker's
+    /// share of the scan's rate.
+    Settings { args: Vec<String>, rate: u64 },
+    /// A new share of the rate, as workers come and go or the deadline moves it.
+    Rate { rate: u64 },
+    Chunk { phase: usize, start: u64, targets: Vec<ChunkTarget> },
+    /// No work is left: finish the chunks held and leave.
+    Finished,
+}
+
+/// Write `message` as one line.
+async fn send<W: AsyncWrite + Unpin>(out: &mut W, message: &impl Serialize) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(message).map_err(std::io::Error::other)?;
+    line.push('\n');
+    out.write_all(line.as_bytes()).await
+}
+
+fn parse<M: DeserializeOwned>(line: &str) -> Result<M, String> {
+    serde_json::from_str(line).map_err(|e| format!("unexpected message '{}': {}", line, e))
+}
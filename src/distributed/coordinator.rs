@@ -0,0 +1,634 @@
+// Coordinator side: hands out chunks of each phase's plan and folds what the
+// workers send back into the scan's AppState, stats and progress bar.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use log::{debug, warn};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use super::{send, parse, ChunkTarget, CoordinatorMessage, CoordinatorToken, WorkerMessage, CHUNKS_IN_FLIGHT, PROTOCOL_VERSION, WORKER_TIMEOUT};
+use crate::appstate::{AppStateManager, ScanStats};
+use crate::configuration::Config;
+use crate::modes::{PortStatus, ScanResult, ScanType};
+use crate::signal_handler::PauseController;
+use crate::strategy::{ScanPlan, Strategy};
+use crate::ui::TermHandle;
+use crate::{may_probe, open_message, probes_per_target, Limits, ScanContext};
+
+/// Targets `start..end` of a phase's plan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Chunk {
+    start: u64,
+    end: u64,
+}
+
+/// A chunk a worker holds, and the targets it was sent: the only ones it may
+/// report results for.
+struct Held {
+    phase: usize,
+    chunk: Chunk,
+    targets: HashSet<(String, u16)>,
+}
+
+/// The phase being handed out.
+struct Phase {
+    index: usize,
+    plan: Arc<ScanPlan>,
+    track_responses: bool,
+    /// Start of the first chunk never handed out.
+    next: u64,
+    /// Chunks of workers that left, handed out again before new ones.
+    requeued: VecDeque<Chunk>,
+    /// Chunks handed out and not done yet, by start.
+    in_flight: HashMap<u64, Chunk>,
+}
+
+impl Phase {
+    fn take(&mut self, chunk_size: u64) -> Option<Chunk> {
+        let end = self.plan.len();
+        let chunk = self.requeued.pop_front().or_else(|| {
+            (self.next < end).then(|| {
+                let chunk = Chunk { start: self.next, end: self.next.saturating_add(chunk_size).min(end) };
+                self.next = chunk.end;
+                chunk
+            })
+        })?;
+        self.in_flight.insert(chunk.start, chunk);
+        Some(chunk)
+    }
+
+    fn is_done(&self) -> bool {
+        self.next >= self.plan.len() && self.requeued.is_empty() && self.in_flight.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct Work {
+    phase: Option<Phase>,
+    /// No phases are left: workers are told to leave.
+    finished: bool,
+    workers: usize,
+}
+
+struct Shared {
+    config: Arc<Config>,
+    strategy: Arc<Strategy>,
+    scanner: Arc<Vec<ScanType>>,
+    stats: Arc<ScanStats>,
+    app_state_manager: Arc<AppStateManager>,
+    term_handle: TermHandle,
+    pause_controller: PauseController,
+    limits: Arc<Limits>,
+    /// A worker that sends nothing for this long is dropped.
+    worker_timeout: Duration,
+    work: StdMutex<Work>,
+    /// Signalled whenever chunks are done or re-queued, phases start or end,
+    /// and workers come or go.
+    changed: Notify,
+}
+
+/// Hands the phases of a scan to the workers connecting to its listener.
+pub(crate) struct Coordinator {
+    shared: Arc<Shared>,
+    accept: JoinHandle<()>,
+}
+
+impl Coordinator {
+    pub(crate) fn start(listener: TcpListener, ctx: &ScanContext) -> Self {
+        Self::with_worker_timeout(listener, ctx, WORKER_TIMEOUT)
+    }
+
+    fn with_worker_timeout(listener: TcpListener, ctx: &ScanContext, worker_timeout: Duration) -> Self {
+        let shared = Arc::new(Shared {
+            config: Arc::clone(&ctx.config),
+            strategy: Arc::clone(&ctx.strategy),
+            scanner: Arc::clone(&ctx.scanner),
+            stats: Arc::clone(&ctx.stats),
+            app_state_manager: Arc::clone(&ctx.app_state_manager),
+            term_handle: ctx.term_handle.clone(),
+            pause_controller: ctx.pause_controller.clone(),
+            limits: Arc::clone(&ctx.limits),
+            worker_timeout,
+            work: StdMutex::new(Work::default()),
+            changed: Notify::new(),
+        });
+        let accept = {
+            let shared = Arc::clone(&shared);
+            tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, addr)) => {
+                            tokio::spawn(Arc::clone(&shared).serve(stream, addr));
+                        }
+                        Err(e) => warn!("Failed to accept a worker: {}", e),
+                    }
+                }
+            })
+        };
+        Self { shared, accept }
+    }
+
+    /// Hand out `plan` from target `skip` on until every chunk is done. Returns
+    /// true if the deadline stopped the phase.
+    pub(crate) async fn run_phase(
+        &self,
+        index: usize,
+        plan: ScanPlan,
+        skip: u64,
+        track_responses: bool,
+        deadline: Option<Instant>,
+    ) -> bool {
+        let shared = &self.shared;
+        {
+            let mut work = shared.work.lock().unwrap();
+            if work.workers == 0 {
+                shared.term_handle.message("Waiting for workers to connect".to_string());
+            }
+            work.phase = Some(Phase {
+                index,
+                plan: Arc::new(plan),
+                track_responses,
+                next: skip,
+                requeued: VecDeque::new(),
+                in_flight: HashMap::new(),
+            });
+        }
+        shared.changed.notify_waiters();
+
+        let done = async {
+            loop {
+                let changed = shared.changed.notified();
+                tokio::pin!(changed);
+                changed.as_mut().enable();
+                let done = shared.work.lock().unwrap().phase.as_ref().is_some_and(Phase::is_done);
+                if done || shared.pause_controller.should_exit() {
+                    return;
+                }
+                // Exit requests come without a notification.
+                tokio::select! {
+                    _ = changed => {}
+                    _ = tokio::time::sleep(Duration::from_millis(200)) => {}
+                }
+            }
+        };
+        let deadline_hit = match deadline {
+            Some(deadline) => tokio::select! {
+                _ = done => false,
+                _ = tokio::time::sleep_until(deadline.into()) => true,
+            },
+            None => {
+                done.await;
+                false
+            }
+        };
+        shared.work.lock().unwrap().phase = None;
+        shared.changed.notify_waiters();
+        deadline_hit
+    }
+
+    /// Tell the workers there is nothing left, giving them a moment to hear it.
+    pub(crate) async fn finish(&self) {
+        let shared = &self.shared;
+        shared.work.lock().unwrap().finished = true;
+        shared.changed.notify_waiters();
+        let gone = async {
+            loop {
+                let changed = shared.changed.notified();
+                tokio::pin!(changed);
+                changed.as_mut().enable();
+                if shared.work.lock().unwrap().workers == 0 {
+                    return;
+                }
+                changed.await;
+            }
+        };
+        let _ = tokio::time::timeout(Duration::from_secs(2), gone).await;
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        self.accept.abort();
+    }
+}
+
+impl Shared {
+    /// One worker's connection, from its hello until it leaves. Chunks it still
+    /// holds then go back to the queue.
+    async fn serve(self: Arc<Self>, stream: TcpStream, addr: SocketAddr) {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let name = match hello(&mut lines, self.config.coordinator_token.as_ref()).await {
+            Ok(name) => format!("{} ({})", name, addr),
+            Err(e) => {
+                warn!("Rejected worker {}: {}", addr, e);
+                return;
+            }
+        };
+        let rate = self.rate_share(1);
+        let settings = CoordinatorMessage::Settings { args: self.config.worker_args.clone(), rate };
+        if send(&mut write, &settings).await.is_err() {
+            return;
+        }
+        self.work.lock().unwrap().workers += 1;
+        // The others' shares shrink.
+        self.changed.notify_waiters();
+        self.term_handle.message(format!("Worker {} joined", name));
+
+        let mut held = Vec::new();
+        let lost = self.hand_out(&mut lines, &mut write, &mut held, rate).await;
+
+        let mut requeued = 0;
+        {
+            let mut work = self.work.lock().unwrap();
+            work.workers -= 1;
+            if let Some(phase) = &mut work.phase {
+                for Held { phase: index, chunk, .. } in held {
+                    if index == phase.index && phase.in_flight.remove(&chunk.start).is_some() {
+                        phase.requeued.push_back(chunk);
+                        requeued += 1;
+                    }
+                }
+            }
+        }
+        self.changed.notify_waiters();
+        if let Err(reason) = lost {
+            self.term_handle.message(format!("Worker {} left ({}), {} chunks re-queued", name, reason, requeued));
+        }
+    }
+
+    /// A worker's share of the scan's rate, counting `joining` workers not yet
+    /// among those connected.
+    fn rate_share(&self, joining: usize) -> u64 {
+        let workers = self.work.lock().unwrap().workers + joining;
+        (self.limits.rate() / workers.max(1) as u64).max(1)
+    }
+
+    /// Keep the worker supplied with chunks and its share of the rate, and take
+    /// in what it sends, until the scan is finished (Ok) or the worker is lost
+    /// (Err with the reason).
+    async fn hand_out(
+        &self,
+        lines: &mut Lines<BufReader<OwnedReadHalf>>,
+        write: &mut OwnedWriteHalf,
+        held: &mut Vec<Held>,
+        mut rate: u64,
+    ) -> Result<(), String> {
+        let mut heard = tokio::time::Instant::now();
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let share = self.rate_share(0);
+            if share != rate {
+                send(write, &CoordinatorMessage::Rate { rate: share }).await.map_err(|e| e.to_string())?;
+                rate = share;
+            }
+            let (chunks, finished) = self.assign(held);
+            for (phase, chunk, plan) in chunks {
+                let targets = self.chunk_targets(&plan, chunk);
+                if let Some(held) = held.iter_mut().find(|h| h.phase == phase && h.chunk == chunk) {
+                    held.targets = targets.iter().map(|t| (t.target.ip.clone(), t.target.port)).collect();
+                }
+                send(write, &CoordinatorMessage::Chunk { phase, start: chunk.start, targets }).await.map_err(|e| e.to_string())?;
+            }
+            if finished && held.is_empty() {
+                let _ = send(write, &CoordinatorMessage::Finished).await;
+                return Ok(());
+            }
+
+            // Timed from the last message, so other workers' progress doesn't keep a hung one.
+            tokio::select! {
+                line = tokio::time::timeout_at(heard + self.worker_timeout, lines.next_line()) => match line {
+                    Err(_) => return Err(format!("silent for {}s", self.worker_timeout.as_secs_f64())),
+                    Ok(Ok(Some(line))) => {
+                        heard = tokio::time::Instant::now();
+                        self.receive(parse(&line)?, held).await?;
+                    }
+                    Ok(Ok(None)) => return Err("disconnected".to_string()),
+                    Ok(Err(e)) => return Err(e.to_string()),
+                },
+                _ = changed => {}
+                // The deadline moves the rate without a notification.
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            }
+        }
+    }
+
+    /// Top the worker up to `CHUNKS_IN_FLIGHT` chunks of the current phase
+    /// (none while paused), and whether the scan is finished.
+    fn assign(&self, held: &mut Vec<Held>) -> (Vec<(usize, Chunk, Arc<ScanPlan>)>, bool) {
+        let mut work = self.work.lock().unwrap();
+        let finished = work.finished;
+        let mut chunks = Vec::new();
+        match &mut work.phase {
+            Some(phase) => {
+                // Chunks of an earlier phase are no longer expected back.
+                held.retain(|h| h.phase == phase.index && phase.in_flight.contains_key(&h.chunk.start));
+                while !self.pause_controller.is_paused()
+                    && held.len() < CHUNKS_IN_FLIGHT
+                    && let Some(chunk) = phase.take(self.config.chunk_size)
+                {
+                    held.push(Held { phase: phase.index, chunk, targets: HashSet::new() });
+                    chunks.push((phase.index, chunk, Arc::clone(&phase.plan)));
+                }
+            }
+            None => held.clear(),
+        }
+        (chunks, finished)
+    }
+
+    /// The targets of `chunk`, without those the scope or exclusions refuse.
+    fn chunk_targets(&self, plan: &ScanPlan, chunk: Chunk) -> Vec<ChunkTarget> {
+        plan.targets_from(&*self.strategy, chunk.start)
+            .take_while(|(index, _, _)| *index < chunk.end)
+            .filter(|(_, target, _)| may_probe(&self.config, target))
+            .map(|(index, target, protocol)| ChunkTarget { last: plan.last_for_host(&*self.strategy, index), target, protocol })
+            .collect()
+    }
+
+    /// Take in a worker's message. Results and down hosts only count for the
+    /// targets of chunks it holds, so a worker can't report on anything else.
+    async fn receive(&self, message: WorkerMessage, held: &mut Vec<Held>) -> Result<(), String> {
+        match message {
+            WorkerMessage::Result { target, .. } if !held.iter().any(|h| h.targets.contains(&(target.ip.clone(), target.port))) => {
+                debug!("Dropped a result for {}, which the worker does not hold", target.socket_addr());
+            }
+            WorkerMessage::Down { host } if !held.iter().any(|h| h.targets.iter().any(|(ip, _)| *ip == host)) => {
+                debug!("Dropped a down report for {}, which the worker does not hold", host);
+            }
+            WorkerMessage::Result { target, protocol, status, banner } => {
+                if status == PortStatus::Open && !self.config.quiet {
+                    self.term_handle.message(open_message(&target, &protocol, banner.as_deref()));
+                }
+                let track_responses = self.work.lock().unwrap().phase.as_ref().is_some_and(|p| p.track_responses);
+                if track_responses && status != PortStatus::Filtered {
+                    self.stats.host_responded(&target.ip);
+                }
+                // Recorded before the chunk's done message is read, so the report has it.
+                self.app_state_manager.add_result(target, ScanResult { status, banner, rtt: None }, protocol).await;
+            }
+            WorkerMessage::Down { host } => {
+                if !self.config.quiet {
+                    self.term_handle.message(format!("Down: {} (no replies), a worker skips its remaining ports", host));
+                }
+                self.app_state_manager.mark_host_down(host).await;
+            }
+            WorkerMessage::Done { phase, start } => {
+                held.retain(|h| !(h.phase == phase && h.chunk.start == start));
+                self.complete(phase, start);
+                self.changed.notify_waiters();
+            }
+            WorkerMessage::Heartbeat => {}
+            WorkerMessage::Hello { .. } => return Err("sent a second hello".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Count a finished chunk towards progress and the resume position.
+    fn complete(&self, phase: usize, start: u64) {
+        let mut work = self.work.lock().unwrap();
+        let Some(current) = work.phase.as_mut().filter(|p| p.index == phase) else {
+            return;
+        };
+        let Some(chunk) = current.in_flight.remove(&start) else {
+            return;
+        };
+        let per_target = |p| probes_per_target(&self.scanner, p);
+        let probes = current.plan.probes_from(chunk.start, per_target) - current.plan.probes_from(chunk.end, per_target);
+        for index in chunk.start..chunk.end {
+            self.stats.complete_target(index);
+        }
+        self.stats.probes_done.fetch_add(probes, Ordering::Relaxed);
+        self.term_handle.inc(probes);
+    }
+}
+
+/// The name a connecting worker gives, if it speaks our protocol and has the token.
+async fn hello(lines: &mut Lines<BufReader<OwnedReadHalf>>, expected: Option<&CoordinatorToken>) -> Result<String, String> {
+    let line = tokio::time::timeout(WORKER_TIMEOUT, lines.next_line()).await
+        .map_err(|_| "no hello".to_string())?
+        .map_err(|e| e.to_string())?
+        .ok_or("disconnected")?;
+    match parse(&line)? {
+        WorkerMessage::Hello { version, .. } if version != PROTOCOL_VERSION => Err(format!("version {}, this coordinator is {}", version, PROTOCOL_VERSION)),
+        WorkerMessage::Hello { token, .. } if expected.is_some_and(|expected| !expected.accepts(token.as_deref())) => {
+            Err("wrong or missing --coordinator-token".to_string())
+        }
+        WorkerMessage::Hello { name, .. } => Ok(name),
+        _ => Err("expected a hello".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::appstate::PortState;
+    use crate::configuration::{LoggingLevel, PortList, Protocol, TargetList};
+    use crate::modes::Target;
+    use crate::distributed::worker::{run_worker, WorkerArgs};
+    use crate::strategy::PlanGroup;
+    use crate::Limits;
+
+    fn context() -> ScanContext {
+        context_with(&[])
+    }
+
+    fn context_with(options: &[&str]) -> ScanContext {
+        let args = ["carescanner", "-t", "127.0.0.1", "--chunk-size", "1"].iter().chain(options);
+        let config = Arc::new(Config::parse_from(args));
+        ScanContext {
+            strategy: Arc::new(Strategy::build(&config.scan_strategy, None, &config.port_frequencies)),
+            scanner: Arc::new(config.scan_type.iter().cloned().map(|s| ScanType::build(s, &config)).collect()),
+            limits: Arc::new(Limits::new(&config, 1000)),
+            stats: Arc::new(ScanStats::default()),
+            app_state_manager: Arc::new(AppStateManager::new()),
+            pause_controller: PauseController::new(),
+            term_handle: TermHandle::detached(),
+            config,
+        }
+    }
+
+    /// A hand-driven worker, connected and introduced.
+    async fn join(address: &str, name: &str) -> (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf) {
+        join_with_token(address, name, None).await
+    }
+
+    async fn join_with_token(address: &str, name: &str, token: Option<&str>) -> (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf) {
+        let (read, mut write) = TcpStream::connect(address).await.unwrap().into_split();
+        let hello = WorkerMessage::Hello { name: name.to_string(), version: PROTOCOL_VERSION.to_string(), token: token.map(str::to_string) };
+        send(&mut write, &hello).await.unwrap();
+        (BufReader::new(read).lines(), write)
+    }
+
+    async fn next_message(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> CoordinatorMessage {
+        let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line()).await.unwrap().unwrap().unwrap();
+        parse(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn hung_workers_are_dropped_while_others_are_busy() {
+        let open = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ports: Vec<u16> = vec![open.local_addr().unwrap().port(); 40];
+        let ctx = context();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = Coordinator::with_worker_timeout(listener, &ctx, Duration::from_millis(1500));
+        let plan = ScanPlan::new(vec![PlanGroup {
+            hosts: TargetList::from_hosts(&["127.0.0.1"]),
+            ports: PortList { ports },
+            protocol: Some(Protocol::Tcp),
+        }]);
+        ctx.stats.start_phase(0, None, 0, 0);
+        // Slow enough that the busy worker keeps the others' notifications coming past the timeout.
+        ctx.limits.set_rate(10);
+
+        let mut busy = None;
+        let mut hung = None;
+        let workers = async {
+            // Takes its chunks, then neither answers nor disconnects.
+            let (mut lines, write) = join(&address, "hung").await;
+            for _ in 0..3 {
+                next_message(&mut lines).await;
+            }
+            hung = Some((lines, write));
+            let args = WorkerArgs { coordinator: address.clone(), coordinator_token: None, name: None, logging_level: LoggingLevel::Off };
+            busy = Some(tokio::spawn(run_worker(args)));
+        };
+        let phase = tokio::time::timeout(Duration::from_secs(20), coordinator.run_phase(0, plan, 0, true, None));
+        let (deadline_hit, _) = tokio::join!(phase, workers);
+        assert!(!deadline_hit.expect("the hung worker's chunks were never re-queued"));
+        coordinator.finish().await;
+        assert_eq!(busy.unwrap().await.unwrap(), Ok(()));
+        drop(hung);
+        assert_eq!(ctx.stats.resume_point().position, 40);
+    }
+
+    #[tokio::test]
+    async fn workers_share_the_rate() {
+        let ctx = context();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let _coordinator = Coordinator::start(listener, &ctx);
+        let rate = |message| match message {
+            CoordinatorMessage::Settings { rate, .. } | CoordinatorMessage::Rate { rate } => rate,
+            other => panic!("expected a rate, got {:?}", other),
+        };
+
+        let (mut first, _first_write) = join(&address, "first").await;
+        assert_eq!(rate(next_message(&mut first).await), 1000);
+        let (mut second, second_write) = join(&address, "second").await;
+        assert_eq!(rate(next_message(&mut second).await), 500);
+        assert_eq!(rate(next_message(&mut first).await), 500);
+
+        drop((second, second_write));
+        assert_eq!(rate(next_message(&mut first).await), 1000);
+        // As the deadline pacing would.
+        ctx.limits.set_rate(3000);
+        assert_eq!(rate(next_message(&mut first).await), 3000);
+    }
+
+    #[tokio::test]
+    async fn chunks_of_lost_workers_go_to_the_others() {
+        // Two open ports and a closed one on loopback.
+        let open = [TcpListener::bind("127.0.0.1:0").await.unwrap(), TcpListener::bind("127.0.0.1:0").await.unwrap()];
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let mut ports: Vec<u16> = open.iter().map(|l| l.local_addr().unwrap().port()).collect();
+        ports.push(closed);
+
+        let ctx = context();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = Coordinator::start(listener, &ctx);
+        let plan = ScanPlan::new(vec![PlanGroup {
+            hosts: TargetList::from_hosts(&["127.0.0.1"]),
+            ports: PortList { ports },
+            protocol: Some(Protocol::Tcp),
+        }]);
+        ctx.stats.start_phase(0, None, 0, 0);
+
+        let mut steady = None;
+        let workers = async {
+            // Takes two chunks and vanishes without scanning them.
+            let (mut lines, write) = join(&address, "flaky").await;
+            for _ in 0..3 {
+                lines.next_line().await.unwrap().unwrap();
+            }
+            drop((lines, write));
+            let args = WorkerArgs { coordinator: address.clone(), coordinator_token: None, name: None, logging_level: LoggingLevel::Off };
+            steady = Some(tokio::spawn(run_worker(args)));
+        };
+        let phase = tokio::time::timeout(Duration::from_secs(20), coordinator.run_phase(0, plan, 0, true, None));
+        let (deadline_hit, _) = tokio::join!(phase, workers);
+        assert!(!deadline_hit.unwrap());
+        coordinator.finish().await;
+        assert_eq!(steady.unwrap().await.unwrap(), Ok(()));
+
+        assert_eq!(ctx.stats.resume_point().position, 3);
+        assert_eq!(ctx.stats.responded_hosts(), ["127.0.0.1"]);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let state = ctx.app_state_manager.get_current_state().await;
+        let mut found: Vec<(u16, PortState)> = state.get_results()["127.0.0.1"].iter().map(|p| (p.number, p.state.clone())).collect();
+        found.sort_by_key(|(port, _)| *port != closed);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0], (closed, PortState::Closed));
+        assert!(found[1..].iter().all(|(_, state)| *state == PortState::Open));
+    }
+
+    #[tokio::test]
+    async fn workers_need_the_token_and_only_report_their_own_targets() {
+        let ctx = context_with(&["--coordinator-token", "s3cret"]);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = Coordinator::start(listener, &ctx);
+        for token in [None, Some("guess")] {
+            let (mut lines, _write) = join_with_token(&address, "intruder", token).await;
+            let closed = tokio::time::timeout(Duration::from_secs(5), lines.next_line()).await.unwrap();
+            assert!(matches!(closed, Ok(None)), "let in with {:?}", token);
+        }
+
+        let plan = ScanPlan::new(vec![PlanGroup {
+            hosts: TargetList::from_hosts(&["127.0.0.1"]),
+            ports: PortList { ports: vec![7] },
+            protocol: Some(Protocol::Tcp),
+        }]);
+        ctx.stats.start_phase(0, None, 0, 0);
+        let worker = async {
+            let (mut lines, mut write) = join_with_token(&address, "trusted", Some("s3cret")).await;
+            assert!(matches!(next_message(&mut lines).await, CoordinatorMessage::Settings { .. }));
+            let CoordinatorMessage::Chunk { phase, start, targets } = next_message(&mut lines).await else {
+                panic!("expected a chunk");
+            };
+            let result = |ip: &str, port| WorkerMessage::Result {
+                target: Target { ip: ip.to_string(), port, hostname: None, aliases: Vec::new() },
+                protocol: "tcp".to_string(),
+                status: PortStatus::Open,
+                banner: None,
+            };
+            assert_eq!(targets[0].target.socket_addr(), "127.0.0.1:7");
+            for message in [result("10.9.9.9", 22), result("127.0.0.1", 8), WorkerMessage::Down { host: "10.9.9.9".to_string() },
+                result("127.0.0.1", 7), WorkerMessage::Done { phase, start }]
+            {
+                send(&mut write, &message).await.unwrap();
+            }
+            (lines, write)
+        };
+        let phase = tokio::time::timeout(Duration::from_secs(10), coordinator.run_phase(0, plan, 0, false, None));
+        let (deadline_hit, _connection) = tokio::join!(phase, worker);
+        assert!(!deadline_hit.unwrap());
+
+        let state = ctx.app_state_manager.get_current_state().await;
+        let reported: Vec<(&String, Vec<u16>)> = state.get_results().iter().map(|(ip, ports)| (ip, ports.iter().map(|p| p.number).collect())).collect();
+        assert_eq!(reported, [(&"127.0.0.1".to_string(), vec![7])]);
+        assert!(state.down_hosts().is_empty());
+    }
+}
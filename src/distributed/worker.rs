@@ -0,0 +1,187 @@
+// Worker side: `carescanner worker --coordinator HOST:PORT` scans the chunks it
+// is handed with the options the coordinator sends, and streams the results back.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use clap::Parser;
+use futures::stream::{self, StreamExt};
+use log::{debug, info, warn};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use super::{send, parse, ChunkTarget, CoordinatorMessage, CoordinatorToken, WorkerMessage, HEARTBEAT, PROTOCOL_VERSION};
+use crate::appstate::ScanStats;
+use crate::configuration::{Config, LoggingLevel};
+use crate::host_tracker::HostTracker;
+use crate::modes::{PortStatus, ScanType, ScanTypeTrait};
+use crate::{increase_ulimit, Limits};
+
+/// How long a starting worker keeps trying to reach its coordinator.
+const CONNECT_PATIENCE: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Parser)]
+#[command(name = "carescanner worker", version, about = "Scan the chunks a carescanner --coordinator hands out")]
+pub struct WorkerArgs {
+    #[arg(long, env = "CARESCANNER_COORDINATOR", help = "Address of the coordinator (e.g., 10.0.0.5:7700)", value_name = "HOST:PORT")]
+    pub coordinator: String,
+
+    #[arg(long, env = "CARESCANNER_COORDINATOR_TOKEN", help = "Token the coordinator asks for (its --coordinator-token); prefer the variable so it stays off the command line", value_name = "TOKEN", hide_env_values = true)]
+    pub coordinator_token: Option<CoordinatorToken>,
+
+    #[arg(long, help = "Name the coordinator shows for this worker (default: worker-<pid>)", value_name = "NAME")]
+    pub name: Option<String>,
+
+    #[arg(short, long, default_value = "info", help = "Logging level")]
+    pub logging_level: LoggingLevel,
+}
+
+/// Work for the coordinator at `args.coordinator` until it has nothing left.
+pub async fn run_worker(args: WorkerArgs) -> Result<(), String> {
+    let stream = connect(&args.coordinator).await?;
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let name = args.name.clone().unwrap_or_else(|| format!("worker-{}", std::process::id()));
+    let token = args.coordinator_token.as_ref().map(|token| token.0.clone());
+    send(&mut write, &WorkerMessage::Hello { name: name.clone(), version: PROTOCOL_VERSION.to_string(), token }).await
+        .map_err(|e| format!("Lost the coordinator: {}", e))?;
+    let settings = match lines.next_line().await {
+        Ok(Some(line)) => parse(&line)?,
+        _ => return Err(format!("The coordinator at {} closed the connection (a different version or token?)", args.coordinator)),
+    };
+    let CoordinatorMessage::Settings { args: options, rate } = settings else {
+        return Err("The coordinator did not send its settings".to_string());
+    };
+    let mut config = Config::for_worker(&options)?;
+    config.max_concurrent_ports = increase_ulimit((config.max_concurrent_ports as f64 * 1.5).ceil() as u64) / 1.5 as u64;
+    let scanner: Vec<ScanType> = config.scan_type.iter().cloned().map(|scan_type| ScanType::build(scan_type, &config)).collect();
+    info!("Working for {} as {}: {} at up to {} scans/sec for now, {} concurrent",
+        args.coordinator, name, options.join(" "), rate, config.max_concurrent_ports);
+
+    // Everything to the coordinator goes through one writer task.
+    let (outbox, mut queued) = mpsc::unbounded_channel::<WorkerMessage>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = queued.recv().await {
+            if send(&mut write, &message).await.is_err() {
+                return;
+            }
+        }
+    });
+    let heartbeat = {
+        let outbox = outbox.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(HEARTBEAT).await;
+                if outbox.send(WorkerMessage::Heartbeat).is_err() {
+                    return;
+                }
+            }
+        })
+    };
+
+    // Targets left of each chunk held, by phase and start.
+    let remaining: Arc<StdMutex<HashMap<(usize, u64), usize>>> = Arc::default();
+    let finished = Arc::new(AtomicBool::new(false));
+    // The rate is the coordinator's to share out: the settings only start it.
+    let limits = Arc::new(Limits::new(&config, rate));
+    let chunks = stream::unfold(lines, {
+        let (finished, limits) = (Arc::clone(&finished), Arc::clone(&limits));
+        move |mut lines| {
+            let (finished, limits) = (Arc::clone(&finished), Arc::clone(&limits));
+            async move {
+                loop {
+                    match lines.next_line().await.map_err(|e| e.to_string()).and_then(|line| line.map(|l| parse(&l)).transpose()) {
+                        Ok(Some(CoordinatorMessage::Chunk { phase, start, targets })) => return Some(((phase, start, targets), lines)),
+                        Ok(Some(CoordinatorMessage::Finished)) => {
+                            finished.store(true, Ordering::Relaxed);
+                            return None;
+                        }
+                        Ok(Some(CoordinatorMessage::Rate { rate })) => {
+                            debug!("Rate share: {} scans/sec", rate);
+                            limits.set_rate(rate);
+                        }
+                        Ok(Some(CoordinatorMessage::Settings { .. })) => continue,
+                        Ok(None) => return None,
+                        Err(e) => {
+                            warn!("Bad message from the coordinator: {}", e);
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+    });
+    let targets = chunks.flat_map(|(phase, start, targets): (usize, u64, Vec<ChunkTarget>)| {
+        if targets.is_empty() {
+            let _ = outbox.send(WorkerMessage::Done { phase, start });
+        } else {
+            remaining.lock().unwrap().insert((phase, start), targets.len());
+        }
+        stream::iter(targets.into_iter().map(move |target| (phase, start, target)))
+    });
+
+    let stats = ScanStats::default();
+    let host_tracker = HostTracker::new(config.host_abort_after);
+    let started = Instant::now();
+    targets.for_each_concurrent(limits.targets_in_hand(config.max_concurrent_ports as usize), |(phase, start, ChunkTarget { target, protocol, last })| {
+        let (scanner, limits, stats, host_tracker, outbox, remaining) = (&scanner, &*limits, &stats, &host_tracker, outbox.clone(), &remaining);
+        async move {
+            for scan_type in scanner.iter().filter(|s| protocol.is_none_or(|p| s.protocol() == p.as_str())) {
+                if host_tracker.is_down(&target.ip) {
+                    continue;
+                }
+                let result = limits.probe(scan_type, &target, stats).await;
+                let replied = !matches!(result.status, PortStatus::Filtered);
+                if (replied || scan_type.protocol() == "tcp") && host_tracker.record(&target.ip, replied) {
+                    let _ = outbox.send(WorkerMessage::Down { host: target.ip.clone() });
+                }
+                let _ = outbox.send(WorkerMessage::Result {
+                    target: target.clone(),
+                    protocol: scan_type.protocol().to_string(),
+                    status: result.status,
+                    banner: result.banner,
+                });
+            }
+            if last {
+                host_tracker.finish_host(&target.ip);
+            }
+            finish_target(remaining, &outbox, phase, start);
+        }
+    }).await;
+
+    heartbeat.abort();
+    drop(outbox);
+    let _ = writer.await;
+    if !finished.load(Ordering::Relaxed) {
+        return Err(format!("Lost the coordinator at {}", args.coordinator));
+    }
+    info!("The coordinator has no more work, done after {:.0}s", started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+/// Count one target of a chunk as scanned, reporting the chunk done with its last one.
+fn finish_target(remaining: &StdMutex<HashMap<(usize, u64), usize>>, outbox: &UnboundedSender<WorkerMessage>, phase: usize, start: u64) {
+    let mut remaining = remaining.lock().unwrap();
+    let Some(left) = remaining.get_mut(&(phase, start)) else {
+        return;
+    };
+    *left -= 1;
+    if *left == 0 {
+        remaining.remove(&(phase, start));
+        let _ = outbox.send(WorkerMessage::Done { phase, start });
+    }
+}
+
+/// Connect to the coordinator, retrying for a while in case it is still starting.
+async fn connect(address: &str) -> Result<TcpStream, String> {
+    let give_up = Instant::now() + CONNECT_PATIENCE;
+    loop {
+        match TcpStream::connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) if Instant::now() >= give_up => return Err(format!("Failed to reach the coordinator at {}: {}", address, e)),
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Hostname resolution before the scan: concurrent, with custom resolvers,
+//! timeouts, retries and address family selection. Names that do not resolve
+//! are reported and left out instead of failing the run. After the scan, the
+//! same resolver optionally looks up PTR names of the hosts that were found.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::stream::{self, StreamExt};
+use governor::{Quota, RateLimiter};
+use hickory_resolver::TokioAsyncResolver;
+use hickory_resolver::config::{LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
+use log::{debug, info, warn};
+use crate::configuration::Config;
+
+const DNS_PORT: u16 = 53;
+
+/// Parse a `--resolvers` entry: `IP` or `IP:PORT` (`[IPv6]:PORT`).
+pub fn parse_resolver(s: &str) -> Result<SocketAddr, String> {
+    let input = s.trim();
+    if let Ok(ip) = input.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, DNS_PORT));
+    }
+    input
+        .parse::<SocketAddr>()
+        .map_err(|_| format!("Invalid resolver '{}': expected IP or IP:PORT (e.g. 127.0.0.1:5353)", input))
+}
+
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub resolved: HashMap<Arc<str>, Vec<IpAddr>>,
+    /// Names that did not resolve, with the reason.
+    pub failed: Vec<(Arc<str>, String)>,
+}
+
+fn build_resolver(config: &Config) -> TokioAsyncResolver {
+    let (resolver_config, mut opts) = if config.resolvers.is_empty() {
+        hickory_resolver::system_conf::read_system_conf().unwrap_or_else(|e| {
+            warn!("Could not read the system DNS configuration ({}), using public resolvers", e);
+            (ResolverConfig::default(), ResolverOpts::default())
+        })
+    } else {
+        let servers: Vec<NameServerConfig> = config.resolvers.iter()
+            .flat_map(|addr| [NameServerConfig::new(*addr, Protocol::Udp), NameServerConfig::new(*addr, Protocol::Tcp)])
+            .collect();
+        (ResolverConfig::from_parts(None, vec![], servers), ResolverOpts::default())
+    };
+    opts.timeout = Duration::from_secs(config.dns_timeout);
+    opts.attempts = config.dns_retries;
+    opts.ip_strategy = if config.ipv4_only {
+        LookupIpStrategy::Ipv4Only
+    } else if config.ipv6_only {
+        LookupIpStrategy::Ipv6Only
+    } else {
+        LookupIpStrategy::Ipv4AndIpv6
+    };
+    TokioAsyncResolver::tokio(resolver_config, opts)
+}
+
+/// Resolve `names` with at most `--dns-concurrency` lookups in flight.
+pub async fn resolve_names(names: &[Arc<str>], config: &Config) -> Resolution {
+    let resolver = build_resolver(config);
+    info!("Resolving {} hostnames ({} at a time)", names.len(), config.dns_concurrency);
+
+    let lookups = stream::iter(names.iter().cloned())
+        .map(|name| {
+            let resolver = resolver.clone();
+            async move {
+                let result = match resolver.lookup_ip(&*name).await {
+                    Ok(lookup) => {
+                        let mut addresses: Vec<IpAddr> = lookup.iter().collect();
+                        addresses.sort();
+                        addresses.dedup();
+                        if addresses.is_empty() { Err("no addresses".to_string()) } else { Ok(addresses) }
+                    }
+                    Err(e) => Err(e.to_string()),
+                };
+                (name, result)
+            }
+        })
+        .buffer_unordered(config.dns_concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut resolution = Resolution::default();
+    for (name, result) in lookups {
+        match result {
+            Ok(addresses) => {
+                debug!("{} -> {:?}", name, addresses);
+                resolution.resolved.insert(name, addresses);
+            }
+            Err(reason) => resolution.failed.push((name, reason)),
+        }
+    }
+    // Report failures in input order.
+    resolution.failed.sort_by_key(|(name, _)| names.iter().position(|n| n == name));
+    resolution
+}
+
+/// PTR names for `hosts`, with at most `--dns-concurrency` lookups in flight and
+/// `--reverse-dns-rate` started per second. Hosts without a name are left out.
+pub async fn reverse_lookup(hosts: &[IpAddr], config: &Config) -> HashMap<IpAddr, Vec<String>> {
+    let resolver = build_resolver(config);
+    let rate = NonZeroU32::new(config.reverse_dns_rate).unwrap_or(NonZeroU32::MIN);
+    let limiter = Arc::new(RateLimiter::direct(Quota::per_second(rate).allow_burst(NonZeroU32::MIN)));
+    info!("Reverse lookups for {} hosts ({} per second)", hosts.len(), rate);
+
+    let lookups = stream::iter(hosts.iter().copied())
+        .map(|ip| {
+            let resolver = resolver.clone();
+            let limiter = Arc::clone(&limiter);
+            async move {
+                limiter.until_ready().await;
+                match resolver.reverse_lookup(ip).await {
+                    Ok(lookup) => {
+                        let mut names: Vec<String> = lookup.iter()
+                            .map(|name| name.to_utf8().trim_end_matches('.').to_lowercase())
+                            .filter(|name| !name.is_empty())
+                            .collect();
+                        names.sort();
+                        names.dedup();
+                        (ip, names)
+                    }
+                    Err(e) => {
+                        debug!("No PTR name for {}: {}", ip, e);
+                        (ip, Vec::new())
+                    }
+                }
+            }
+        })
+        .buffer_unordered(config.dns_concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    lookups.into_iter().filter(|(_, names)| !names.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+    use std::time::Instant;
+    use clap::Parser;
+    use hickory_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use hickory_resolver::proto::rr::{RData, Record, RecordType};
+    use hickory_resolver::proto::rr::rdata::{A, PTR};
+    use tokio::net::UdpSocket;
+
+    /// A resolver on a loopback UDP port: `host.test` is 192.0.2.7 (PTR
+    /// `Host.Test.`), `flaky.test` 192.0.2.8 once its first query was lost, and
+    /// `silent.test` never answers. Returns its address and every name asked for.
+    async fn stub_resolver() -> (SocketAddr, Arc<StdMutex<Vec<String>>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let asked: Arc<StdMutex<Vec<String>>> = Arc::default();
+        let log = Arc::clone(&asked);
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            loop {
+                let Ok((len, from)) = socket.recv_from(&mut buffer).await else { return };
+                let Ok(query) = Message::from_vec(&buffer[..len]) else { continue };
+                let Some(question) = query.queries().first() else { continue };
+                let name = question.name().to_ascii().to_lowercase();
+                let times = {
+                    let mut asked = log.lock().unwrap();
+                    asked.push(name.clone());
+                    asked.iter().filter(|n| **n == name).count()
+                };
+                let answer = match (name.as_str(), question.query_type()) {
+                    ("silent.test.", _) => continue,
+                    ("flaky.test.", _) if times == 1 => continue,
+                    ("host.test.", RecordType::A) => Some(RData::A(A([192, 0, 2, 7].into()))),
+                    ("flaky.test.", RecordType::A) => Some(RData::A(A([192, 0, 2, 8].into()))),
+                    ("7.2.0.192.in-addr.arpa.", RecordType::PTR) => Some(RData::PTR(PTR("Host.Test.".parse().unwrap()))),
+                    _ => None,
+                };
+                let mut response = Message::new();
+                response.set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(true)
+                    .set_recursion_available(true)
+                    .add_queries(query.queries().to_vec())
+                    .set_response_code(if answer.is_some() { ResponseCode::NoError } else { ResponseCode::NXDomain });
+                if let Some(rdata) = answer {
+                    response.add_answer(Record::from_rdata(question.name().clone(), 60, rdata));
+                }
+                let _ = socket.send_to(&response.to_vec().unwrap(), from).await;
+            }
+        });
+        (address, asked)
+    }
+
+    fn config(resolver: SocketAddr, options: &[&str]) -> Config {
+        let resolvers = resolver.to_string();
+        let args = ["carescanner", "-t", "127.0.0.1", "-4", "--resolvers", &resolvers].into_iter().chain(options.iter().copied());
+        Config::parse_from(args)
+    }
+
+    fn names(names: &[&str]) -> Vec<Arc<str>> {
+        names.iter().map(|&name| Arc::from(name)).collect()
+    }
+
+    #[tokio::test]
+    async fn resolves_through_the_given_resolver() {
+        let (resolver, _) = stub_resolver().await;
+        let resolution = resolve_names(&names(&["missing.test", "host.test"]), &config(resolver, &[])).await;
+        assert_eq!(resolution.resolved[&Arc::from("host.test")], [IpAddr::from([192, 0, 2, 7])]);
+        assert_eq!(resolution.failed.len(), 1);
+        assert_eq!(&*resolution.failed[0].0, "missing.test");
+    }
+
+    #[tokio::test]
+    async fn lost_queries_are_retried_until_the_timeout() {
+        let (resolver, asked) = stub_resolver().await;
+        let started = Instant::now();
+        let resolution = resolve_names(&names(&["flaky.test", "silent.test"]), &config(resolver, &["--dns-timeout", "1", "--dns-retries", "2"])).await;
+        assert_eq!(resolution.resolved[&Arc::from("flaky.test")], [IpAddr::from([192, 0, 2, 8])]);
+        assert_eq!(resolution.failed.iter().map(|(name, _)| &**name).collect::<Vec<_>>(), ["silent.test"]);
+        let silent = asked.lock().unwrap().iter().filter(|name| *name == "silent.test.").count();
+        assert!(silent >= 2, "silent.test asked {} times", silent);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn reverse_lookups_are_paced() {
+        let (resolver, asked) = stub_resolver().await;
+        let hosts: Vec<IpAddr> = (3..=7).map(|last| IpAddr::from([192, 0, 2, last])).collect();
+        let started = Instant::now();
+        let found = reverse_lookup(&hosts, &config(resolver, &["--reverse-dns-rate", "4"])).await;
+        // The first goes at once, the other four a quarter second apart.
+        assert!(started.elapsed() >= Duration::from_millis(900), "{:?}", started.elapsed());
+        assert_eq!(found, HashMap::from([(IpAddr::from([192, 0, 2, 7]), vec!["host.test".to_string()])]));
+        assert_eq!(asked.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn parses_resolver_addresses() {
+        assert_eq!(parse_resolver("127.0.0.1:5353").unwrap(), "127.0.0.1:5353".parse().unwrap());
+        assert_eq!(parse_resolver("9.9.9.9").unwrap(), "9.9.9.9:53".parse().unwrap());
+        assert_eq!(parse_resolver("[::1]:5353").unwrap(), "[::1]:5353".parse().unwrap());
+        assert_eq!(parse_resolver("::1").unwrap(), "[::1]:53".parse().unwrap());
+        assert!(parse_resolver("dns.example").is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! `--dry-run`: what a scan would touch and roughly how long it would take,
+//! without sending a single probe.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::configuration::{Config, ExclusionSet, Protocol, ScanPhase, TargetList};
+use crate::strategy::{ScanPlan, Strategy};
+
+/// Shards of up to this many targets are walked to count their own hosts;
+/// larger ones are bounded by the hosts of the whole plan.
+const WALKED_SHARD_TARGETS: u64 = 1 << 22;
+
+/// Planned work for one protocol of a phase.
+#[derive(Debug, Default, PartialEq)]
+pub struct ProtocolTotals {
+    /// Distinct hosts with targets.
+    pub hosts: u64,
+    /// `hosts` is an upper bound: a large shard gets some of the plan's hosts.
+    pub hosts_at_most: bool,
+    pub ports: usize,
+    pub targets: u64,
+    pub probes: u64,
+    /// Most probes any single host gets.
+    pub busiest_host: u64,
+}
+
+/// Disjoint sets of hosts, each with the probes every one of its hosts gets.
+pub type HostProbes = Vec<(TargetList, u64)>;
+
+/// Add `probes` for every host of `hosts`, splitting the sets it overlaps so a
+/// host listed in several groups, protocols or phases is counted once, with
+/// all its probes. Ranges are cut arithmetically, never expanded.
+fn add_probes(sets: &mut HostProbes, hosts: TargetList, probes: u64) {
+    let mut rest = hosts;
+    let mut split = Vec::new();
+    for (set, set_probes) in sets.iter_mut() {
+        if rest.is_empty() {
+            break;
+        }
+        let outside = set.exclude(&ExclusionSet::from(&rest));
+        if outside.len() == set.len() {
+            continue;
+        }
+        let inside = set.exclude(&ExclusionSet::from(&outside));
+        rest = rest.exclude(&ExclusionSet::from(&inside));
+        split.push((inside, set_probes.saturating_add(probes)));
+        *set = outside;
+    }
+    sets.retain(|(set, _)| !set.is_empty());
+    sets.extend(split);
+    if !rest.is_empty() {
+        sets.push((rest, probes));
+    }
+}
+
+/// The hosts `plan` probes over `protocol` from target `skip` on, with
+/// `scan_types` probes per target, and whether the sets are exact. A shard is
+/// walked target by target when small enough; a larger one is bounded by the
+/// whole plan's hosts, each with its share of their probes.
+fn host_probes(plan: &ScanPlan, skip: u64, protocol: Protocol, scan_types: u64, strategy: &Strategy) -> (HostProbes, bool) {
+    let mut sets = HostProbes::new();
+    let shard = plan.shard();
+    if shard.is_some() && plan.len() <= WALKED_SHARD_TARGETS {
+        let mut per_host: HashMap<IpAddr, u64> = HashMap::new();
+        for (_, target, _) in plan.targets_from(strategy, skip).filter(|(_, _, p)| *p == Some(protocol)) {
+            if let Ok(ip) = target.ip.parse() {
+                *per_host.entry(ip).or_default() += scan_types;
+            }
+        }
+        let mut by_probes: BTreeMap<u64, Vec<IpAddr>> = BTreeMap::new();
+        for (ip, probes) in per_host {
+            by_probes.entry(probes).or_default().push(ip);
+        }
+        for (probes, mut hosts) in by_probes {
+            hosts.sort_unstable();
+            sets.push((hosts.into_iter().collect(), probes));
+        }
+        return (sets, true);
+    }
+    let share = shard.map_or(1, |shard| shard.total);
+    for group in plan.groups().iter().filter(|g| g.protocol == Some(protocol)) {
+        let probes = (group.ports.len() as u64).div_ceil(share).saturating_mul(scan_types);
+        add_probes(&mut sets, group.hosts.clone(), probes);
+    }
+    (sets, shard.is_none())
+}
+
+/// Totals of `plan` for `protocol` from target `skip` on, with `scan_types`
+/// probes per target, and the probes of each host.
+pub fn protocol_totals(plan: &ScanPlan, skip: u64, protocol: Protocol, scan_types: u64, strategy: &Strategy) -> (ProtocolTotals, HostProbes) {
+    let targets = plan.probes_from(skip, |p| u64::from(p == Some(protocol)));
+    let mut ports = vec![false; 65_536];
+    for group in plan.groups().iter().filter(|g| g.protocol == Some(protocol)) {
+        for port in &group.ports.ports {
+            ports[*port as usize] = true;
+        }
+    }
+    let (hosts, exact) = host_probes(plan, skip, protocol, scan_types, strategy);
+    let totals = ProtocolTotals {
+        hosts: hosts.iter().map(|(set, _)| set.len()).fold(0, u64::saturating_add),
+        hosts_at_most: !exact,
+        ports: ports.iter().filter(|p| **p).count(),
+        targets,
+        probes: targets.saturating_mul(scan_types),
+        busiest_host: hosts.iter().map(|(_, probes)| *probes).max().unwrap_or_default(),
+    };
+    (totals, hosts)
+}
+
+/// Shortest and longest expected run time for `probes` probes, the busiest
+/// host getting `busiest_host` of them: the rate limits bound the fast end,
+/// every probe waiting out the timeout at full concurrency the slow end.
+pub fn estimate(config: &Config, rate: u64, probes: u64, busiest_host: u64) -> (Duration, Duration) {
+    let per_second = |count: u64, rate: u64| count as f64 / rate.max(1) as f64;
+    let mut fastest = per_second(probes, rate);
+    if let Some(host_rate) = config.ratelimit_per_host {
+        fastest = fastest.max(per_second(busiest_host, host_rate));
+    }
+    let mut slowest = fastest.max(per_second(probes.saturating_mul(config.timeout), config.max_concurrent_ports));
+    if let Some(host_concurrency) = config.max_concurrent_per_host {
+        slowest = slowest.max(per_second(busiest_host.saturating_mul(config.timeout), host_concurrency as u64));
+    }
+    let cap = |secs: f64| {
+        let duration = Duration::from_secs_f64(secs.min(u64::MAX as f64 / 2.0));
+        config.maximum_scan_time.map_or(duration, |limit| duration.min(limit))
+    };
+    (cap(fastest), cap(slowest))
+}
+
+/// `1h 05m`, `3m 20s`, `12s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64().ceil() as u64;
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
+
+/// Print what each phase would scan, from target `skip` of the first one.
+/// Phases after the first only scan hosts that answer, so theirs are upper bounds.
+pub fn print_summary(
+    config: &Config,
+    phases: &[ScanPhase],
+    plans: &[(usize, ScanPlan)],
+    skip: u64,
+    scan_types: impl Fn(Protocol) -> u64,
+    strategy: &Strategy,
+    rate: u64,
+) {
+    println!("Dry run: nothing will be sent.");
+    let mut probes = 0u64;
+    // Every host's probes over all phases and protocols.
+    let mut host_probes = HostProbes::new();
+    for (i, (phase, plan)) in plans.iter().enumerate() {
+        let first = i == 0;
+        let bound = if first { "" } else { "at most " };
+        println!("Phase {}/{} ({}):{}", phase + 1, phases.len(), phases[*phase],
+            if first { String::new() } else { format!(" only hosts that answer phase {}", phase) });
+        if let Some(shard) = plan.shard() {
+            println!("  Worker {} of {}: {} of {} targets (seed {})", shard.id, shard.total, plan.len(), plan.unsharded_len(), shard.seed);
+        }
+        for protocol in [Protocol::Tcp, Protocol::Udp] {
+            let (totals, hosts) = protocol_totals(plan, if first { skip } else { 0 }, protocol, scan_types(protocol), strategy);
+            if totals.targets == 0 {
+                continue;
+            }
+            let hosts_bound = if totals.hosts_at_most { "at most " } else { bound };
+            println!("  {}: {}{} hosts, {} ports, {}{} targets, {}{} probes",
+                protocol, hosts_bound, totals.hosts, totals.ports, bound, totals.targets, bound, totals.probes);
+            probes = probes.saturating_add(totals.probes);
+            for (set, set_probes) in hosts {
+                add_probes(&mut host_probes, set, set_probes);
+            }
+        }
+    }
+    let busiest_host = host_probes.iter().map(|(_, probes)| *probes).max().unwrap_or_default();
+    println!("Total: {}{} probes", if plans.len() > 1 { "at most " } else { "" }, probes);
+
+    let (fastest, slowest) = estimate(config, rate, probes, busiest_host);
+    println!("Estimated time: {} at {} probes/sec, up to {} if every probe times out ({}s timeout, {} concurrent)",
+        format_duration(fastest), rate, format_duration(slowest), config.timeout, config.max_concurrent_ports);
+    if let Some(limit) = config.maximum_scan_time {
+        println!("The scan stops at the maximum scan time of {}", format_duration(limit));
+    }
+}
+
+/// Write every planned target as `phase,address,port,protocol,hostname`, in
+/// scanning order (`-` for stdout). An address with several names lists them
+/// separated by `;`.
+pub fn write_plan(path: &str, plans: &[(usize, ScanPlan)], strategy: &Strategy, skip: u64) -> Result<u64, String> {
+    let out: Box<dyn Write> = if path == "-" {
+        Box::new(std::io::stdout().lock())
+    } else {
+        Box::new(std::fs::File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?)
+    };
+    let mut out = std::io::BufWriter::new(out);
+    let mut written = 0u64;
+    let error = |e: std::io::Error| format!("Failed to write {}: {}", path, e);
+    writeln!(out, "phase,address,port,protocol,hostname").map_err(error)?;
+    for (i, (phase, plan)) in plans.iter().enumerate() {
+        for (_, target, protocol) in plan.targets_from(strategy, if i == 0 { skip } else { 0 }) {
+            let protocol = protocol.map_or("all", |p| p.as_str());
+            let names: Vec<&str> = target.hostname.iter().chain(&target.aliases).map(String::as_str).collect();
+            writeln!(out, "{},{},{},{},{}", phase + 1, target.ip, target.port, protocol, names.join(";"))
+                .map_err(error)?;
+            written += 1;
+        }
+    }
+    out.flush().map_err(error)?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::configuration::{PortList, TargetList};
+    use crate::strategy::{PlanGroup, Shard};
+    use crate::strategy::round_robin::RoundRobinStrategy;
+
+    #[test]
+    fn totals_per_protocol() {
+        let group = |hosts: &[&str], ports: Vec<u16>, protocol| PlanGroup {
+            hosts: TargetList::from_hosts(hosts),
+            ports: PortList { ports },
+            protocol: Some(protocol),
+        };
+        let plan = ScanPlan::new(vec![
+            group(&["10.0.0.1", "10.0.0.2"], vec![22, 80], Protocol::Tcp),
+            group(&["10.0.0.3"], vec![80, 443, 8080], Protocol::Tcp),
+            group(&["10.0.0.1", "10.0.0.2"], vec![53], Protocol::Udp),
+        ]);
+        let strategy = Strategy::RoundRobin(RoundRobinStrategy);
+        let (tcp, _) = protocol_totals(&plan, 0, Protocol::Tcp, 2, &strategy);
+        assert_eq!(tcp, ProtocolTotals { hosts: 3, hosts_at_most: false, ports: 4, targets: 7, probes: 14, busiest_host: 6 });
+        assert_eq!(protocol_totals(&plan, 5, Protocol::Tcp, 1, &strategy).0.targets, 2);
+        assert_eq!(protocol_totals(&plan, 0, Protocol::Udp, 1, &strategy).0.targets, 2);
+    }
+
+    #[test]
+    fn hosts_are_counted_once_with_all_their_probes() {
+        let group = |hosts: &[&str], ports: Vec<u16>, protocol| PlanGroup {
+            hosts: TargetList::from_hosts(hosts),
+            ports: PortList { ports },
+            protocol: Some(protocol),
+        };
+        let strategy = Strategy::RoundRobin(RoundRobinStrategy);
+        let plan = ScanPlan::new(vec![
+            group(&["10.0.0.1", "10.0.0.2"], vec![22, 80], Protocol::Tcp),
+            group(&["10.0.0.2", "10.0.0.3"], vec![443, 8080, 8443], Protocol::Tcp),
+            group(&["10.0.0.2"], vec![53, 123], Protocol::Udp),
+        ]);
+        let (tcp, tcp_hosts) = protocol_totals(&plan, 0, Protocol::Tcp, 1, &strategy);
+        assert_eq!((tcp.hosts, tcp.busiest_host), (3, 5));
+        let (_, udp_hosts) = protocol_totals(&plan, 0, Protocol::Udp, 1, &strategy);
+        // 10.0.0.2 gets 5 TCP and 2 UDP probes; 10.0.0.3 gets 3 and 0.
+        let mut all = HostProbes::new();
+        for (set, probes) in tcp_hosts.into_iter().chain(udp_hosts) {
+            add_probes(&mut all, set, probes);
+        }
+        assert_eq!(all.iter().map(|(set, _)| set.len()).sum::<u64>(), 3);
+        assert_eq!(all.iter().map(|(_, probes)| *probes).max(), Some(7));
+
+        // A shard counts only the hosts it was dealt.
+        let wide = ScanPlan::new(vec![group(&["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4"], vec![80], Protocol::Tcp)]);
+        let counted: Vec<u64> = (0..2)
+            .map(|id| protocol_totals(&wide.clone().sharded(Shard { id, total: 2, seed: 1 }), 0, Protocol::Tcp, 1, &strategy).0)
+            .inspect(|totals| assert!(!totals.hosts_at_most && totals.busiest_host == 1))
+            .map(|totals| totals.hosts)
+            .collect();
+        assert_eq!(counted, [2, 2]);
+    }
+
+    #[test]
+    fn estimate_spans_rate_and_timeouts() {
+        let config = Config::parse_from(["carescanner", "-t", "10.0.0.1", "--timeout", "2", "--max-concurrent-ports", "100"]);
+        let (fastest, slowest) = estimate(&config, 1000, 10_000, 10_000);
+        assert_eq!((fastest.as_secs(), slowest.as_secs()), (10, 200));
+        assert_eq!(format_duration(slowest), "3m 20s");
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1h 02m");
+
+        let config = Config::parse_from(["carescanner", "-t", "10.0.0.1", "--ratelimit-per-host", "10", "-M", "1min"]);
+        let (fastest, _) = estimate(&config, 1000, 1000, 1000);
+        assert_eq!(fastest.as_secs(), 60);
+    }
+}
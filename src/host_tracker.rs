@@ -0,0 +1,117 @@
+// Per-host outcome tracking: hosts that only ever time out are marked down
+// so the rest of their ports can be skipped.
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Mutex, RwLock};
+
+pub struct HostTracker {
+    /// Consecutive timeouts (with no reply ever) after which a host is down. None = never.
+    abort_after: Option<u32>,
+    /// Timeouts of hosts still being scanned that have neither answered nor gone down.
+    timeouts: Mutex<HashMap<IpAddr, u32>>,
+    responsive: RwLock<HashSet<IpAddr>>,
+    down: RwLock<HashSet<IpAddr>>,
+}
+
+impl HostTracker {
+    pub fn new(abort_after: Option<u32>) -> Self {
+        Self {
+            abort_after: abort_after.map(|n| n.max(1)),
+            timeouts: Mutex::new(HashMap::new()),
+            responsive: RwLock::new(HashSet::new()),
+            down: RwLock::new(HashSet::new()),
+        }
+    }
+
+    pub fn abort_after(&self) -> Option<u32> {
+        self.abort_after
+    }
+
+    /// Record a probe outcome. Returns true if this timeout just marked the host down.
+    pub fn record(&self, host: &str, replied: bool) -> bool {
+        let Ok(ip) = host.parse::<IpAddr>() else { return false };
+        if replied {
+            if !self.responsive.read().unwrap().contains(&ip) {
+                self.responsive.write().unwrap().insert(ip);
+                self.timeouts.lock().unwrap().remove(&ip);
+            }
+            return false;
+        }
+        let Some(limit) = self.abort_after else { return false };
+        if self.responsive.read().unwrap().contains(&ip) {
+            return false;
+        }
+
+        let mut timeouts = self.timeouts.lock().unwrap();
+        let count = timeouts.entry(ip).or_insert(0);
+        *count += 1;
+        if *count < limit {
+            return false;
+        }
+        timeouts.remove(&ip);
+        drop(timeouts);
+        self.down.write().unwrap().insert(ip)
+    }
+
+    /// Forget the timeouts of a host whose last port was just scanned.
+    pub fn finish_host(&self, host: &str) {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            self.timeouts.lock().unwrap().remove(&ip);
+        }
+    }
+
+    /// Whether the host has answered at least one probe.
+    pub fn responded(&self, host: &str) -> bool {
+        host.parse().is_ok_and(|ip| self.responsive.read().unwrap().contains(&ip))
+    }
+
+    pub fn is_down(&self, host: &str) -> bool {
+        self.abort_after.is_some() && host.parse().is_ok_and(|ip| self.down.read().unwrap().contains(&ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_silent_host_down_once() {
+        let tracker = HostTracker::new(Some(3));
+        assert!(!tracker.record("10.0.0.1", false));
+        assert!(!tracker.record("10.0.0.1", false));
+        assert!(tracker.record("10.0.0.1", false));
+        assert!(!tracker.record("10.0.0.1", false));
+        assert!(tracker.is_down("10.0.0.1"));
+    }
+
+    #[test]
+    fn any_reply_keeps_host_up() {
+        let tracker = HostTracker::new(Some(2));
+        tracker.record("10.0.0.2", true);
+        for _ in 0..10 {
+            assert!(!tracker.record("10.0.0.2", false));
+        }
+        assert!(!tracker.is_down("10.0.0.2"));
+        assert!(tracker.responded("10.0.0.2"));
+
+        let disabled = HostTracker::new(None);
+        for _ in 0..10 {
+            assert!(!disabled.record("10.0.0.3", false));
+        }
+        assert!(!disabled.is_down("10.0.0.3"));
+    }
+
+    #[test]
+    fn timeouts_are_forgotten_once_they_cannot_matter() {
+        let tracker = HostTracker::new(Some(2));
+        for host in ["10.0.0.1", "10.0.0.2", "10.0.0.3", "2001:db8::1"] {
+            tracker.record(host, false);
+        }
+        assert_eq!(tracker.timeouts.lock().unwrap().len(), 4);
+        tracker.record("10.0.0.1", true);
+        assert!(tracker.record("10.0.0.2", false));
+        tracker.finish_host("10.0.0.3");
+        assert_eq!(tracker.timeouts.lock().unwrap().keys().collect::<Vec<_>>(), [&"2001:db8::1".parse::<IpAddr>().unwrap()]);
+    }
+}
@@ -8,142 +8,201 @@ pub mod nmap;
 pub mod service_detection;
 pub mod service_probes;
 pub mod web_dashboard;
+pub mod rate_control;
+pub mod host_tracker;
+pub mod dns;
+pub mod dry_run;
+pub mod distributed;
 
 use crate::configuration::Config;
-use crate::configuration::top_ports::TOP_PORTS;
-use crate::configuration::PortList;
-use crate::modes::{ScanType, ScanTypeTrait};
+use crate::configuration::top_ports::PortFrequencies;
+use crate::configuration::{PortList, PortSpec, Protocol};
+use crate::modes::{ScanResult, ScanType, ScanTypeTrait, Target};
 use crate::modes::ping;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::HashSet;
 use futures::stream::{self, StreamExt};
-use governor::{Quota, RateLimiter};
-use log::{info, error};
-use std::num::NonZeroU32;
-use crate::appstate::{AppState, AppStateManager};
-use crate::configuration::TargetList;
+use log::{debug, info, warn, error};
+use crate::appstate::{AppState, AppStateManager, ResumeFile, ResumePoint, ScanStats, ShardReport};
+use crate::configuration::{ScanPhase, ScanStrategy, SubnetLimit};
+use std::sync::atomic::Ordering;
+use crate::configuration::{ExclusionSet, TargetList};
 use crate::modes::PortStatus;
 use rand::seq::SliceRandom;
-use rand::rng;
-use crate::strategy::ScanStrategyTrait;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::time::{Duration, Instant};
+use crate::strategy::{PlanGroup, ScanPlan, ScanStrategyTrait, Shard, Strategy};
 use crate::signal_handler::PauseController;
-use crate::ui::spawn_term_controller;
+use crate::ui::{spawn_term_controller, TermHandle};
+use crate::rate_control::{AdjustableLimiter, CongestionController, KeyedLimits, ProbeOutcome};
+use crate::host_tracker::HostTracker;
+use crate::distributed::coordinator::Coordinator;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
 
-type SharedLimiter = Arc<std::sync::RwLock<Arc<governor::DefaultDirectRateLimiter>>>;
+pub(crate) const DEFAULT_RATELIMIT: u64 = 1000;
+/// Aim to finish a little before the deadline, not exactly on it.
+const DEADLINE_HEADROOM: f64 = 1.1;
+/// Without `--ratelimit`, deadline pacing may speed up to this multiple of the starting rate.
+const DEADLINE_MAX_SPEEDUP: u64 = 4;
+/// Seed that splits the targets between workers when none is given, so workers
+/// started with the same options agree on it.
+/// Targets that may wait on a host or subnet limit per probe slot, so a blocked
+/// host parks its targets instead of taking every slot.
+const PARKED_PER_SLOT: usize = 8;
+const DEFAULT_SHARD_SEED: u64 = 0x6361_7265_7363_616e;
 
-fn make_limiter(rate: u64) -> Arc<governor::DefaultDirectRateLimiter> {
-    let rate = rate.max(1);
-    Arc::new(RateLimiter::direct(Quota::per_second(NonZeroU32::new(rate as u32).unwrap())))
-}
+pub async fn run(mut config: Config) {
+    let deadline = config.maximum_scan_time.map(|d| Instant::now() + d);
 
-/// Background task: monitors timeout ratio and adjusts rate limiter.
-fn spawn_adaptive_task(
-    shared_limiter: SharedLimiter,
-    filtered_count: Arc<AtomicU64>,
-    total_count: Arc<AtomicU64>,
-    initial_rate: u64,
-) {
-    tokio::spawn(async move {
-        let mut current_rate = initial_rate;
-        let min_rate = (initial_rate / 20).max(10);
-        let max_rate = initial_rate;
+    if let Some(path) = &config.config_file {
+        info!("Loaded config from {}", path);
+    }
 
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    if config.require_scope && config.scope_file.is_none() {
+        error!("This configuration requires a scope (--require-scope) but no --scope-file is given: refusing to scan");
+        return;
+    }
 
-            let filtered = filtered_count.swap(0, Ordering::Relaxed);
-            let total = total_count.swap(0, Ordering::Relaxed);
+    if config.proxy_password.is_some() && config.proxy_username.is_none() {
+        warn!("A proxy password is set without a username: proxies are used without authentication");
+    }
 
-            if total < 50 {
-                continue;
-            }
+    if config.quiet {
+        config.disable_all = true;
+    }
 
-            let timeout_ratio = filtered as f64 / total as f64;
+    let modes: Vec<ScanType> = config.scan_type.iter().cloned().map(|scan_type| ScanType::build(scan_type, &config)).collect::<Vec<_>>();
 
-            let new_rate = if timeout_ratio > 0.5 {
-                (current_rate / 2).max(min_rate)
-            } else if timeout_ratio < 0.1 && current_rate < max_rate {
-                (current_rate * 5 / 4).min(max_rate)
-            } else {
-                current_rate
-            };
+    for (protocol, ports) in config.ports.parts() {
+        if let Some(protocol) = protocol
+            && !ports.is_empty()
+            && probes_per_target(&modes, Some(protocol)) == 0
+        {
+            warn!("{} {} ports given but no {} scan type selected (-s): they will not be scanned", ports.len(), protocol, protocol);
+        }
+    }
 
-            if new_rate != current_rate {
-                info!("Adaptive rate: {} -> {} scans/sec (timeout ratio: {:.1}%)", current_rate, new_rate, timeout_ratio * 100.0);
-                *shared_limiter.write().unwrap() = make_limiter(new_rate);
-                current_rate = new_rate;
+    if let Some(path) = &config.services_file {
+        match PortFrequencies::from_nmap_services(path) {
+            Ok(frequencies) => {
+                info!("Ranking ports by {} ({} TCP, {} UDP ports)", path, frequencies.tcp.len(), frequencies.udp.len());
+                config.port_frequencies = frequencies;
+            }
+            Err(e) => {
+                error!("{}", e);
+                return;
             }
         }
-    });
-}
-
-pub async fn run(mut config: Config) {
-    // Apply config file defaults (CLI flags take precedence)
-    let file_cfg = crate::configuration::config_file::load_config_file();
-    if config.ratelimit.is_none() { config.ratelimit = file_cfg.ratelimit; }
-    if config.timeout == 3 { if let Some(t) = file_cfg.timeout { config.timeout = t; } }
-    if config.max_concurrent_ports == 1000 { if let Some(m) = file_cfg.max_concurrent_ports { config.max_concurrent_ports = m; } }
-    if config.output.is_none() { config.output = file_cfg.output; }
-    if !config.banner { config.banner = file_cfg.banner.unwrap_or(false); }
-    if !config.adaptive { config.adaptive = file_cfg.adaptive.unwrap_or(false); }
-    if !config.shuffle_ports { config.shuffle_ports = file_cfg.shuffle_ports.unwrap_or(false); }
-    if !config.ping { config.ping = file_cfg.ping.unwrap_or(false); }
-    if config.nmap_path == "nmap" { if let Some(p) = file_cfg.nmap_path { config.nmap_path = p; } }
-    if config.nmap_args.is_empty() { config.nmap_args = file_cfg.nmap_args.unwrap_or_default(); }
-
-    if config.quiet {
-        config.disable_all = true;
     }
 
-    let modes: Vec<ScanType> = config.scan_type.iter().cloned().map(|scan_type| ScanType::build(scan_type, &config)).collect::<Vec<_>>();
-
     if let Some(n) = config.top_ports {
-        let n = n.min(TOP_PORTS.len());
-        config.ports = PortList { ports: TOP_PORTS[..n].to_vec() };
-        info!("Using top {} ports", n);
+        let frequencies = &config.port_frequencies;
+        config.ports = PortSpec {
+            all: PortList::default(),
+            tcp: PortList { ports: frequencies.top(Protocol::Tcp, n).to_vec() },
+            udp: PortList { ports: frequencies.top(Protocol::Udp, n).to_vec() },
+        };
+        info!("Using top {} ports per protocol ({} TCP, {} UDP)", n, config.ports.tcp.len(), config.ports.udp.len());
+    }
+    if let Some(excluded) = &config.exclude_ports {
+        info!("Excluding ports: {}", excluded);
     }
 
-    if let (Some(total), Some(id)) = (config.total_workers, config.worker_id) {
-        if id >= total {
-            error!("worker-id ({}) must be less than total-workers ({})", id, total);
+    resolve_hostnames(&mut config).await;
+    if let Err(e) = config.targets.check_size() {
+        error!("{}", e);
+        return;
+    }
+    if config.targets.is_empty() {
+        error!("No targets to scan: none of the given hostnames resolved");
+        return;
+    }
+
+    // Enforce the scope on every resolved address before anything else can use it.
+    if let Some(scope) = &config.scope_file {
+        let (allowed, refused) = scope.split(&config.targets);
+        for label in refused.labels() {
+            warn!("Out of scope ({}), refused: {}", scope.path, label);
+        }
+        info!("Scope {}: {} addresses in scope, {} refused", scope.path, allowed.len(), refused.len());
+        config.targets = allowed;
+        if config.targets.is_empty() {
+            error!("No targets in scope: nothing to scan");
             return;
         }
-        let all = std::mem::take(&mut config.targets.targets);
-        config.targets.targets = all.into_iter().enumerate()
-            .filter(|(i, _)| i % total == id)
-            .map(|(_, h)| h)
-            .collect();
-        info!("Worker {}/{}: scanning {} hosts", id, total, config.targets.len());
     }
 
+    // Exclude before sharding so workers split only the targets that are really scanned.
     if let Some(exclude) = &config.exclude {
-        let exclude_set: std::collections::HashSet<&str> =
-            exclude.targets.iter().map(|s| s.as_str()).collect();
         let before = config.targets.len();
-        config.targets.targets.retain(|h| !exclude_set.contains(h.as_str()));
+        config.targets = config.targets.exclude(exclude);
         info!("Excluded {} hosts ({} remaining)", before - config.targets.len(), config.targets.len());
     }
 
-    if config.ping {
+    let sharded = match (config.total_workers, config.worker_id) {
+        (Some(total), Some(id)) if id >= total => {
+            error!("worker-id ({}) must be less than total-workers ({})", id, total);
+            return;
+        }
+        (Some(total), Some(id)) => {
+            info!("Worker {}/{}: scanning one in {} of the permuted host x port targets", id, total, total);
+            true
+        }
+        (None, None) => false,
+        _ => {
+            error!("--total-workers and --worker-id must be given together");
+            return;
+        }
+    };
+    if sharded && config.coordinator.is_some() {
+        error!("--coordinator hands out the work itself: leave out --total-workers and --worker-id");
+        return;
+    }
+    if config.coordinator.is_some()
+        && (config.ratelimit_per_host.is_some() || config.max_concurrent_per_host.is_some() || !config.subnet_limit.is_empty())
+    {
+        // A host's targets go to several workers, none of which sees the others' probes.
+        error!("--ratelimit-per-host, --max-concurrent-per-host and --subnet-limit can't be enforced across workers: leave them out with --coordinator");
+        return;
+    }
+
+    if config.ping && sharded {
+        // Workers see different hosts answer, and would no longer split the same plan.
+        error!("--ping can't be used with --total-workers and --worker-id: every worker has to plan the same hosts");
+        return;
+    }
+
+    if config.ping && config.dry_run {
+        info!("Dry run: skipping the ping scan, every host is counted");
+    } else if config.ping {
         let alive = ping::discover_hosts(&config).await;
         if alive.is_empty() {
             info!("No alive hosts found during ping scan. Exiting.");
             return;
         }
-        config.targets = TargetList { targets: alive };
+        // Keep the hostnames and own ports of the hosts that answered.
+        config.targets = config.targets.restrict_to(&alive);
     }
 
+    let mut resume = ResumePoint::default();
     if let Some(resume_path) = &config.resume_from {
-        match AppState::load_resume_file(resume_path) {
-            Ok(completed_hosts) => {
+        match ScanStats::load_resume_file(resume_path) {
+            Ok(ResumeFile::Position(point)) => {
+                info!("Resuming scan from {}: skipping the first {} targets of phase {}", resume_path, point.position, point.phase + 1);
+                if config.ping {
+                    warn!("Resuming with --ping: the resume position assumes the same alive hosts as the previous run");
+                }
+                resume = point;
+            }
+            Ok(ResumeFile::Hosts(completed_hosts)) => {
                 let before = config.targets.len();
-                let completed_set: std::collections::HashSet<&str> =
-                    completed_hosts.iter().map(|s| s.as_str()).collect();
-                config.targets.targets.retain(|h| !completed_set.contains(h.as_str()));
+                config.targets = config.targets.exclude(&ExclusionSet::from(&TargetList::from_hosts(&completed_hosts)));
                 let skipped = before - config.targets.len();
                 info!("Resuming scan from {}: skipping {} already-scanned hosts ({} remaining)",
                     resume_path, skipped, config.targets.len());
-                if config.targets.targets.is_empty() {
+                if config.targets.is_empty() {
                     info!("All hosts already scanned. Nothing to do.");
                     return;
                 }
@@ -154,69 +213,391 @@ pub async fn run(mut config: Config) {
         }
     }
 
-    if config.shuffle_ports {
-        config.ports.ports.shuffle(&mut rng());
+    if sharded || config.shuffle_ports || matches!(config.scan_strategy, ScanStrategy::Random) {
+        // A resumed scan must keep the order it started with.
+        if let (Some(saved), Some(given)) = (resume.seed, config.seed)
+            && saved != given
+        {
+            warn!("Ignoring --seed {}: the resume file was written with seed {}", given, saved);
+        }
+        let seed = *resume.seed.get_or_insert_with(|| {
+            config.seed.unwrap_or_else(|| if sharded { DEFAULT_SHARD_SEED } else { rand::random() })
+        });
+        if sharded {
+            info!("Shard seed: {} (every worker must use the same --seed)", seed);
+        } else {
+            info!("Random seed: {} (repeat this order with --seed {})", seed, seed);
+        }
     }
 
     config.max_concurrent_ports = increase_ulimit((config.max_concurrent_ports as f64 * 1.5).ceil() as u64) / 1.5 as u64;
 
-    start_mass_scan(Arc::new(config), Arc::new(modes)).await;
+    start_mass_scan(Arc::new(config), Arc::new(modes), resume, deadline).await;
+}
+
+/// Resolve the hostnames in the targets, exclusions and scope. Unresolvable names are
+/// reported and skipped.
+async fn resolve_hostnames(config: &mut Config) {
+    let mut names: Vec<Arc<str>> = config.targets.unresolved().to_vec();
+    let pending = config.exclude.iter().flat_map(|e| e.unresolved())
+        .chain(config.scope_file.iter().flat_map(|s| s.unresolved()));
+    for name in pending {
+        if !names.contains(name) {
+            names.push(Arc::clone(name));
+        }
+    }
+    if names.is_empty() {
+        return;
+    }
+
+    let resolution = dns::resolve_names(&names, config).await;
+    for (name, reason) in &resolution.failed {
+        warn!("Could not resolve {}: {}", name, reason);
+    }
+    if !resolution.failed.is_empty() {
+        warn!("{} of {} hostnames did not resolve and will not be scanned", resolution.failed.len(), names.len());
+    }
+    config.targets = config.targets.with_resolved(&resolution.resolved);
+    if let Some(exclude) = &mut config.exclude {
+        exclude.add_resolved(&resolution.resolved);
+    }
+    if let Some(scope) = &mut config.scope_file {
+        scope.add_resolved(&resolution.resolved);
+    }
+}
+
+/// Probes a target gets: one per scan type of its protocol (`None`: every scan type).
+pub(crate) fn probes_per_target(scanner: &[ScanType], protocol: Option<Protocol>) -> u64 {
+    scanner.iter().filter(|s| protocol.is_none_or(|p| s.protocol() == p.as_str())).count() as u64
+}
+
+fn parse_addresses(hosts: &[String]) -> Vec<std::net::IpAddr> {
+    hosts.iter().filter_map(|host| host.parse().ok()).collect()
+}
+
+/// Keep the scan on track for the deadline: raise the rate (up to `ceiling`)
+/// when the remaining probes no longer fit in the remaining time.
+fn spawn_deadline_task(
+    deadline: Instant,
+    total_probes: u64,
+    base_rate: u64,
+    ceiling: u64,
+    stats: Arc<ScanStats>,
+    limits: Arc<Limits>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut current_rate = base_rate;
+        let mut warned = false;
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            let time_left = deadline.saturating_duration_since(Instant::now()).as_secs_f64();
+            if time_left <= 0.0 {
+                return;
+            }
+            let remaining = total_probes.saturating_sub(stats.probes_done.load(Ordering::Relaxed));
+            let required = (remaining as f64 / time_left * DEADLINE_HEADROOM).ceil() as u64;
+            let new_rate = required.clamp(base_rate, ceiling);
+
+            if required > ceiling && !warned {
+                warn!("Behind schedule for the maximum scan time: need {} scans/sec, capped at {}", required, ceiling);
+                warned = true;
+            }
+            if new_rate != current_rate {
+                debug!("Deadline pacing: {} -> {} scans/sec ({} probes left, {:.0}s left)", current_rate, new_rate, remaining, time_left);
+                limits.set_rate(new_rate);
+                current_rate = new_rate;
+            }
+        }
+    })
+}
+
+/// Rate and concurrency limits every probe waits for.
+pub(crate) struct Limits {
+    limiter: Arc<AdjustableLimiter>,
+    host_limits: KeyedLimits,
+    subnet_limits: Vec<(SubnetLimit, KeyedLimits)>,
+    congestion: Option<Arc<CongestionController>>,
+    /// With host or subnet limits: the `--max-concurrent-ports` probe slots,
+    /// taken only once those limits let a probe through.
+    slots: Option<Semaphore>,
+}
+
+impl Limits {
+    pub(crate) fn new(config: &Config, rate: u64) -> Self {
+        let limiter = Arc::new(AdjustableLimiter::new(rate));
+        let host_limits = KeyedLimits::new(config.ratelimit_per_host, config.max_concurrent_per_host);
+        let keyed = host_limits.is_enabled() || !config.subnet_limit.is_empty();
+        Self {
+            host_limits,
+            subnet_limits: config.subnet_limit.iter()
+                .map(|l| (l.clone(), KeyedLimits::new(l.rate, l.max_concurrent)))
+                .collect(),
+            congestion: config.adaptive.then(|| Arc::new(CongestionController::new(Arc::clone(&limiter), rate))),
+            limiter,
+            slots: keyed.then(|| Semaphore::new((config.max_concurrent_ports as usize).max(1))),
+        }
+    }
+
+    /// Targets to have in hand at once for `max_concurrent` probes in flight.
+    pub(crate) fn targets_in_hand(&self, max_concurrent: usize) -> usize {
+        match self.slots {
+            Some(_) => max_concurrent.saturating_mul(PARKED_PER_SLOT + 1),
+            None => max_concurrent,
+        }
+    }
+
+    /// The global rate probes are paced to: its ceiling with `--adaptive`.
+    pub(crate) fn rate(&self) -> u64 {
+        self.congestion.as_ref().map_or_else(|| self.limiter.rate(), |cc| cc.max_rate())
+    }
+
+    /// Move the global rate, or its ceiling with `--adaptive`.
+    pub(crate) fn set_rate(&self, rate: u64) {
+        match &self.congestion {
+            Some(cc) => cc.set_max_rate(rate),
+            None => self.limiter.set_rate(rate),
+        }
+    }
+
+    /// Probe `target` with `scan_type` once the subnet, host and global limits allow it.
+    pub(crate) async fn probe(&self, scan_type: &ScanType, target: &Target, stats: &ScanStats) -> ScanResult {
+        // Limits are always taken subnet -> host -> probe slot -> global rate, so permits can't deadlock,
+        // and a target waiting on its host or subnet holds no probe slot.
+        let mut subnet_permits = Vec::with_capacity(self.subnet_limits.len());
+        for (limit, limits) in &self.subnet_limits {
+            if let Some(key) = limit.subnet_key(&target.ip) {
+                let permit = limits.acquire(&key).await;
+                if permit.waited() {
+                    stats.throttled_subnet.fetch_add(1, Ordering::Relaxed);
+                }
+                subnet_permits.push(permit);
+            }
+        }
+        let host_permit = if self.host_limits.is_enabled() {
+            let permit = self.host_limits.acquire(&target.ip).await;
+            if permit.waited() {
+                stats.throttled_host.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(permit)
+        } else {
+            None
+        };
+        let slot = match &self.slots {
+            Some(slots) => Some(slots.acquire().await.unwrap()),
+            None => None,
+        };
+        self.limiter.until_ready().await;
+
+        let result = ScanTypeTrait::scan(scan_type, target).await;
+        drop(slot);
+        drop(host_permit);
+        drop(subnet_permits);
+
+        if let Some(cc) = &self.congestion {
+            cc.on_outcome(&target.ip, match result.status {
+                PortStatus::Filtered => ProbeOutcome::Timeout,
+                _ => ProbeOutcome::Reply(result.rtt),
+            });
+        }
+        result
+    }
+}
+
+/// Scan machinery shared by every phase of a scan.
+pub(crate) struct ScanContext {
+    pub(crate) config: Arc<Config>,
+    pub(crate) strategy: Arc<Strategy>,
+    pub(crate) scanner: Arc<Vec<ScanType>>,
+    pub(crate) limits: Arc<Limits>,
+    pub(crate) stats: Arc<ScanStats>,
+    pub(crate) app_state_manager: Arc<AppStateManager>,
+    pub(crate) pause_controller: PauseController,
+    pub(crate) term_handle: TermHandle,
+}
+
+/// Ports of every phase for one protocol, whose frequency table is `ranked`.
+/// A phase leaves out ports an earlier phase already scanned. Hosts with their
+/// own ports (`own`) scan those instead of `configured`, and never leave them
+/// in the other phases either.
+fn phase_port_lists(phases: &[ScanPhase], configured: &PortList, own: Option<&PortList>, ranked: &[u16], shuffle_seed: Option<u64>) -> Vec<PortList> {
+    let allowed: Option<HashSet<u16>> = own.map(|own| own.ports.iter().copied().collect());
+    let mut scanned = HashSet::new();
+    phases.iter()
+        .map(|phase| {
+            let mut ports: Vec<u16> = phase.ports(own.unwrap_or(configured), ranked).ports.into_iter()
+                .filter(|port| allowed.as_ref().is_none_or(|allowed| allowed.contains(port)))
+                .filter(|port| scanned.insert(*port))
+                .collect();
+            if let Some(seed) = shuffle_seed {
+                // Seeded so a resumed scan walks the ports in the same order.
+                ports.shuffle(&mut StdRng::seed_from_u64(seed));
+            }
+            PortList { ports }
+        })
+        .collect()
 }
 
 pub async fn start_mass_scan(
     config: Arc<Config>,
     modes: Arc<Vec<ScanType>>,
+    resume: ResumePoint,
+    deadline: Option<Instant>,
 ) {
-    let hosts = config.targets.clone();
-    let ports = config.ports.clone();
-    let number_of_targets = hosts.len() * ports.len() * modes.len();
-    let targets = config.scan_strategy.create_targets(&config.targets, &config.ports);
-
-    let scanner: Arc<Vec<ScanType>> = modes;
-    let mut ratelimit: u64 = 1000;
-
-    if let Some(config_ratelimit) = config.ratelimit {
-        ratelimit = config_ratelimit;
-    } else if let Some(ratelimit_per_host) = config.ratelimit_per_host {
-        ratelimit = ratelimit_per_host * hosts.len() as u64;
-    } else if let Some(maximum_scan_time) = &config.maximum_scan_time {
-        match parse_duration::parse(maximum_scan_time) {
-            Ok(duration) => {
-                ratelimit = (number_of_targets as f64 / duration.as_secs() as f64).ceil() as u64;
-            }
-            Err(e) => {
-                error!("Invalid maximum scan time: {}", e);
-                return;
+    let phases = if config.phases.is_empty() {
+        vec![ScanPhase::Ports]
+    } else {
+        config.phases.clone()
+    };
+    let strategy = Arc::new(Strategy::build(&config.scan_strategy, resume.seed, &config.port_frequencies));
+    if resume.phase >= phases.len() {
+        error!("Resume file is for phase {}, but only {} phases are configured", resume.phase + 1, phases.len());
+        return;
+    }
+    // The plan of a phase: its hosts grouped by port set, with that phase's ports
+    // for each protocol that has scan types. Top phases use the protocol's own
+    // frequency table, and `T:`/`U:` ports only go to their protocol.
+    let shuffle_seed = resume.seed.filter(|_| config.shuffle_ports);
+    let protocols: Vec<Protocol> = [Protocol::Tcp, Protocol::Udp].into_iter()
+        .filter(|protocol| probes_per_target(&modes, Some(*protocol)) > 0)
+        .collect();
+    let most_asked = phases.iter()
+        .filter_map(|phase| match phase {
+            ScanPhase::Top(n) => Some(*n),
+            _ => None,
+        })
+        .chain(config.top_ports)
+        .max();
+    for protocol in &protocols {
+        let ranked = config.port_frequencies.ranked(*protocol).len();
+        if let Some(n) = most_asked.filter(|&n| n > ranked) {
+            warn!("Top {} ports asked for, but only {} {} ports are ranked: those are scanned. Give nmap's nmap-services file with --services-file to rank more",
+                n, ranked, protocol.as_str().to_ascii_uppercase());
+        }
+    }
+    // Workers split the first phase; later phases follow up the hosts that
+    // answered this worker, so none of them is left out.
+    let shard = config.total_workers.zip(config.worker_id).map(|(total, id)| Shard {
+        id: id as u64,
+        total: total as u64,
+        seed: resume.seed.unwrap_or_default(),
+    });
+    let phase_plan = |hosts: &TargetList, phase: usize| {
+        let mut groups = Vec::new();
+        for (own, group) in hosts.port_groups() {
+            let spec = own.as_deref().unwrap_or(&config.ports);
+            for protocol in &protocols {
+                let configured = spec.for_protocol(*protocol);
+                let ranked = config.port_frequencies.ranked(*protocol);
+                let mut ports = phase_port_lists(&phases, &configured, own.is_some().then_some(&configured), ranked, shuffle_seed)
+                    .swap_remove(phase);
+                if let Some(excluded) = &config.exclude_ports {
+                    let excluded = excluded.mask(*protocol);
+                    ports.ports.retain(|port| !excluded[*port as usize]);
+                }
+                let ports = strategy.prepare_ports(ports, Some(*protocol));
+                groups.push(PlanGroup { hosts: group.clone(), ports, protocol: Some(*protocol) });
             }
         }
+        let plan = ScanPlan::new(groups);
+        match shard {
+            Some(shard) if phase == 0 => plan.sharded(shard),
+            _ => plan,
+        }
+    };
+
+    // Hosts of the first phase to run: later phases only scan hosts that answered before.
+    let mut hosts = match &resume.hosts {
+        Some(hosts) if resume.phase > 0 => config.targets.restrict_to(&parse_addresses(hosts)),
+        _ => config.targets.clone(),
+    };
+    let first_plan = phase_plan(&hosts, resume.phase);
+    if let Err(e) = first_plan.check_size() {
+        error!("{}", e);
+        return;
     }
+    let number_of_targets = first_plan.probes_from(resume.position, |p| probes_per_target(&modes, p));
 
-    let shared_limiter: SharedLimiter = Arc::new(std::sync::RwLock::new(make_limiter(ratelimit)));
+    // Ceiling the rate may never exceed, and the rate the scan starts at.
+    let rate_ceiling = config.ratelimit.or_else(|| {
+        // No global cap given: allow every host to run at its own limit at once.
+        config.ratelimit_per_host.map(|r| r.saturating_mul(hosts.len()))
+    });
+    let mut ratelimit = rate_ceiling.unwrap_or(DEFAULT_RATELIMIT);
+    if let Some(deadline) = deadline {
+        let time_left = deadline.saturating_duration_since(Instant::now()).as_secs_f64().max(1.0);
+        let needed = (number_of_targets as f64 / time_left).ceil().max(1.0) as u64;
+        ratelimit = rate_ceiling.map_or(needed, |c| needed.min(c));
+    }
 
-    let filtered_count = Arc::new(AtomicU64::new(0));
-    let total_count = Arc::new(AtomicU64::new(0));
-    if config.adaptive {
-        spawn_adaptive_task(
-            Arc::clone(&shared_limiter),
-            Arc::clone(&filtered_count),
-            Arc::clone(&total_count),
-            ratelimit,
-        );
+    if config.dry_run {
+        // Later phases depend on responses: plan them for every host as an upper bound.
+        let plans: Vec<(usize, ScanPlan)> = (resume.phase..phases.len())
+            .map(|phase| (phase, phase_plan(&hosts, phase)))
+            .collect();
+        if let Some(e) = plans.iter().find_map(|(_, plan)| plan.check_size().err()) {
+            error!("{}", e);
+            return;
+        }
+        dry_run::print_summary(&config, &phases, &plans, resume.position, |p| probes_per_target(&modes, Some(p)), &strategy, ratelimit);
+        if let Some(path) = &config.plan_output {
+            match dry_run::write_plan(path, &plans, &strategy, resume.position) {
+                Ok(count) if path != "-" => info!("Wrote {} planned targets to {}", count, path),
+                Ok(_) => {}
+                Err(e) => error!("{}", e),
+            }
+        }
+        return;
     }
 
+    // Listen before anything else starts, so a taken address fails right away.
+    let listener = match &config.coordinator {
+        Some(address) => match TcpListener::bind(address).await {
+            Ok(listener) => {
+                let port = listener.local_addr().map_or(0, |a| a.port());
+                info!("Coordinator listening on {}: start workers with `carescanner worker --coordinator <this host>:{}`", address, port);
+                if config.coordinator_token.is_none() {
+                    warn!("No --coordinator-token: anything that reaches {} can join as a worker and report results", address);
+                }
+                Some(listener)
+            }
+            Err(e) => {
+                error!("Failed to listen on {}: {}", address, e);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let limits = Arc::new(Limits::new(&config, ratelimit));
+    let stats = Arc::new(ScanStats::new(resume.seed));
+
     info!(
         "Starting scan for {} targets with {} concurrent scans and {} scans/sec limit.",
         number_of_targets,
         config.max_concurrent_ports,
         ratelimit
     );
+    for (limit, _) in &limits.subnet_limits {
+        info!("Subnet limit: {}", limit);
+    }
+    if limits.host_limits.is_enabled() {
+        info!(
+            "Per-host limits: {} scans/sec, {} concurrent scans.",
+            config.ratelimit_per_host.map_or("unlimited".to_string(), |r| r.to_string()),
+            config.max_concurrent_per_host.map_or("unlimited".to_string(), |m| m.to_string()),
+        );
+    }
 
-    let app_state_manager = Arc::new(AppStateManager::new());
-    let results_sender = app_state_manager.get_results_sender();
+    let mut initial_state = AppState::new();
+    if let Some(excluded) = &config.exclude_ports {
+        initial_state.set_excluded_ports(excluded.to_string());
+    }
+    let app_state_manager = Arc::new(AppStateManager::with_state(initial_state));
 
     // Start web dashboard if requested
     if let Some(port) = config.dashboard {
-        web_dashboard::spawn_dashboard(&config.dashboard_host, port, number_of_targets as u64, Arc::clone(&app_state_manager));
+        web_dashboard::spawn_dashboard(&config.dashboard_host, port, phases.len(), Arc::clone(&app_state_manager), Arc::clone(&stats));
     }
 
     // Setup TermController (owns ALL terminal I/O) and PauseController (signal-hook)
@@ -225,67 +606,110 @@ pub async fn start_mass_scan(
         pause_controller.clone(),
         Arc::clone(&app_state_manager),
         Arc::clone(&config),
+        Arc::clone(&stats),
     );
-    term_handle.set_total(number_of_targets as u64);
-
-    let quiet = config.quiet;
-    let scan_future = stream::iter(targets)
-        .for_each_concurrent(config.max_concurrent_ports as usize, |target_to_scan| {
-            let scanner_clone = Arc::clone(&scanner);
-            let shared_limiter_clone = Arc::clone(&shared_limiter);
-            let results_sender_clone = results_sender.clone();
-            let pause = pause_controller.clone();
-            let fc = Arc::clone(&filtered_count);
-            let tc = Arc::clone(&total_count);
-            let th = term_handle.clone();
-            async move {
-                for scan_type in scanner_clone.iter() {
-                    pause.wait_if_paused().await;
-                    if pause.should_exit() {
-                        return;
-                    }
-
-                    let limiter = shared_limiter_clone.read().unwrap().clone();
-                    limiter.until_ready().await;
 
-                    let result = ScanTypeTrait::scan(scan_type, &target_to_scan).await;
-
-                    tc.fetch_add(1, Ordering::Relaxed);
-                    if matches!(result.status, PortStatus::Filtered) {
-                        fc.fetch_add(1, Ordering::Relaxed);
-                    }
-                    let is_open = matches!(result.status, PortStatus::Open);
+    let ctx = ScanContext {
+        config: Arc::clone(&config),
+        strategy: Arc::clone(&strategy),
+        scanner: Arc::clone(&modes),
+        limits,
+        stats: Arc::clone(&stats),
+        app_state_manager: Arc::clone(&app_state_manager),
+        pause_controller: pause_controller.clone(),
+        term_handle: term_handle.clone(),
+    };
+    let coordinator = listener.map(|listener| Coordinator::start(listener, &ctx));
 
-                    let service_display = if is_open {
-                        result.banner.as_deref()
-                            .and_then(|b| crate::service_detection::identify(b, target_to_scan.port))
-                            .and_then(|info| info.version.or_else(|| Some(info.name.to_string())))
-                    } else {
-                        None
-                    };
-
-                    results_sender_clone.send((target_to_scan.clone(), result, scan_type.protocol().to_string())).unwrap();
-
-                    if is_open && !quiet {
-                        let msg = match &service_display {
-                            Some(svc) => format!("Open: {}:{}/{} ({})", &target_to_scan.ip, &target_to_scan.port, scan_type.protocol(), svc),
-                            None => format!("Open: {}:{}/{}", &target_to_scan.ip, &target_to_scan.port, scan_type.protocol()),
-                        };
-                        th.message(msg);
-                    }
+    // Set when the deadline stops a phase: its plan.
+    let mut unfinished: Option<ScanPlan> = None;
+    for phase_index in resume.phase..phases.len() {
+        let resuming = phase_index == resume.phase;
+        if phase_index > 0 && hosts.is_empty() {
+            info!("No hosts answered phase {}, skipping the remaining phases", phase_index);
+            break;
+        }
+        let plan = phase_plan(&hosts, phase_index);
+        // Later phases are planned for the hosts that answered, so are only checked now.
+        if let Err(e) = plan.check_size() {
+            error!("Phase {}: {}", phase_index + 1, e);
+            break;
+        }
+        let total_targets = plan.len();
+        let skip = if resuming { resume.position.min(total_targets) } else { 0 };
+        let phase_probes = plan.probes_from(skip, |p| probes_per_target(&ctx.scanner, p));
 
-                    th.inc(1);
-                }
-            }
+        // Later phases only hold the hosts that answered, so listing them stays small.
+        let phase_hosts = (phase_index > 0).then(|| {
+            let mut seen = HashSet::new();
+            hosts.iter().filter(|ip| seen.insert(*ip)).map(|ip| ip.to_string()).collect()
         });
+        stats.start_phase(phase_index, phase_hosts, skip, phase_probes);
+        if resuming {
+            for host in &resume.responded {
+                stats.host_responded(host);
+            }
+        }
+        if phases.len() > 1 {
+            let mut size = match plan.groups() {
+                [group] => format!("{} hosts x {} ports", group.hosts.len(), group.ports.len()),
+                _ => format!("{} hosts, {} targets (several port lists)", hosts.len(), total_targets),
+            };
+            if plan.shard().is_some() {
+                size.push_str(&format!(", {} for this worker", total_targets));
+            }
+            term_handle.phase(
+                format!("Phase {}/{} ({}): {}", phase_index + 1, phases.len(), phases[phase_index], size),
+                phase_probes,
+            );
+        } else {
+            term_handle.set_total(phase_probes);
+        }
 
-    // Scan runs until all tasks complete (or exit early via should_exit)
-    scan_future.await;
+        // With a coordinator, workers get their share of this rate as it moves.
+        let deadline_task = deadline.map(|deadline| spawn_deadline_task(
+            deadline,
+            phase_probes,
+            ratelimit,
+            rate_ceiling.unwrap_or(ratelimit.saturating_mul(DEADLINE_MAX_SPEEDUP)),
+            Arc::clone(&stats),
+            Arc::clone(&ctx.limits),
+        ));
+        let track_responses = phase_index + 1 < phases.len();
+        let deadline_hit = match &coordinator {
+            Some(coordinator) => coordinator.run_phase(phase_index, plan.clone(), skip, track_responses, deadline).await,
+            None => scan_phase(&ctx, &plan, skip, track_responses, deadline).await,
+        };
+        if let Some(task) = deadline_task {
+            task.abort();
+        }
+        if deadline_hit {
+            pause_controller.request_exit();
+            unfinished = Some(plan);
+            break;
+        }
+        if pause_controller.should_exit() {
+            break;
+        }
+        hosts = config.targets.restrict_to(&parse_addresses(&stats.responded_hosts()));
+    }
+    if let Some(coordinator) = coordinator
+        && unfinished.is_none()
+        && !pause_controller.should_exit()
+    {
+        coordinator.finish().await;
+    }
+    drop(ctx);
 
-    let exited_early = pause_controller.should_exit();
+    let deadline_hit = unfinished.is_some();
+    let exited_early = pause_controller.should_exit() && !deadline_hit;
+
+    if let Some(status) = stats.status_line() {
+        info!("Final scan status: {}", status);
+    }
 
     // Signal the TermController to stop
-    if exited_early {
+    if exited_early || deadline_hit {
         term_handle.exit_early();
     } else {
         term_handle.finish();
@@ -297,7 +721,52 @@ pub async fn start_mass_scan(
     // --- Post-scan (TermController is done, safe to write to terminal) ---
 
     tokio::task::yield_now().await;
-    let state = app_state_manager.get_current_state().await;
+    if config.reverse_dns && !exited_early {
+        let hosts: Vec<std::net::IpAddr> = app_state_manager.get_current_state().await
+            .hosts_with_results().iter()
+            .filter_map(|host| host.parse().ok())
+            .collect();
+        if !hosts.is_empty() {
+            let names = dns::reverse_lookup(&hosts, &config).await;
+            info!("Found PTR names for {} of {} hosts", names.len(), hosts.len());
+            app_state_manager.add_ptr_names(names).await;
+        }
+    }
+    let mut state = app_state_manager.get_current_state().await;
+    if let Some(shard) = shard {
+        let plan = phase_plan(&config.targets, 0);
+        let stopped_at = unfinished.as_ref().map(|_| stats.resume_point()).filter(|point| point.phase == 0);
+        state.set_shard(ShardReport {
+            worker_id: shard.id,
+            total_workers: shard.total,
+            seed: shard.seed,
+            plan_targets: plan.unsharded_len(),
+            targets: plan.len(),
+            scanned: stopped_at.map_or(plan.len(), |point| point.position),
+        });
+    }
+
+    if let Some(plan) = &unfinished {
+        let point = stats.resume_point();
+        let total_targets = plan.len();
+        let left = total_targets - point.position;
+        let next = plan.target_at(&*strategy, point.position)
+            .map(|t| format!(", next: {}", t.socket_addr()))
+            .unwrap_or_default();
+        let phase = if phases.len() > 1 {
+            format!(" in phase {}/{}", point.phase + 1, phases.len())
+        } else {
+            String::new()
+        };
+        eprintln!(
+            "Maximum scan time reached: {} of {} targets left unscanned{}{}",
+            left, total_targets, phase, next,
+        );
+        match stats.save_resume_file(ui::RESUME_FILE) {
+            Ok(()) => eprintln!("Continue with: --resume-from {}", ui::RESUME_FILE),
+            Err(e) => eprintln!("Error saving resume file: {}", e),
+        }
+    }
 
     if !exited_early {
         let piping_stdout = config.output.as_deref() == Some("-");
@@ -313,17 +782,161 @@ pub async fn start_mass_scan(
         }
 
         let run_nmap = config.nmap || !config.nmap_args.is_empty();
-        if run_nmap {
+        if run_nmap && deadline_hit {
+            eprintln!("Skipping nmap: maximum scan time reached");
+        } else if run_nmap {
             let nmap_args = if config.nmap_args.is_empty() {
                 vec!["-sV".to_string(), "-sC".to_string()]
             } else {
                 config.nmap_args.clone()
             };
-            nmap::run_on_results(&state, &nmap_args, &config.nmap_path);
+            nmap::run_on_results(&state, &nmap_args, &config.nmap_path, deadline);
         }
     }
 }
 
+/// Scan the targets of `plan` from target `skip` on. Returns true if the deadline
+/// stopped the phase, dropping the probes still in flight.
+async fn scan_phase(
+    ctx: &ScanContext,
+    plan: &ScanPlan,
+    skip: u64,
+    track_responses: bool,
+    deadline: Option<Instant>,
+) -> bool {
+    let config = &ctx.config;
+    let host_tracker = Arc::new(HostTracker::new(config.host_abort_after));
+    let results_sender = ctx.app_state_manager.get_results_sender();
+    let quiet = config.quiet;
+
+    let targets = plan.targets_from(&*ctx.strategy, skip);
+    // Report the port frequency tier as the scan reaches it.
+    let targets = {
+        let strategy = Arc::clone(&ctx.strategy);
+        let stats = Arc::clone(&ctx.stats);
+        let mut tier = None;
+        targets.inspect(move |(_, target, protocol)| {
+            let reached = strategy.tier(target.port, *protocol);
+            if reached != tier && let Some(name) = reached {
+                stats.set_tier(name);
+            }
+            tier = reached;
+        })
+    };
+    // Skip the remaining ports of hosts marked down, whatever the strategy's order,
+    // and re-check the scope and exclusions on the address that is actually about to be probed.
+    let targets = {
+        let host_tracker = Arc::clone(&host_tracker);
+        let stats = Arc::clone(&ctx.stats);
+        let th = ctx.term_handle.clone();
+        let config = Arc::clone(&ctx.config);
+        let scanner = Arc::clone(&ctx.scanner);
+        targets.filter(move |(index, target, protocol)| {
+            if may_probe(&config, target) && !host_tracker.is_down(&target.ip) {
+                return true;
+            }
+            let probes = probes_per_target(&scanner, *protocol);
+            stats.complete_target(*index);
+            stats.probes_done.fetch_add(probes, Ordering::Relaxed);
+            th.inc(probes);
+            false
+        })
+    };
+    let scan_future = stream::iter(targets)
+        .for_each_concurrent(ctx.limits.targets_in_hand(config.max_concurrent_ports as usize), |(index, target_to_scan, protocol)| {
+            let scanner_clone = Arc::clone(&ctx.scanner);
+            let limits_clone = Arc::clone(&ctx.limits);
+            let stats_clone = Arc::clone(&ctx.stats);
+            let host_tracker_clone = Arc::clone(&host_tracker);
+            let app_state_manager_clone = Arc::clone(&ctx.app_state_manager);
+            let results_sender_clone = results_sender.clone();
+            let pause = ctx.pause_controller.clone();
+            let th = ctx.term_handle.clone();
+            let last_for_host = plan.last_for_host(&*ctx.strategy, index);
+            async move {
+                let scan_types = scanner_clone.iter().filter(|s| protocol.is_none_or(|p| s.protocol() == p.as_str()));
+                for scan_type in scan_types {
+                    pause.wait_if_paused().await;
+                    if pause.should_exit() {
+                        return;
+                    }
+                    if host_tracker_clone.is_down(&target_to_scan.ip) {
+                        stats_clone.probes_done.fetch_add(1, Ordering::Relaxed);
+                        th.inc(1);
+                        continue;
+                    }
+
+                    let result = limits_clone.probe(scan_type, &target_to_scan, &stats_clone).await;
+                    // Silent UDP ports are normal, so only TCP timeouts count towards "down".
+                    let replied = !matches!(result.status, PortStatus::Filtered);
+                    if replied && track_responses && !host_tracker_clone.responded(&target_to_scan.ip) {
+                        stats_clone.host_responded(&target_to_scan.ip);
+                    }
+                    if (replied || scan_type.protocol() == "tcp")
+                        && host_tracker_clone.record(&target_to_scan.ip, replied)
+                    {
+                        app_state_manager_clone.mark_host_down(target_to_scan.ip.clone()).await;
+                        if !quiet {
+                            th.message(format!(
+                                "Down: {} ({} timeouts, no replies), skipping its remaining ports",
+                                target_to_scan.ip,
+                                host_tracker_clone.abort_after().unwrap_or_default(),
+                            ));
+                        }
+                    }
+                    if matches!(result.status, PortStatus::Open) && !quiet {
+                        th.message(open_message(&target_to_scan, scan_type.protocol(), result.banner.as_deref()));
+                    }
+                    results_sender_clone.send((target_to_scan.clone(), result, scan_type.protocol().to_string())).unwrap();
+
+                    stats_clone.probes_done.fetch_add(1, Ordering::Relaxed);
+                    th.inc(1);
+                }
+                if last_for_host {
+                    host_tracker_clone.finish_host(&target_to_scan.ip);
+                }
+                stats_clone.complete_target(index);
+            }
+        });
+
+    // Scan runs until all tasks complete (or exit early via should_exit),
+    // or until the deadline, which drops the probes still in flight.
+    match deadline {
+        Some(deadline) => tokio::select! {
+            _ = scan_future => false,
+            _ = tokio::time::sleep_until(deadline.into()) => true,
+        },
+        None => {
+            scan_future.await;
+            false
+        }
+    }
+}
+
+/// Re-check the scope and exclusions on the address that is actually about to be probed.
+pub(crate) fn may_probe(config: &Config, target: &Target) -> bool {
+    if config.scope_file.as_ref().is_some_and(|s| !s.allows_str(&target.ip)) {
+        warn!("Out of scope, refused: {}", target.socket_addr());
+        return false;
+    }
+    if config.exclude.as_ref().is_some_and(|e| e.contains_str(&target.ip)) {
+        debug!("Skipping excluded address {}", target.socket_addr());
+        return false;
+    }
+    true
+}
+
+/// Progress message for an open port, with the service its banner identifies.
+pub(crate) fn open_message(target: &Target, protocol: &str, banner: Option<&str>) -> String {
+    let service = banner
+        .and_then(|b| crate::service_detection::identify(b, target.port))
+        .and_then(|info| info.version.or_else(|| Some(info.name.to_string())));
+    match service {
+        Some(svc) => format!("Open: {}:{}/{} ({})", target.ip, target.port, protocol, svc),
+        None => format!("Open: {}:{}/{}", target.ip, target.port, protocol),
+    }
+}
+
 #[cfg(unix)]
 pub fn increase_ulimit(new_size: u64) -> u64 {
     use rlimit::Resource;
@@ -356,6 +969,9 @@ pub fn increase_ulimit(new_size: u64) -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clap::Parser;
+    use crate::strategy::PlanGroup;
+    use crate::configuration::top_ports::{TOP_PORTS, TOP_UDP_PORTS};
 
     #[test]
     fn test_increase_ulimit() {
@@ -371,4 +987,68 @@ mod tests {
         #[cfg(unix)]
         assert!(large_result >= 1024);
     }
+
+    #[test]
+    fn later_phases_skip_ports_already_scanned() {
+        let configured = PortList { ports: vec![22, 80, 8080] };
+        let phases = [ScanPhase::Top(3), ScanPhase::Ports, ScanPhase::Full];
+        let lists = phase_port_lists(&phases, &configured, None, TOP_PORTS, None);
+        assert_eq!(lists[0].ports, TOP_PORTS[..3].to_vec());
+        assert!(lists[1].ports.iter().all(|p| !lists[0].ports.contains(p)));
+        assert!(lists[1].ports.contains(&8080));
+        assert_eq!(lists.iter().map(|l| l.len()).sum::<usize>(), 65535);
+
+        let shuffled = phase_port_lists(&phases, &configured, None, TOP_PORTS, Some(5));
+        assert_eq!(shuffled[1].ports, phase_port_lists(&phases, &configured, None, TOP_PORTS, Some(5))[1].ports);
+        assert_eq!(shuffled[2].len(), lists[2].len());
+
+        let own = PortList { ports: vec![443, 80, 9999] };
+        let lists = phase_port_lists(&phases, &configured, Some(&own), TOP_PORTS, None);
+        assert_eq!(lists[0].ports, [80, 443]);
+        assert_eq!(lists[1].ports, [9999]);
+        assert!(lists[2].is_empty());
+    }
+
+    #[test]
+    fn top_phases_use_each_protocols_table() {
+        let configured = PortList { ports: vec![53, 161, 8080] };
+        let phases = [ScanPhase::Top(2), ScanPhase::Ports];
+        let udp = phase_port_lists(&phases, &configured, None, TOP_UDP_PORTS, None);
+        assert_eq!(udp[0].ports, [631, 161]);
+        assert_eq!(udp[1].ports, [53, 8080]);
+        let tcp = phase_port_lists(&phases, &configured, None, TOP_PORTS, None);
+        assert_eq!(tcp[0].ports, [80, 23]);
+        assert_eq!(tcp[1].ports, [53, 161, 8080]);
+    }
+
+    #[tokio::test]
+    async fn a_host_held_back_by_its_limit_leaves_the_others_running() {
+        let config = Arc::new(Config::parse_from(["carescanner", "-t", "127.0.0.2", "--ratelimit-per-host", "4", "--max-concurrent-ports", "2"]));
+        let ctx = ScanContext {
+            strategy: Arc::new(Strategy::build(&config.scan_strategy, None, &config.port_frequencies)),
+            scanner: Arc::new(config.scan_type.iter().cloned().map(|s| ScanType::build(s, &config)).collect()),
+            limits: Arc::new(Limits::new(&config, 100_000)),
+            stats: Arc::new(ScanStats::default()),
+            app_state_manager: Arc::new(AppStateManager::new()),
+            pause_controller: PauseController::new(),
+            term_handle: TermHandle::detached(),
+            config,
+        };
+        // The slow host's 20 targets come first; at 4/s they take 5s.
+        let idle = ["127.0.0.3", "127.0.0.4", "127.0.0.5", "127.0.0.6"];
+        let plan = ScanPlan::new(vec![
+            PlanGroup { hosts: TargetList::from_hosts(&["127.0.0.2"]), ports: PortList { ports: (1..=20).collect() }, protocol: Some(Protocol::Tcp) },
+            PlanGroup { hosts: TargetList::from_hosts(&idle), ports: PortList { ports: vec![1, 2] }, protocol: Some(Protocol::Tcp) },
+        ]);
+        ctx.stats.start_phase(0, None, 0, 0);
+
+        let _ = tokio::time::timeout(Duration::from_millis(1500), scan_phase(&ctx, &plan, 0, false, None)).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let state = ctx.app_state_manager.get_current_state().await;
+        let results = state.get_results();
+        for host in idle {
+            assert_eq!(results.get(host).map_or(0, |ports| ports.len()), 2, "{} waited for the slow host", host);
+        }
+        assert!(results["127.0.0.2"].len() < 20);
+    }
 }
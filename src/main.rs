@@ -1,10 +1,24 @@
 use carescanner::configuration::Config;
+use carescanner::distributed::worker::{run_worker, WorkerArgs};
 use clap::Parser;
-use log::debug;
+use log::{debug, error};
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() {
-    let config = Config::parse();
+    // `carescanner worker --coordinator HOST:PORT` takes its options from the coordinator.
+    if std::env::args().nth(1).as_deref() == Some("worker") {
+        let args = WorkerArgs::parse_from(std::env::args().skip(1));
+        colog::basic_builder()
+            .filter_level(args.logging_level.clone().into())
+            .init();
+        if let Err(e) = run_worker(args).await {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let config = Config::load();
 
     colog::basic_builder()
         .filter_level(config.logging_level.clone().into())
@@ -3,15 +3,24 @@ pub mod fulltcp;
 pub mod sockstcp;
 pub mod udp;
 pub mod ping;
+pub mod tls;
 
 use async_trait::async_trait;
 use crate::configuration::Config;
 use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Target {
     pub ip: String,
     pub port: u16,
+    /// Name the address was resolved from, used for the HTTP Host header and TLS SNI.
+    pub hostname: Option<String>,
+    /// Other names that resolved to the same address: it is probed once, as
+    /// `hostname`, and reported under all of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
 }
 
 impl Target {
@@ -25,7 +34,8 @@ impl Target {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum PortStatus {
     Open,
     Filtered,
@@ -36,17 +46,24 @@ pub enum PortStatus {
 pub struct ScanResult {
     pub status: PortStatus,
     pub banner: Option<String>,
+    /// Time until the target answered, if it did.
+    pub rtt: Option<Duration>,
 }
 
 impl ScanResult {
     pub fn open(banner: Option<String>) -> Self {
-        Self { status: PortStatus::Open, banner }
+        Self { status: PortStatus::Open, banner, rtt: None }
     }
     pub fn closed() -> Self {
-        Self { status: PortStatus::Closed, banner: None }
+        Self { status: PortStatus::Closed, banner: None, rtt: None }
     }
     pub fn filtered() -> Self {
-        Self { status: PortStatus::Filtered, banner: None }
+        Self { status: PortStatus::Filtered, banner: None, rtt: None }
+    }
+
+    pub fn with_rtt(mut self, rtt: Duration) -> Self {
+        self.rtt = Some(rtt);
+        self
     }
 }
 
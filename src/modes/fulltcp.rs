@@ -1,11 +1,13 @@
-use tokio::io::{AsyncWriteExt, AsyncReadExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio::time::Duration;
-use crate::modes::{ScanTypeTrait, Target, ScanResult};
+use tokio::time::{Duration, Instant};
+use crate::modes::{ScanTypeTrait, Target, ScanResult, tls};
 use crate::configuration::Config;
 use async_trait::async_trait;
 
 const HTTP_PORTS: &[u16] = &[80, 443, 8080, 8443, 8000, 8888, 8081, 3000];
+/// Ports spoken to over TLS, with the hostname as SNI.
+const TLS_PORTS: &[u16] = &[443, 8443];
 const BANNER_TIMEOUT_MS: u64 = 2000;
 const MAX_BANNER_LEN: usize = 1024;
 
@@ -36,32 +38,57 @@ impl ScanTypeTrait for TcpScan {
     }
 
     async fn scan(&self, target: &Target) -> ScanResult {
+        let started = Instant::now();
         let stream = tokio::time::timeout(
             Duration::from_secs(self.timeout),
             TcpStream::connect(target.socket_addr()),
         )
         .await;
 
+        let rtt = started.elapsed();
+
         match stream {
             Ok(Ok(mut stream)) => {
                 let banner = if self.grab_banner {
-                    grab_banner(&mut stream, target).await
+                    grab_banner(stream, target).await
                 } else {
+                    let _ = stream.shutdown().await;
                     None
                 };
-                let _ = stream.shutdown().await;
-                ScanResult::open(banner)
+                ScanResult::open(banner).with_rtt(rtt)
             }
-            Ok(Err(_)) => ScanResult::closed(),
+            Ok(Err(_)) => ScanResult::closed().with_rtt(rtt),
             Err(_) => ScanResult::filtered(),
         }
     }
 }
 
-async fn grab_banner(stream: &mut TcpStream, target: &Target) -> Option<String> {
+async fn grab_banner(mut stream: TcpStream, target: &Target) -> Option<String> {
+    if TLS_PORTS.contains(&target.port) {
+        let handshake = tokio::time::timeout(
+            Duration::from_millis(BANNER_TIMEOUT_MS),
+            tls::connect(stream, target),
+        )
+        .await;
+        let Ok(Ok(mut stream)) = handshake else { return None };
+        let banner = read_banner(&mut stream, target).await;
+        let _ = stream.shutdown().await;
+        return banner;
+    }
+    let banner = read_banner(&mut stream, target).await;
+    let _ = stream.shutdown().await;
+    banner
+}
+
+async fn read_banner<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, target: &Target) -> Option<String> {
     // For HTTP ports, send a minimal request to trigger a response.
     if HTTP_PORTS.contains(&target.port) {
-        let request = format!("GET / HTTP/1.0\r\nHost: {}\r\n\r\n", target.ip);
+        let host = match &target.hostname {
+            Some(hostname) => hostname.clone(),
+            None if target.ip.contains(':') => format!("[{}]", target.ip),
+            None => target.ip.clone(),
+        };
+        let request = format!("GET / HTTP/1.0\r\nHost: {}\r\n\r\n", host);
         if stream.write_all(request.as_bytes()).await.is_err() {
             return None;
         }
@@ -1,22 +1,23 @@
+use std::net::IpAddr;
 use std::sync::Arc;
 use futures::stream::{self, StreamExt};
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio::time::Duration;
-use tokio_socks::tcp::socks5::Socks5Stream;
-use log::{info, debug};
+use log::{info, debug, warn};
 
-use crate::configuration::{Config, ProxyStrategy};
-use crate::configuration::top_ports::TOP_PORTS;
+use crate::configuration::{Config, Protocol, ProxyStrategy};
+use crate::modes::sockstcp;
 
 const DEFAULT_PING_PORTS: usize = 10;
 
 /// Discover alive hosts by attempting TCP connections to top ports.
 /// A host is "alive" if any port responds (open or connection-refused/RST).
 /// Timeouts on all ports = host is considered down.
-pub async fn discover_hosts(config: &Config) -> Vec<String> {
-    let hosts = &config.targets.targets;
-    let ping_ports: Vec<u16> = TOP_PORTS.iter().take(DEFAULT_PING_PORTS).copied().collect();
+/// Returns the alive addresses, sorted.
+pub async fn discover_hosts(config: &Config) -> Vec<IpAddr> {
+    let hosts = &config.targets;
+    let ping_ports: Vec<u16> = config.port_frequencies.top(Protocol::Tcp, DEFAULT_PING_PORTS).to_vec();
     let timeout = Duration::from_secs(config.timeout);
     let concurrency = config.max_concurrent_ports as usize;
 
@@ -25,29 +26,33 @@ pub async fn discover_hosts(config: &Config) -> Vec<String> {
 
     info!("Ping scan: checking {} hosts via {} top ports", hosts.len(), ping_ports.len());
 
-    let results: Vec<(String, bool)> = stream::iter(hosts.iter().cloned())
-        .map(|host| {
+    // The targets are already limited to the scope; check each address again anyway.
+    let in_scope = |ip: &IpAddr| {
+        let allowed = config.scope_file.as_ref().is_none_or(|scope| scope.allows(*ip));
+        if !allowed {
+            warn!("Out of scope, refused: {}", ip);
+        }
+        allowed
+    };
+    let mut alive: Vec<IpAddr> = stream::iter(hosts.iter().filter(in_scope))
+        .map(|ip| {
             let ping_ports = ping_ports.clone();
-            let timeout = timeout;
             let config = Arc::clone(&config);
             async move {
+                let host = ip.to_string();
                 let alive = if use_proxy {
                     ping_host_proxy(&host, &ping_ports, timeout, &config).await
                 } else {
                     ping_host_direct(&host, &ping_ports, timeout).await
                 };
-                (host, alive)
+                alive.then_some(ip)
             }
         })
         .buffer_unordered(concurrency)
+        .filter_map(std::future::ready)
         .collect()
         .await;
-
-    let alive: Vec<String> = results
-        .into_iter()
-        .filter(|(_, alive)| *alive)
-        .map(|(host, _)| host)
-        .collect();
+    alive.sort();
 
     info!("Ping scan complete: {}/{} hosts alive", alive.len(), hosts.len());
     alive
@@ -94,6 +99,7 @@ async fn ping_host_proxy(host: &str, ports: &[u16], timeout: Duration, config: &
         ProxyStrategy::Sequential | ProxyStrategy::Random => &proxies[0],
         ProxyStrategy::Chain => &proxies[0], // For ping, just use first proxy
     };
+    let auth = config.proxy_auth();
 
     for port in ports {
         let target_addr = if host.contains(':') {
@@ -103,7 +109,7 @@ async fn ping_host_proxy(host: &str, ports: &[u16], timeout: Duration, config: &
         };
         match tokio::time::timeout(
             timeout,
-            Socks5Stream::connect(proxy.as_str(), target_addr.as_str()),
+            sockstcp::connect(proxy.as_str(), target_addr.as_str(), auth.as_ref()),
         )
         .await
         {
@@ -1,10 +1,11 @@
-use crate::configuration::{Config, ProxyStrategy, ProxyList};
+use crate::configuration::{Config, ProxyAuth, ProxyStrategy, ProxyList};
 use crate::modes::{ScanResult, ScanTypeTrait, Target};
 use tokio_socks::tcp::socks5::Socks5Stream;
 use async_trait::async_trait;
 use tokio::sync::Mutex;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
 use log::debug;
 use rand::prelude::IndexedRandom;
 
@@ -17,6 +18,7 @@ pub struct Socks5TcpScan {
     pub timeout: u64,
     pub socks5_proxies: ProxyList,
     pub proxy_strategy: ProxyStrategy,
+    auth: Option<ProxyAuth>,
     offset: Mutex<usize>,
 }
 
@@ -29,6 +31,7 @@ impl Socks5TcpScan {
             timeout: config.timeout,
             socks5_proxies,
             proxy_strategy: config.proxy_strategy.clone().unwrap_or(ProxyStrategy::Sequential),
+            auth: config.proxy_auth(),
             offset: Mutex::new(0),
         }
     }
@@ -36,18 +39,19 @@ impl Socks5TcpScan {
     /// Scan a target through a single SOCKS5 proxy.
     async fn scan_single_proxy(&self, target: &Target, proxy: &str) -> ScanResult {
         let target_addr = target.socket_addr();
+        let started = Instant::now();
 
         let result = tokio::time::timeout(
             Duration::from_secs(self.timeout),
-            Socks5Stream::connect(proxy, target_addr.as_str()),
+            connect(proxy, target_addr.as_str(), self.auth.as_ref()),
         )
         .await;
 
         match result {
-            Ok(Ok(_)) => ScanResult::open(None),
+            Ok(Ok(_)) => ScanResult::open(None).with_rtt(started.elapsed()),
             Ok(Err(e)) => {
                 debug!("SOCKS5 connection error for {}:{} via {}: {}", target.ip, target.port, proxy, e);
-                ScanResult::closed()
+                ScanResult::closed().with_rtt(started.elapsed())
             }
             Err(_) => ScanResult::filtered(),
         }
@@ -64,43 +68,58 @@ impl Socks5TcpScan {
         let target_addr = target.socket_addr();
         // Scale timeout with chain length
         let timeout = Duration::from_secs(self.timeout * proxies.len() as u64);
+        let started = Instant::now();
 
         let result = tokio::time::timeout(timeout, async {
-            chain_connect(proxies, &target_addr).await
+            chain_connect(proxies, &target_addr, self.auth.as_ref()).await
         })
         .await;
 
         match result {
-            Ok(Ok(())) => ScanResult::open(None),
+            Ok(Ok(())) => ScanResult::open(None).with_rtt(started.elapsed()),
             Ok(Err(e)) => {
                 debug!("SOCKS5 chain error for {}:{}: {}", target.ip, target.port, e);
-                ScanResult::closed()
+                ScanResult::closed().with_rtt(started.elapsed())
             }
             Err(_) => ScanResult::filtered(),
         }
     }
 }
 
+/// Connect to target through one SOCKS5 proxy, authenticating if credentials are set.
+pub(crate) async fn connect(proxy: &str, target_addr: &str, auth: Option<&ProxyAuth>) -> Result<Socks5Stream<TcpStream>, tokio_socks::Error> {
+    match auth {
+        Some(auth) => Socks5Stream::connect_with_password(proxy, target_addr, &auth.username, auth.password()).await,
+        None => Socks5Stream::connect(proxy, target_addr).await,
+    }
+}
+
+/// Like `connect`, over an existing stream to the proxy (the previous hop).
+async fn connect_with_socket(socket: Box<dyn BoxableStream>, target_addr: &str, auth: Option<&ProxyAuth>) -> Result<Socks5Stream<Box<dyn BoxableStream>>, tokio_socks::Error> {
+    match auth {
+        Some(auth) => Socks5Stream::connect_with_password_and_socket(socket, target_addr, &auth.username, auth.password()).await,
+        None => Socks5Stream::connect_with_socket(socket, target_addr).await,
+    }
+}
+
 /// Connect to target through a chain of SOCKS5 proxies.
-async fn chain_connect(proxies: &[String], target_addr: &str) -> Result<(), tokio_socks::Error> {
+async fn chain_connect(proxies: &[String], target_addr: &str, auth: Option<&ProxyAuth>) -> Result<(), tokio_socks::Error> {
     if proxies.len() == 1 {
-        Socks5Stream::connect(proxies[0].as_str(), target_addr).await?;
+        connect(proxies[0].as_str(), target_addr, auth).await?;
         return Ok(());
     }
 
     // First hop: connect through proxy[0] to proxy[1]
-    let stream = Socks5Stream::connect(proxies[0].as_str(), proxies[1].as_str()).await?;
+    let stream = connect(proxies[0].as_str(), proxies[1].as_str(), auth).await?;
     let mut boxed: Box<dyn BoxableStream> = Box::new(stream);
 
     // Chain through remaining proxies (proxy[2], proxy[3], ...)
     for proxy in &proxies[2..] {
-        boxed = Box::new(
-            Socks5Stream::connect_with_socket(boxed, proxy.as_str()).await?,
-        );
+        boxed = Box::new(connect_with_socket(boxed, proxy.as_str(), auth).await?);
     }
 
     // Final hop: through the last proxy to the actual target
-    Socks5Stream::connect_with_socket(boxed, target_addr).await?;
+    connect_with_socket(boxed, target_addr, auth).await?;
     Ok(())
 }
 
@@ -0,0 +1,91 @@
+// TLS for banner grabbing: connects with the target's hostname as SNI and
+// accepts any certificate, since we only want to see what the service says.
+
+use std::sync::{Arc, OnceLock};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::CryptoProvider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use crate::modes::Target;
+
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn connector() -> &'static TlsConnector {
+    static CONNECTOR: OnceLock<TlsConnector> = OnceLock::new();
+    CONNECTOR.get_or_init(|| {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default TLS versions")
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth();
+        TlsConnector::from(Arc::new(config))
+    })
+}
+
+/// Server name for the handshake: the hostname (sent as SNI) or, without one, the address (no SNI).
+fn server_name(target: &Target) -> Option<ServerName<'static>> {
+    match &target.hostname {
+        Some(hostname) => ServerName::try_from(hostname.clone()).ok(),
+        None => target.ip.parse::<std::net::IpAddr>().ok().map(ServerName::from),
+    }
+}
+
+pub async fn connect(stream: TcpStream, target: &Target) -> std::io::Result<TlsStream<TcpStream>> {
+    let name = server_name(target)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no usable TLS server name"))?;
+    connector().connect(name, stream).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sni_only_for_hostnames() {
+        let mut target = Target { ip: "93.184.216.34".to_string(), port: 443, hostname: Some("example.com".to_string()), aliases: Vec::new() };
+        assert!(matches!(server_name(&target), Some(ServerName::DnsName(name)) if name.as_ref() == "example.com"));
+        target.hostname = None;
+        assert!(matches!(server_name(&target), Some(ServerName::IpAddress(_))));
+    }
+}
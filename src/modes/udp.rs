@@ -1,5 +1,5 @@
 use tokio::net::UdpSocket;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 use crate::modes::{ScanTypeTrait, Target, ScanResult};
 use crate::configuration::Config;
 use async_trait::async_trait;
@@ -42,6 +42,7 @@ impl ScanTypeTrait for UdpScan {
             return ScanResult::filtered();
         }
 
+        let started = Instant::now();
         let mut buf = [0; 1024];
         let response = tokio::time::timeout(
             Duration::from_secs(self.timeout),
@@ -50,8 +51,8 @@ impl ScanTypeTrait for UdpScan {
         .await;
 
         match response {
-            Ok(Ok(_)) => ScanResult::open(None),
-            Ok(Err(_)) => ScanResult::closed(),
+            Ok(Ok(_)) => ScanResult::open(None).with_rtt(started.elapsed()),
+            Ok(Err(_)) => ScanResult::closed().with_rtt(started.elapsed()),
             Err(_) => ScanResult::filtered(),
         }
     }
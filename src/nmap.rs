@@ -1,4 +1,5 @@
 use std::process::Command;
+use std::time::Instant;
 use log::info;
 
 use crate::appstate::{AppState, PortState};
@@ -6,8 +7,9 @@ use crate::appstate::{AppState, PortState};
 const MAX_PARALLEL_NMAP: usize = 4;
 
 /// Run nmap on discovered open ports for service/script detection.
-/// Spawns up to MAX_PARALLEL_NMAP processes concurrently.
-pub fn run_on_results(state: &AppState, nmap_args: &[String], nmap_path: &str) {
+/// Spawns up to MAX_PARALLEL_NMAP processes concurrently. With a deadline,
+/// each run gets a `--host-timeout` for the time left and no new runs start after it.
+pub fn run_on_results(state: &AppState, nmap_args: &[String], nmap_path: &str, deadline: Option<Instant>) {
     let results = state.get_results();
 
     // Collect hosts with open ports
@@ -34,15 +36,24 @@ pub fn run_on_results(state: &AppState, nmap_args: &[String], nmap_path: &str) {
     println!("\n--- Running nmap on {} hosts (up to {} parallel) ---\n", tasks.len(), MAX_PARALLEL_NMAP);
 
     // Process in chunks for parallel execution
+    let mut done = 0;
     for chunk in tasks.chunks(MAX_PARALLEL_NMAP) {
         let mut children: Vec<(String, std::process::Child)> = Vec::new();
 
+        let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()).as_secs());
+        if left == Some(0) {
+            eprintln!("Maximum scan time reached: skipping nmap for {} remaining hosts", tasks.len() - done);
+            return;
+        }
+        let args = run_args(nmap_args, left);
+        done += chunk.len();
+
         // Spawn all in chunk
         for (host, port_arg) in chunk {
-            println!("Starting: {} {} -p {} {}", nmap_path, nmap_args.join(" "), port_arg, host);
+            println!("Starting: {} {} -p {} {}", nmap_path, args.join(" "), port_arg, host);
 
             match Command::new(nmap_path)
-                .args(nmap_args)
+                .args(&args)
                 .arg("-p")
                 .arg(port_arg)
                 .arg(host)
@@ -73,3 +84,37 @@ pub fn run_on_results(state: &AppState, nmap_args: &[String], nmap_path: &str) {
         }
     }
 }
+
+/// The user's nmap arguments, with `--host-timeout <secs>s` (nmap's separate
+/// form) added for `time_left` unless they already set a host timeout.
+fn run_args(nmap_args: &[String], time_left: Option<u64>) -> Vec<String> {
+    let mut args = nmap_args.to_vec();
+    let has_timeout = args.iter().any(|a| a == "--host-timeout" || a.starts_with("--host-timeout="));
+    if let Some(left) = time_left.filter(|_| !has_timeout) {
+        args.push("--host-timeout".to_string());
+        args.push(format!("{}s", left));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn deadline_adds_a_host_timeout_in_nmaps_form() {
+        let generated = run_args(&args(&["-sV"]), Some(90));
+        assert_eq!(generated, ["-sV", "--host-timeout", "90s"]);
+        // What nmap parses: the option, then its value as the next argument.
+        let at = generated.iter().position(|a| a == "--host-timeout").unwrap();
+        assert!(generated[at + 1].trim_end_matches('s').parse::<u64>().is_ok());
+
+        assert_eq!(run_args(&args(&["-sV"]), None), ["-sV"]);
+        assert_eq!(run_args(&args(&["--host-timeout", "5m"]), Some(90)), ["--host-timeout", "5m"]);
+        assert_eq!(run_args(&args(&["--host-timeout=5m", "-sC"]), Some(90)), ["--host-timeout=5m", "-sC"]);
+    }
+}
@@ -0,0 +1,472 @@
+//! Rate control: an adjustable pacing limiter, an AIMD congestion controller
+//! that retunes it from per-probe outcomes and RTT samples, and keyed limits
+//! that cap the rate and concurrency per host or per subnet.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use log::debug;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+/// Outcomes needed before a round is evaluated.
+const MIN_ROUND_SAMPLES: u64 = 20;
+/// Lower bound on round length, so tiny RTTs don't cause rapid-fire decisions.
+const MIN_ROUND_TIME: Duration = Duration::from_millis(250);
+/// Timeout ratio above the filtering baseline that is treated as packet loss.
+const LOSS_MARGIN: f64 = 0.2;
+/// Smoothed RTT this many times above the minimum RTT of its path signals queueing.
+const RTT_INFLATION: f64 = 2.0;
+/// Paths whose RTT baselines are kept; past this they are learned afresh.
+const MAX_PATHS: usize = 65_536;
+/// Queueing delay below this is ignored (jitter on low-latency links).
+const MIN_QUEUE_DELAY: f64 = 0.010;
+/// EWMA weight of a clean round in the filtering baseline.
+const BASELINE_WEIGHT: f64 = 0.1;
+/// Additive increase per round, as a fraction of the maximum rate.
+const ADDITIVE_INCREASE: f64 = 0.05;
+/// Minimum rate as a fraction of the maximum rate.
+const MIN_RATE_DIVISOR: u64 = 20;
+/// Keyed rate limiter state for idle keys is pruned every this many acquisitions.
+const KEYED_CLEANUP_INTERVAL: u64 = 10_000;
+
+/// Pacing limiter whose rate can be changed while tasks are waiting on it.
+/// Each caller reserves the next free slot, so changing the rate keeps the
+/// schedule instead of resetting a bucket.
+pub struct AdjustableLimiter {
+    interval_nanos: AtomicU64,
+    next_slot: Mutex<Instant>,
+}
+
+impl AdjustableLimiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            interval_nanos: AtomicU64::new(interval_for(rate)),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        self.interval_nanos.store(interval_for(rate), Ordering::Relaxed);
+    }
+
+    pub fn rate(&self) -> u64 {
+        (NANOS_PER_SEC / self.interval_nanos.load(Ordering::Relaxed) as f64).round() as u64
+    }
+
+    pub async fn until_ready(&self) {
+        let now = Instant::now();
+        let slot = {
+            let mut next = self.next_slot.lock().unwrap();
+            let slot = (*next).max(now);
+            *next = slot + Duration::from_nanos(self.interval_nanos.load(Ordering::Relaxed));
+            slot
+        };
+        if slot > now {
+            tokio::time::sleep_until(slot.into()).await;
+        }
+    }
+}
+
+fn interval_for(rate: u64) -> u64 {
+    (NANOS_PER_SEC / rate.max(1) as f64) as u64
+}
+
+/// Rate and concurrency limits applied per key (e.g. per target IP).
+/// Idle keys are dropped so memory follows the number of active hosts.
+pub struct KeyedLimits {
+    rate: Option<DefaultKeyedRateLimiter<String>>,
+    max_concurrent: Option<usize>,
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+    acquired: AtomicU64,
+}
+
+/// Held for the duration of a probe; releases the key's concurrency slot on drop.
+pub struct KeyedPermit<'a> {
+    limits: &'a KeyedLimits,
+    key: String,
+    slot: Option<(Arc<Semaphore>, OwnedSemaphorePermit)>,
+    waited: bool,
+}
+
+impl KeyedLimits {
+    pub fn new(rate: Option<u64>, max_concurrent: Option<usize>) -> Self {
+        Self {
+            rate: rate.map(|r| {
+                let r = NonZeroU32::new(r.clamp(1, u32::MAX as u64) as u32).unwrap();
+                RateLimiter::keyed(Quota::per_second(r).allow_burst(NonZeroU32::MIN))
+            }),
+            max_concurrent: max_concurrent.map(|m| m.max(1)),
+            semaphores: Mutex::new(HashMap::new()),
+            acquired: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.rate.is_some() || self.max_concurrent.is_some()
+    }
+
+    /// Wait for a concurrency slot and a rate token for `key`.
+    pub async fn acquire(&self, key: &str) -> KeyedPermit<'_> {
+        let key = key.to_string();
+        let mut waited = false;
+
+        let slot = match self.max_concurrent {
+            Some(max) => {
+                let semaphore = Arc::clone(
+                    self.semaphores.lock().unwrap()
+                        .entry(key.clone())
+                        .or_insert_with(|| Arc::new(Semaphore::new(max))),
+                );
+                let permit = match Arc::clone(&semaphore).try_acquire_owned() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        waited = true;
+                        Arc::clone(&semaphore).acquire_owned().await.unwrap()
+                    }
+                };
+                Some((semaphore, permit))
+            }
+            None => None,
+        };
+
+        if let Some(rate) = &self.rate {
+            if rate.check_key(&key).is_err() {
+                waited = true;
+                rate.until_key_ready(&key).await;
+            }
+            if self.acquired.fetch_add(1, Ordering::Relaxed).is_multiple_of(KEYED_CLEANUP_INTERVAL) {
+                rate.retain_recent();
+            }
+        }
+
+        KeyedPermit { limits: self, key, slot, waited }
+    }
+}
+
+impl KeyedPermit<'_> {
+    /// Whether this probe had to wait for the key's limits.
+    pub fn waited(&self) -> bool {
+        self.waited
+    }
+}
+
+impl Drop for KeyedPermit<'_> {
+    fn drop(&mut self) {
+        if let Some((semaphore, permit)) = self.slot.take() {
+            drop(permit);
+            let mut semaphores = self.limits.semaphores.lock().unwrap();
+            // One reference in the map, one here: nobody else holds or waits on it.
+            if Arc::strong_count(&semaphore) == 2 {
+                semaphores.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// What a single probe told us about the path to the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeOutcome {
+    /// The target answered (open or closed), with the measured round-trip time.
+    Reply(Option<Duration>),
+    /// No answer before the timeout: filtered, dead, or lost.
+    Timeout,
+}
+
+/// RTT baseline of one path: the /24 (IPv4) or /64 (IPv6) a host is in.
+struct PathRtt {
+    srtt: f64,
+    min_rtt: f64,
+}
+
+impl PathRtt {
+    fn is_inflated(&self) -> bool {
+        self.srtt > self.min_rtt * RTT_INFLATION && self.srtt - self.min_rtt > MIN_QUEUE_DELAY
+    }
+}
+
+struct ControlState {
+    rate: f64,
+    ssthresh: f64,
+    min_rate: f64,
+    max_rate: f64,
+    /// Smoothed RTT over every path: only sets the round length.
+    srtt: Option<f64>,
+    paths: HashMap<IpAddr, PathRtt>,
+    baseline_timeout_ratio: Option<f64>,
+    round_replies: u64,
+    round_timeouts: u64,
+    /// RTT samples of the round, and those whose path was inflated.
+    round_rtts: u64,
+    round_inflated: u64,
+    round_start: Instant,
+}
+
+/// AIMD congestion controller. Outcomes are grouped into rounds of roughly one
+/// smoothed RTT; at the end of each round the rate is halved on a loss or delay
+/// signal, otherwise grown (doubling below `ssthresh`, additively above it).
+///
+/// Timeouts alone are not treated as loss: a stable share of filtered ports
+/// becomes the baseline, and only a jump above it counts as congestion. RTTs
+/// are judged against the minimum of their own path, so a mix of near and far
+/// hosts is not mistaken for queueing.
+pub struct CongestionController {
+    limiter: Arc<AdjustableLimiter>,
+    state: Mutex<ControlState>,
+}
+
+impl CongestionController {
+    pub fn new(limiter: Arc<AdjustableLimiter>, max_rate: u64) -> Self {
+        let max_rate = max_rate.max(1) as f64;
+        let min_rate = (max_rate / MIN_RATE_DIVISOR as f64).max(10.0).min(max_rate);
+        Self {
+            limiter,
+            state: Mutex::new(ControlState {
+                rate: max_rate,
+                ssthresh: max_rate,
+                min_rate,
+                max_rate,
+                srtt: None,
+                paths: HashMap::new(),
+                baseline_timeout_ratio: None,
+                round_replies: 0,
+                round_timeouts: 0,
+                round_rtts: 0,
+                round_inflated: 0,
+                round_start: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.state.lock().unwrap().rate as u64
+    }
+
+    pub fn max_rate(&self) -> u64 {
+        self.state.lock().unwrap().max_rate as u64
+    }
+
+    /// Move the ceiling (e.g. to catch up with a deadline). Growth continues from the current rate.
+    pub fn set_max_rate(&self, max_rate: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.max_rate = (max_rate.max(1) as f64).max(state.min_rate);
+        if state.rate > state.max_rate {
+            state.rate = state.max_rate;
+            self.limiter.set_rate(state.rate as u64);
+        }
+        state.ssthresh = state.ssthresh.min(state.max_rate);
+    }
+
+    /// Count the outcome of a probe of `host`.
+    pub fn on_outcome(&self, host: &str, outcome: ProbeOutcome) {
+        let mut state = self.state.lock().unwrap();
+        match outcome {
+            ProbeOutcome::Reply(rtt) => {
+                state.round_replies += 1;
+                if let Some(rtt) = rtt {
+                    let sample = rtt.as_secs_f64();
+                    state.srtt = Some(state.srtt.map_or(sample, |srtt| smooth(srtt, sample)));
+                    if let Some(path) = path_of(host) {
+                        if state.paths.len() >= MAX_PATHS && !state.paths.contains_key(&path) {
+                            state.paths.clear();
+                        }
+                        let path = state.paths.entry(path).or_insert(PathRtt { srtt: sample, min_rtt: sample });
+                        path.srtt = smooth(path.srtt, sample);
+                        path.min_rtt = path.min_rtt.min(sample);
+                        let inflated = path.is_inflated();
+                        state.round_rtts += 1;
+                        state.round_inflated += inflated as u64;
+                    }
+                }
+            }
+            ProbeOutcome::Timeout => state.round_timeouts += 1,
+        }
+
+        let samples = state.round_replies + state.round_timeouts;
+        let round_time = state.srtt
+            .map(Duration::from_secs_f64)
+            .unwrap_or(MIN_ROUND_TIME)
+            .max(MIN_ROUND_TIME);
+        if samples < MIN_ROUND_SAMPLES || state.round_start.elapsed() < round_time {
+            return;
+        }
+
+        let new_rate = end_round(&mut state);
+        self.limiter.set_rate(new_rate as u64);
+    }
+}
+
+fn smooth(srtt: f64, sample: f64) -> f64 {
+    0.875 * srtt + 0.125 * sample
+}
+
+/// The /24 (IPv4) or /64 (IPv6) of `host`: hosts sharing one are taken to share a path.
+fn path_of(host: &str) -> Option<IpAddr> {
+    Some(match host.parse().ok()? {
+        IpAddr::V4(ip) => IpAddr::V4((u32::from(ip) & !0xff).into()),
+        IpAddr::V6(ip) => IpAddr::V6((u128::from(ip) & !(u64::MAX as u128)).into()),
+    })
+}
+
+/// Evaluate a finished round and return the new rate.
+fn end_round(state: &mut ControlState) -> f64 {
+    let total = (state.round_replies + state.round_timeouts) as f64;
+    let timeout_ratio = state.round_timeouts as f64 / total;
+    // Most of the round's samples came back late for their own path.
+    let queueing = state.round_inflated * 2 > state.round_rtts;
+    state.round_replies = 0;
+    state.round_timeouts = 0;
+    state.round_rtts = 0;
+    state.round_inflated = 0;
+    state.round_start = Instant::now();
+
+    let loss = match state.baseline_timeout_ratio {
+        Some(baseline) => timeout_ratio > baseline + LOSS_MARGIN,
+        None => false,
+    };
+
+    let old_rate = state.rate;
+    if loss && !queueing && state.rate <= state.min_rate {
+        // Already at the floor and still timing out: this is the network's
+        // filtering level, not congestion we can back off from.
+        state.baseline_timeout_ratio = Some(timeout_ratio);
+        debug!("Congestion: at minimum rate, new filtering baseline {:.1}%", timeout_ratio * 100.0);
+    } else if loss || queueing {
+        state.ssthresh = (state.rate / 2.0).max(state.min_rate);
+        state.rate = state.ssthresh;
+        // Forget the inflated RTTs so the next round judges the new rate on its own.
+        for path in state.paths.values_mut() {
+            path.srtt = path.min_rtt;
+        }
+        debug!(
+            "Congestion: {} -> rate {:.0} -> {:.0} scans/sec (timeout ratio {:.1}%, baseline {:.1}%, srtt {:?})",
+            if loss { "loss" } else { "queueing delay" },
+            old_rate,
+            state.rate,
+            timeout_ratio * 100.0,
+            state.baseline_timeout_ratio.unwrap_or(0.0) * 100.0,
+            state.srtt.map(Duration::from_secs_f64),
+        );
+    } else {
+        state.baseline_timeout_ratio = Some(match state.baseline_timeout_ratio {
+            Some(baseline) => (1.0 - BASELINE_WEIGHT) * baseline + BASELINE_WEIGHT * timeout_ratio,
+            None => timeout_ratio,
+        });
+        state.rate = if state.rate < state.ssthresh {
+            (state.rate * 2.0).min(state.ssthresh)
+        } else {
+            state.rate + state.max_rate * ADDITIVE_INCREASE
+        }
+        .min(state.max_rate);
+        if state.rate != old_rate {
+            debug!(
+                "Congestion: increase rate {:.0} -> {:.0} scans/sec (timeout ratio {:.1}%, ssthresh {:.0})",
+                old_rate, state.rate, timeout_ratio * 100.0, state.ssthresh,
+            );
+        }
+    }
+    state.rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_round(controller: &CongestionController, replies: u64, timeouts: u64, rtt: Duration) {
+        controller.state.lock().unwrap().round_start = Instant::now() - MIN_ROUND_TIME * 2;
+        for _ in 0..timeouts {
+            controller.on_outcome("10.0.0.1", ProbeOutcome::Timeout);
+        }
+        for _ in 0..replies {
+            controller.on_outcome("10.0.0.1", ProbeOutcome::Reply(Some(rtt)));
+        }
+    }
+
+    #[tokio::test]
+    async fn keyed_concurrency_is_per_key_and_released() {
+        let limits = KeyedLimits::new(None, Some(1));
+
+        let first = limits.acquire("10.0.0.1").await;
+        // A different key is not blocked by the first one.
+        let _other = limits.acquire("10.0.0.2").await;
+        // The same key is blocked until the first permit is dropped.
+        let blocked = tokio::time::timeout(Duration::from_millis(50), limits.acquire("10.0.0.1")).await;
+        assert!(blocked.is_err());
+        assert!(!first.waited());
+
+        drop(first);
+        let again = tokio::time::timeout(Duration::from_millis(50), limits.acquire("10.0.0.1")).await;
+        assert!(again.as_ref().is_ok_and(|p| !p.waited()));
+        drop(again);
+        assert_eq!(limits.semaphores.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn steady_filtering_is_not_loss() {
+        let limiter = Arc::new(AdjustableLimiter::new(1000));
+        let controller = CongestionController::new(Arc::clone(&limiter), 1000);
+
+        // 90% filtered ports on every round: the baseline absorbs it.
+        for _ in 0..5 {
+            run_round(&controller, 2, 18, Duration::from_millis(20));
+        }
+        assert_eq!(controller.rate(), 1000);
+    }
+
+    #[test]
+    fn timeout_spike_halves_rate_then_recovers_additively() {
+        let limiter = Arc::new(AdjustableLimiter::new(1000));
+        let controller = CongestionController::new(Arc::clone(&limiter), 1000);
+
+        run_round(&controller, 18, 2, Duration::from_millis(20));
+        run_round(&controller, 4, 16, Duration::from_millis(20));
+        assert_eq!(controller.rate(), 500);
+        assert_eq!(limiter.rate(), 500);
+
+        run_round(&controller, 18, 2, Duration::from_millis(20));
+        assert_eq!(controller.rate(), 550);
+    }
+
+    #[test]
+    fn rtt_inflation_halves_rate() {
+        let limiter = Arc::new(AdjustableLimiter::new(1000));
+        let controller = CongestionController::new(Arc::clone(&limiter), 1000);
+
+        run_round(&controller, 20, 0, Duration::from_millis(10));
+        run_round(&controller, 40, 0, Duration::from_millis(200));
+        assert_eq!(controller.rate(), 500);
+    }
+
+    #[test]
+    fn near_and_far_hosts_are_not_queueing() {
+        let limiter = Arc::new(AdjustableLimiter::new(1000));
+        let controller = CongestionController::new(Arc::clone(&limiter), 1000);
+
+        // A LAN at 1ms and a WAN at 80ms, interleaved, without loss.
+        for _ in 0..10 {
+            controller.state.lock().unwrap().round_start = Instant::now() - Duration::from_secs(1);
+            for i in 0..40 {
+                let (host, rtt) = if i % 2 == 0 { ("192.168.1.7", 1) } else { ("203.0.113.9", 80) };
+                controller.on_outcome(host, ProbeOutcome::Reply(Some(Duration::from_millis(rtt))));
+            }
+        }
+        assert_eq!(controller.rate(), 1000);
+    }
+
+    #[test]
+    fn rate_never_drops_below_minimum() {
+        let limiter = Arc::new(AdjustableLimiter::new(1000));
+        let controller = CongestionController::new(Arc::clone(&limiter), 1000);
+
+        run_round(&controller, 20, 0, Duration::from_millis(10));
+        for _ in 0..6 {
+            run_round(&controller, 0, 20, Duration::from_millis(10));
+        }
+        assert_eq!(controller.rate(), 50);
+    }
+}
@@ -1,9 +1,9 @@
-/// Service identification from banner strings.
-///
-/// Level 1: simple string matching (fast, no dependencies).
-/// Level 2: regex-based probe matching via `service_probes` module.
-///
-/// The `identify()` function tries Level 2 first, then falls back to Level 1.
+//! Service identification from banner strings.
+//!
+//! Level 1: simple string matching (fast, no dependencies).
+//! Level 2: regex-based probe matching via `service_probes` module.
+//!
+//! The `identify()` function tries Level 2 first, then falls back to Level 1.
 
 use crate::service_probes;
 
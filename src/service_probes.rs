@@ -1,7 +1,7 @@
-/// Level 2 service detection: nmap-style probe-based matching with regex patterns.
-///
-/// Instead of parsing nmap-service-probes at runtime, we embed the most common
-/// probes and match patterns as static data. Regexes are compiled lazily on first use.
+//! Level 2 service detection: nmap-style probe-based matching with regex patterns.
+//!
+//! Instead of parsing nmap-service-probes at runtime, we embed the most common
+//! probes and match patterns as static data. Regexes are compiled lazily on first use.
 
 use regex::Regex;
 use std::sync::LazyLock;
@@ -345,10 +345,10 @@ pub fn match_banner(probe: &ServiceProbe, banner: &str) -> Option<ProbeResult> {
 /// banner (so we try HTTP patterns), false for a passive/NULL read.
 pub fn identify_from_probes(banner: &str, is_http_response: bool) -> Option<ProbeResult> {
     // If this looks like an HTTP response, try HTTP patterns first.
-    if is_http_response || banner.starts_with("HTTP/") {
-        if let Some(result) = match_banner(&PROBES[1], banner) {
-            return Some(result);
-        }
+    if (is_http_response || banner.starts_with("HTTP/"))
+        && let Some(result) = match_banner(&PROBES[1], banner)
+    {
+        return Some(result);
     }
 
     // Try NULL probe patterns (SSH, FTP, SMTP, etc.)
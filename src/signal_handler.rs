@@ -11,6 +11,12 @@ pub struct PauseController {
     resume_notify: Arc<Notify>,
 }
 
+impl Default for PauseController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PauseController {
     pub fn new() -> Self {
         let signal_flag = Arc::new(AtomicBool::new(false));
@@ -48,6 +54,10 @@ impl PauseController {
         self.exit_flag.load(Ordering::SeqCst)
     }
 
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
     pub async fn wait_if_paused(&self) {
         while self.paused.load(Ordering::SeqCst) {
             self.resume_notify.notified().await;
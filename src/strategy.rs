@@ -1,30 +1,395 @@
 // Strategy is a method of scanning - host-first, random port order host-first, round-robin, etc.
 pub mod round_robin;
 pub mod host_first;
+pub mod random;
+pub mod port_priority;
 
 use crate::modes::Target;
 use crate::configuration::TargetList;
-use crate::configuration::{self, PortList};
+use crate::configuration::{PortList, Protocol, ScanStrategy};
+use crate::configuration::top_ports::PortFrequencies;
 use enum_dispatch::enum_dispatch;
 
 #[enum_dispatch] 
 pub trait ScanStrategyTrait {
+    /// Put the ports of `protocol` in the order this strategy visits them.
+    fn prepare_ports(&self, ports: PortList, _protocol: Option<Protocol>) -> PortList {
+        ports
+    }
+
+    /// Progress label for reaching `port`, for strategies whose order means something.
+    fn tier(&self, _port: u16, _protocol: Option<Protocol>) -> Option<&'static str> {
+        None
+    }
+
+    /// The target at `index` in this strategy's order, or None past the end.
+    fn target_at(&self, hosts: &TargetList, ports: &PortList, index: u64) -> Option<Target>;
+
+    /// Whether the target at `index` is the last this strategy visits on its host,
+    /// so per-host state can be dropped. False where that is not cheap to tell.
+    fn last_for_host(&self, _hosts: &TargetList, _ports: &PortList, _index: u64) -> bool {
+        false
+    }
+
+    /// Targets from `start` on, with their index. Nothing is materialised up front,
+    /// so resuming deep into a huge range costs no more than starting at 0.
+    fn targets_from<'a>(
+        &'a self,
+        hosts: &'a TargetList,
+        ports: &'a PortList,
+        start: u64,
+    ) -> Box<dyn Iterator<Item = (u64, Target)> + 'a> {
+        // Plans too large to count are refused up front (`ScanPlan::check_size`).
+        let Some(total) = hosts.len().checked_mul(ports.len() as u64) else {
+            return Box::new(std::iter::empty());
+        };
+        Box::new((start..total).filter_map(move |index| self.target_at(hosts, ports, index).map(|t| (index, t))))
+    }
+
     fn create_targets<'a>(
         &'a self,
         hosts:  &'a TargetList,
         ports:  &'a PortList,
-    ) -> Box<dyn Iterator<Item = Target> + 'a>;
+    ) -> Box<dyn Iterator<Item = Target> + 'a> {
+        Box::new(self.targets_from(hosts, ports, 0).map(|(_, target)| target))
+    }
 }
 
-impl ScanStrategyTrait for configuration::ScanStrategy {
-    fn create_targets<'a>(
+#[enum_dispatch(ScanStrategyTrait)]
+pub enum Strategy {
+    HostFirst(host_first::HostFirstStrategy),
+    RoundRobin(round_robin::RoundRobinStrategy),
+    Random(random::RandomStrategy),
+    PortPriority(port_priority::PortPriorityStrategy),
+}
+
+impl Strategy {
+    /// `seed` orders the random strategy and `frequencies` the port-priority one;
+    /// the others ignore them.
+    pub fn build(name: &ScanStrategy, seed: Option<u64>, frequencies: &PortFrequencies) -> Self {
+        match name {
+            ScanStrategy::HostFirst => Strategy::HostFirst(host_first::HostFirstStrategy),
+            ScanStrategy::RoundRobin => Strategy::RoundRobin(round_robin::RoundRobinStrategy),
+            ScanStrategy::Random => Strategy::Random(random::RandomStrategy::new(seed.unwrap_or_default())),
+            ScanStrategy::PortPriority => Strategy::PortPriority(port_priority::PortPriorityStrategy::new(frequencies)),
+        }
+    }
+}
+
+/// Hosts scanned on the same ports, and the protocol those ports are limited to
+/// (`None`: every scan type).
+#[derive(Debug, Clone)]
+pub struct PlanGroup {
+    pub hosts: TargetList,
+    pub ports: PortList,
+    pub protocol: Option<Protocol>,
+}
+
+/// One worker's part of a plan split across `total` workers. Each group's
+/// host × port targets are permuted with `seed`, and the worker takes every
+/// `total`-th position of the whole plan from `id` on, so every worker gets an
+/// even share of every host and port however the targets are laid out, and
+/// shares differ by one target at most however many small groups there are.
+/// All workers must plan the same targets with the same seed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shard {
+    pub id: u64,
+    pub total: u64,
+    pub seed: u64,
+}
+
+impl Shard {
+    /// This worker's first position in a group starting at `offset` in the whole plan.
+    fn first(&self, offset: u64) -> u64 {
+        (self.id + self.total - offset % self.total) % self.total
+    }
+
+    /// How many of the `size` targets of a group starting at `offset` this worker gets.
+    fn share(&self, offset: u64, size: u64) -> u64 {
+        size.saturating_sub(self.first(offset)).div_ceil(self.total)
+    }
+}
+
+/// What a phase scans: one group per distinct port set and protocol (hosts with
+/// their own ports from a target file, `T:`/`U:` ports, and the rest with the
+/// global list). Groups are scanned one after the other in the strategy's
+/// order, and indexes run across all of them, so progress, resume positions and
+/// deadline reports count the real targets. A sharded plan only holds this
+/// worker's targets, visited in permuted order.
+#[derive(Debug, Clone, Default)]
+pub struct ScanPlan {
+    groups: Vec<PlanGroup>,
+    /// Index of the first target of each group.
+    offsets: Vec<u64>,
+    /// Index of the first target of each group over every worker, which decides
+    /// the worker each target goes to.
+    unsharded_offsets: Vec<u64>,
+    shard: Option<Shard>,
+}
+
+impl PlanGroup {
+    fn len(&self) -> u64 {
+        self.hosts.len().saturating_mul(self.ports.len() as u64)
+    }
+}
+
+/// Where each of consecutive runs of `lens` items starts.
+fn starts(lens: impl Iterator<Item = u64>) -> Vec<u64> {
+    lens.scan(0u64, |next, len| {
+        let offset = *next;
+        *next = next.saturating_add(len);
+        Some(offset)
+    })
+    .collect()
+}
+
+impl ScanPlan {
+    pub fn new(groups: Vec<PlanGroup>) -> Self {
+        let groups = groups.into_iter().filter(|g| !g.hosts.is_empty() && !g.ports.is_empty()).collect();
+        ScanPlan { groups, ..Default::default() }.with_offsets()
+    }
+
+    /// Only the targets of `shard`.
+    pub fn sharded(self, shard: Shard) -> Self {
+        ScanPlan { shard: Some(shard), ..self }.with_offsets()
+    }
+
+    fn with_offsets(mut self) -> Self {
+        self.unsharded_offsets = starts(self.groups.iter().map(PlanGroup::len));
+        self.offsets = starts((0..self.groups.len()).map(|slot| self.group_len(slot)));
+        self
+    }
+
+    /// Targets of group `slot` in this plan: all of them, or this worker's share.
+    fn group_len(&self, slot: usize) -> u64 {
+        let group = &self.groups[slot];
+        match &self.shard {
+            Some(shard) => shard.share(self.unsharded_offsets[slot], group.len()),
+            None => group.len(),
+        }
+    }
+
+    pub fn groups(&self) -> &[PlanGroup] {
+        &self.groups
+    }
+
+    pub fn shard(&self) -> Option<&Shard> {
+        self.shard.as_ref()
+    }
+
+    /// Refuse plans with more host × port targets than a u64 counts.
+    pub fn check_size(&self) -> Result<(), String> {
+        let total = self.groups.iter().map(|g| u128::from(g.hosts.len()) * g.ports.len() as u128).sum::<u128>();
+        if total <= u64::MAX as u128 {
+            return Ok(());
+        }
+        Err(format!("{} host x port targets are more than the {} a scan can count: scan fewer addresses or ports at once", total, u64::MAX))
+    }
+
+    /// Number of host × port targets, saturating at u64::MAX (see `check_size`).
+    pub fn len(&self) -> u64 {
+        match self.offsets.last() {
+            Some(offset) => offset.saturating_add(self.group_len(self.groups.len() - 1)),
+            None => 0,
+        }
+    }
+
+    /// Number of targets over every worker, the same as `len` when not sharded.
+    pub fn unsharded_len(&self) -> u64 {
+        self.groups.iter().map(PlanGroup::len).fold(0, u64::saturating_add)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Probes left from target `start` on, given the probes each target of a
+    /// protocol gets (the number of scan types for it).
+    pub fn probes_from(&self, start: u64, per_target: impl Fn(Option<Protocol>) -> u64) -> u64 {
+        self.groups.iter().zip(&self.offsets).enumerate()
+            .map(|(slot, (group, &offset))| {
+                let end = offset.saturating_add(self.group_len(slot));
+                (end - start.clamp(offset, end)).saturating_mul(per_target(group.protocol))
+            })
+            .fold(0, u64::saturating_add)
+    }
+
+    pub fn target_at<S: ScanStrategyTrait>(&self, strategy: &S, index: u64) -> Option<Target> {
+        let slot = self.offsets.partition_point(|&offset| offset <= index).checked_sub(1)?;
+        self.group_target(strategy, slot, index - self.offsets[slot])
+    }
+
+    /// Whether target `index` is the last of its host in its group. A shard visits
+    /// its targets in permuted order, so it never says so.
+    pub fn last_for_host<S: ScanStrategyTrait>(&self, strategy: &S, index: u64) -> bool {
+        if self.shard.is_some() {
+            return false;
+        }
+        let Some(slot) = self.offsets.partition_point(|&offset| offset <= index).checked_sub(1) else {
+            return false;
+        };
+        let group = &self.groups[slot];
+        strategy.last_for_host(&group.hosts, &group.ports, index - self.offsets[slot])
+    }
+
+    /// Target `index` of group `slot`, mapping a shard position into the whole group.
+    fn group_target<S: ScanStrategyTrait>(&self, strategy: &S, slot: usize, index: u64) -> Option<Target> {
+        let group = &self.groups[slot];
+        let index = match &self.shard {
+            Some(shard) => {
+                let position = index.checked_mul(shard.total)?.checked_add(shard.first(self.unsharded_offsets[slot]))?;
+                if position >= group.len() {
+                    return None;
+                }
+                random::permute(position, group.len(), shard.seed.wrapping_add(slot as u64))
+            }
+            None => index,
+        };
+        strategy.target_at(&group.hosts, &group.ports, index)
+    }
+
+    /// Targets from `start` on, with their index across all groups and their protocol.
+    pub fn targets_from<'a, S: ScanStrategyTrait>(
         &'a self,
-        hosts: &'a TargetList,
-        ports: &'a PortList,
-    ) -> Box<dyn Iterator<Item = Target> + 'a> {
-        match self {
-            configuration::ScanStrategy::HostFirst => host_first::HostFirstStrategy.create_targets(hosts, ports),
-            configuration::ScanStrategy::RoundRobin => round_robin::RoundRobinStrategy.create_targets(hosts, ports)
+        strategy: &'a S,
+        start: u64,
+    ) -> Box<dyn Iterator<Item = (u64, Target, Option<Protocol>)> + 'a> {
+        let first = self.offsets.partition_point(|&offset| offset <= start).saturating_sub(1);
+        if self.shard.is_some() {
+            return Box::new((first..self.groups.len()).flat_map(move |slot| {
+                let (group, offset) = (&self.groups[slot], self.offsets[slot]);
+                (start.saturating_sub(offset)..self.group_len(slot)).filter_map(move |index| {
+                    self.group_target(strategy, slot, index).map(|target| (offset + index, target, group.protocol))
+                })
+            }));
         }
+        Box::new(self.groups.iter().zip(&self.offsets).skip(first).flat_map(move |(group, &offset)| {
+            strategy.targets_from(&group.hosts, &group.ports, start.saturating_sub(offset))
+                .map(move |(index, target)| (offset + index, target, group.protocol))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_indexes_run_across_port_groups() {
+        let group = |hosts: &[&str], ports: Vec<u16>, protocol| PlanGroup {
+            hosts: TargetList::from_hosts(hosts),
+            ports: PortList { ports },
+            protocol,
+        };
+        let plan = ScanPlan::new(vec![
+            group(&["10.0.0.1", "10.0.0.2"], vec![80, 443], None),
+            group(&["10.0.0.9"], vec![], None),
+            group(&["10.0.0.9"], vec![53], Some(Protocol::Udp)),
+        ]);
+        assert_eq!(plan.len(), 5);
+        let strategy = round_robin::RoundRobinStrategy;
+        let all: Vec<(u64, String, Option<Protocol>)> = plan.targets_from(&strategy, 0).map(|(i, t, p)| (i, t.socket_addr(), p)).collect();
+        assert_eq!(all[3], (3, "10.0.0.2:443".to_string(), None));
+        assert_eq!(all[4], (4, "10.0.0.9:53".to_string(), Some(Protocol::Udp)));
+        let resumed: Vec<u64> = plan.targets_from(&strategy, 3).map(|(i, _, _)| i).collect();
+        assert_eq!(resumed, [3, 4]);
+        assert_eq!(plan.target_at(&strategy, 4).map(|t| t.port), Some(53));
+        assert!(plan.target_at(&strategy, 5).is_none());
+        // Two scan types for every port, one for the UDP-only group.
+        assert_eq!(plan.probes_from(3, |p| if p.is_some() { 1 } else { 2 }), 3);
+    }
+
+    #[test]
+    fn last_target_of_each_host_is_known() {
+        let plan = ScanPlan::new(vec![
+            PlanGroup { hosts: TargetList::from_hosts(&["10.0.0.1", "10.0.0.2", "10.0.0.3"]), ports: PortList { ports: vec![22, 80] }, protocol: None },
+            PlanGroup { hosts: TargetList::from_hosts(&["10.0.0.1"]), ports: PortList { ports: vec![53] }, protocol: Some(Protocol::Udp) },
+        ]);
+        let check = |strategy: &Strategy| {
+            let targets: Vec<(u64, String)> = plan.targets_from(strategy, 0).map(|(i, t, _)| (i, t.ip)).collect();
+            let last: Vec<u64> = targets.iter().filter(|(i, _)| plan.last_for_host(strategy, *i)).map(|(i, _)| *i).collect();
+            // The last index of each host within its group.
+            let expected: Vec<u64> = targets.iter()
+                .filter(|(i, ip)| !targets.iter().any(|(j, other)| j > i && other == ip && (*j < 6) == (*i < 6)))
+                .map(|(i, _)| *i)
+                .collect();
+            assert_eq!(last, expected);
+        };
+        check(&Strategy::RoundRobin(round_robin::RoundRobinStrategy));
+        check(&Strategy::HostFirst(host_first::HostFirstStrategy));
+        let random = Strategy::Random(random::RandomStrategy::new(1));
+        assert!((0..plan.len()).all(|i| !plan.last_for_host(&random, i)));
+        let shard = plan.clone().sharded(Shard { id: 0, total: 2, seed: 1 });
+        assert!((0..shard.len()).all(|i| !shard.last_for_host(&Strategy::RoundRobin(round_robin::RoundRobinStrategy), i)));
+    }
+
+    #[test]
+    fn plans_too_large_to_count_are_refused() {
+        let plan = |ports: Vec<u16>| ScanPlan::new(vec![PlanGroup {
+            hosts: "2001:db8::/65".parse::<TargetList>().unwrap(),
+            ports: PortList { ports },
+            protocol: None,
+        }]);
+        assert_eq!(plan(vec![80]).check_size(), Ok(()));
+        let too_large = plan(vec![80, 443]);
+        assert!(too_large.check_size().unwrap_err().starts_with("18446744073709551616 host x port targets"));
+        // Never a silent spin over indices that yield nothing.
+        let random = Strategy::Random(random::RandomStrategy::new(1));
+        assert!(too_large.targets_from(&random, 0).next().is_none());
+    }
+
+    #[test]
+    fn shards_split_every_group_without_overlap() {
+        let group = |hosts: &str, ports: Vec<u16>, protocol| PlanGroup {
+            hosts: hosts.parse::<TargetList>().unwrap(),
+            ports: PortList { ports },
+            protocol: Some(protocol),
+        };
+        let plan = ScanPlan::new(vec![
+            group("10.0.0.0/28", (1..=20).collect(), Protocol::Tcp),
+            group("10.0.0.1", vec![53, 161, 500], Protocol::Udp),
+        ]);
+        let strategy = host_first::HostFirstStrategy;
+        let shards: Vec<ScanPlan> = (0..3).map(|id| plan.clone().sharded(Shard { id, total: 3, seed: 7 })).collect();
+        assert_eq!(shards.iter().map(ScanPlan::len).collect::<Vec<_>>(), [108, 108, 107]);
+        assert_eq!(shards[2].unsharded_len(), 323);
+        // Each UDP target goes to a different worker.
+        assert!(shards.iter().all(|s| s.probes_from(0, |p| u64::from(p == Some(Protocol::Udp))) == 1));
+
+        let mut seen: Vec<(String, Option<Protocol>)> = shards.iter()
+            .flat_map(|s| s.targets_from(&strategy, 0).map(|(_, t, p)| (t.socket_addr(), p)).collect::<Vec<_>>())
+            .collect();
+        seen.sort_by(|a, b| a.0.cmp(&b.0));
+        seen.dedup();
+        assert_eq!(seen.len(), 323);
+
+        // Resuming a shard picks up where its own positions left off.
+        let resumed: Vec<u64> = shards[1].targets_from(&strategy, 100).map(|(i, _, _)| i).collect();
+        assert_eq!(resumed, (100..108).collect::<Vec<_>>());
+        assert_eq!(shards[1].target_at(&strategy, 104).map(|t| t.socket_addr()),
+            shards[1].targets_from(&strategy, 104).next().map(|(_, t, _)| t.socket_addr()));
+        // Another seed deals the targets out differently.
+        let other = plan.sharded(Shard { id: 0, total: 3, seed: 8 });
+        assert_ne!(other.target_at(&strategy, 0).map(|t| t.socket_addr()), shards[0].target_at(&strategy, 0).map(|t| t.socket_addr()));
+    }
+
+    #[test]
+    fn shards_stay_even_over_many_small_groups() {
+        // Hosts with ports of their own: one or two targets per group.
+        let plan = ScanPlan::new((0..40u16).map(|i| PlanGroup {
+            hosts: TargetList::from_hosts(&[format!("10.0.0.{}", i)]),
+            ports: PortList { ports: (1..=1 + i % 2).collect() },
+            protocol: None,
+        }).collect());
+        let strategy = host_first::HostFirstStrategy;
+        let shards: Vec<ScanPlan> = (0..4).map(|id| plan.clone().sharded(Shard { id, total: 4, seed: 3 })).collect();
+        assert_eq!(shards.iter().map(ScanPlan::len).collect::<Vec<_>>(), [15, 15, 15, 15]);
+
+        let mut seen: Vec<String> = shards.iter()
+            .flat_map(|s| s.targets_from(&strategy, 0).map(|(_, t, _)| t.socket_addr()).collect::<Vec<_>>())
+            .collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 60);
     }
 }
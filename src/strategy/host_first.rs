@@ -4,16 +4,17 @@ use crate::modes::Target;
 pub struct HostFirstStrategy;
 
 impl ScanStrategyTrait for HostFirstStrategy {
-    fn create_targets<'a>(
-        &'a self,
-        hosts: &'a TargetList,
-        ports: &'a PortList,
-    ) -> Box<dyn Iterator<Item = Target> + 'a> {
-        Box::new(
-            hosts.targets.iter().flat_map(move |ip| {
-                ports.ports.iter().map(move |port| Target { ip: ip.clone(), port: *port })
-            }),
-        )
+    fn target_at(&self, hosts: &TargetList, ports: &PortList, index: u64) -> Option<Target> {
+        let port_count = ports.len() as u64;
+        if port_count == 0 {
+            return None;
+        }
+        hosts.target(index / port_count, ports.ports[(index % port_count) as usize])
+    }
+
+    fn last_for_host(&self, hosts: &TargetList, ports: &PortList, index: u64) -> bool {
+        let port_count = ports.len() as u64;
+        port_count > 0 && index / port_count < hosts.len() && index % port_count == port_count - 1
     }
 }
 
@@ -23,7 +24,7 @@ mod tests {
 
     #[test]
     fn host_first_order() {
-        let targets = TargetList { targets: vec!["192.168.1.1".to_string(), "192.168.1.2".to_string()] };
+        let targets = TargetList::from_hosts(&["192.168.1.1", "192.168.1.2"]);
         let ports = PortList { ports: vec![80, 443] };
 
         let targets_iter = HostFirstStrategy.create_targets(&targets, &ports);
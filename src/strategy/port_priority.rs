@@ -0,0 +1,95 @@
+use super::ScanStrategyTrait;
+use crate::modes::Target;
+use crate::configuration::TargetList;
+use crate::configuration::{PortList, Protocol};
+use crate::configuration::top_ports::PortFrequencies;
+
+/// Frequency tiers reported while scanning, by upper bound of the rank.
+const TIERS: [(usize, &str); 3] = [(10, "top 10"), (100, "top 100"), (1000, "top 1000")];
+
+/// Scans ports in descending nmap frequency across all hosts, then the ports
+/// that are not in the frequency table, so an interrupted scan has already
+/// covered the most valuable ports. TCP and UDP ports follow their own table.
+pub struct PortPriorityStrategy {
+    /// Position of each port in the TCP and UDP tables, or `u16::MAX` if it is not listed.
+    rank: [Vec<u16>; 2],
+}
+
+impl Default for PortPriorityStrategy {
+    fn default() -> Self {
+        Self::new(&PortFrequencies::default())
+    }
+}
+
+impl PortPriorityStrategy {
+    pub fn new(frequencies: &PortFrequencies) -> Self {
+        let rank = [Protocol::Tcp, Protocol::Udp].map(|protocol| {
+            let mut rank = vec![u16::MAX; 65536];
+            for (i, port) in frequencies.ranked(protocol).iter().enumerate() {
+                let i = u16::try_from(i).unwrap_or(u16::MAX);
+                rank[*port as usize] = rank[*port as usize].min(i);
+            }
+            rank
+        });
+        Self { rank }
+    }
+
+    /// Ports scanned with every protocol are ranked by the TCP table.
+    fn rank(&self, port: u16, protocol: Option<Protocol>) -> u16 {
+        match protocol {
+            Some(Protocol::Udp) => self.rank[1][port as usize],
+            _ => self.rank[0][port as usize],
+        }
+    }
+}
+
+impl ScanStrategyTrait for PortPriorityStrategy {
+    fn prepare_ports(&self, mut ports: PortList, protocol: Option<Protocol>) -> PortList {
+        // Stable, so unlisted ports keep their (possibly shuffled) order.
+        ports.ports.sort_by_key(|port| self.rank(*port, protocol));
+        ports
+    }
+
+    fn target_at(&self, hosts: &TargetList, ports: &PortList, index: u64) -> Option<Target> {
+        let host_count = hosts.len();
+        if host_count == 0 {
+            return None;
+        }
+        let port = *ports.ports.get((index / host_count) as usize)?;
+        hosts.target(index % host_count, port)
+    }
+
+    fn last_for_host(&self, hosts: &TargetList, ports: &PortList, index: u64) -> bool {
+        !hosts.is_empty() && index / hosts.len() + 1 == ports.len() as u64
+    }
+
+    fn tier(&self, port: u16, protocol: Option<Protocol>) -> Option<&'static str> {
+        let rank = self.rank(port, protocol) as usize;
+        Some(TIERS.iter().find(|(limit, _)| rank < *limit).map_or("other ports", |(_, name)| *name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_common_ports_first_across_hosts() {
+        let strategy = PortPriorityStrategy::default();
+        let hosts = TargetList::from_hosts(&["10.0.0.1", "10.0.0.2"]);
+        let ports = strategy.prepare_ports(PortList { ports: vec![9, 22, 80, 3306, 1] }, Some(Protocol::Tcp));
+        assert_eq!(ports.ports, vec![80, 22, 3306, 9, 1]);
+
+        let targets: Vec<String> = strategy.create_targets(&hosts, &ports).map(|t| t.socket_addr()).collect();
+        assert_eq!(targets[..3], ["10.0.0.1:80", "10.0.0.2:80", "10.0.0.1:22"]);
+        assert_eq!(strategy.tier(80, None), Some("top 10"));
+        assert_eq!(strategy.tier(3306, None), Some("top 100"));
+        assert_eq!(strategy.tier(1, None), Some("top 1000"));
+        assert_eq!(strategy.tier(9, None), Some("top 100"));
+        assert_eq!(strategy.tier(60000, None), Some("other ports"));
+
+        let udp = strategy.prepare_ports(PortList { ports: vec![80, 53, 161] }, Some(Protocol::Udp));
+        assert_eq!(udp.ports, vec![161, 53, 80]);
+        assert_eq!(strategy.tier(161, Some(Protocol::Udp)), Some("top 10"));
+    }
+}
@@ -0,0 +1,90 @@
+use super::ScanStrategyTrait;
+use crate::modes::Target;
+use crate::configuration::TargetList;
+use crate::configuration::PortList;
+
+const FEISTEL_ROUNDS: u64 = 4;
+
+/// Visits the whole host × port space in a seeded pseudo-random order. Index `i`
+/// goes through a Feistel permutation, so no target list is ever built.
+pub struct RandomStrategy {
+    seed: u64,
+}
+
+impl RandomStrategy {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Bijection on `0..size`: a balanced Feistel network over the smallest even
+/// number of bits that covers `size`, cycle-walking values that land outside.
+pub fn permute(index: u64, size: u64, seed: u64) -> u64 {
+    debug_assert!(index < size);
+    let bits = (64 - (size - 1).leading_zeros()).max(2).div_ceil(2) * 2;
+    let half = bits / 2;
+    let mask = u64::MAX >> (64 - half);
+
+    let mut value = index;
+    loop {
+        let (mut left, mut right) = (value >> half, value & mask);
+        for round in 0..FEISTEL_ROUNDS {
+            let f = splitmix64(seed ^ splitmix64(round ^ (right << 8))) & mask;
+            (left, right) = (right, left ^ f);
+        }
+        value = (left << half) | right;
+        if value < size {
+            return value;
+        }
+    }
+}
+
+impl ScanStrategyTrait for RandomStrategy {
+    fn target_at(&self, hosts: &TargetList, ports: &PortList, index: u64) -> Option<Target> {
+        let port_count = ports.len() as u64;
+        let size = hosts.len().checked_mul(port_count)?;
+        if index >= size {
+            return None;
+        }
+        let slot = permute(index, size, self.seed);
+        hosts.target(slot / port_count, ports.ports[(slot % port_count) as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permutation_is_a_seeded_bijection() {
+        for size in [1u64, 2, 3, 17, 256, 1000, 4099] {
+            let mut seen: Vec<u64> = (0..size).map(|i| permute(i, size, 42)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..size).collect::<Vec<_>>());
+        }
+        let a: Vec<u64> = (0..100).map(|i| permute(i, 1000, 1)).collect();
+        let b: Vec<u64> = (0..100).map(|i| permute(i, 1000, 2)).collect();
+        assert_ne!(a, b);
+        assert_eq!(a, (0..100).map(|i| permute(i, 1000, 1)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn random_order_covers_every_target() {
+        let hosts = TargetList::from_hosts(&["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+        let ports = PortList { ports: vec![22, 80, 443] };
+        let mut targets: Vec<String> = RandomStrategy::new(7).create_targets(&hosts, &ports)
+            .map(|t| t.socket_addr())
+            .collect();
+        assert_ne!(targets[0..3], ["10.0.0.1:22", "10.0.0.1:80", "10.0.0.1:443"]);
+        targets.sort();
+        targets.dedup();
+        assert_eq!(targets.len(), 9);
+    }
+}
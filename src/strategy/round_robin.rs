@@ -6,14 +6,17 @@ use crate::configuration::TargetList;
 use crate::configuration::PortList;
 
 impl ScanStrategyTrait for RoundRobinStrategy {
-    fn create_targets<'a>(
-        &'a self,
-        hosts: &'a TargetList,
-        ports: &'a PortList,
-    ) -> Box<dyn Iterator<Item = Target> + 'a> {
-        Box::new(
-            ports.ports.iter().flat_map(move |port| hosts.targets.iter().map(move |target| Target { ip: target.clone(), port: *port }))
-        )
+    fn target_at(&self, hosts: &TargetList, ports: &PortList, index: u64) -> Option<Target> {
+        let host_count = hosts.len();
+        if host_count == 0 {
+            return None;
+        }
+        let port = *ports.ports.get((index / host_count) as usize)?;
+        hosts.target(index % host_count, port)
+    }
+
+    fn last_for_host(&self, hosts: &TargetList, ports: &PortList, index: u64) -> bool {
+        !hosts.is_empty() && index / hosts.len() + 1 == ports.len() as u64
     }
 }
 
@@ -23,7 +26,7 @@ mod tests {
 
     #[test]
     fn round_robin_order() {
-        let targets = TargetList { targets: vec!["192.168.1.1".to_string(), "192.168.1.2".to_string()] };
+        let targets = TargetList::from_hosts(&["192.168.1.1", "192.168.1.2"]);
         let ports = PortList { ports: vec![80, 443] };
 
         let targets = RoundRobinStrategy.create_targets(&targets, &ports);
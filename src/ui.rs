@@ -7,17 +7,20 @@ use std::sync::mpsc;
 use std::io::{Write, BufRead, stderr};
 use std::time::Duration;
 
-use crate::appstate::AppStateManager;
+use crate::appstate::{AppStateManager, ScanStats};
 use crate::configuration::Config;
 use crate::signal_handler::PauseController;
 
 const BAR_WIDTH: usize = 40;
 const TICK_MS: u64 = 50;
+pub const RESUME_FILE: &str = "carescanner.resume";
 
 // --- Public API ---
 
 pub enum TermEvent {
     SetTotal(u64),
+    /// Start a new scan phase: print its description and restart the bar at 0/total.
+    Phase(String, u64),
     Inc(u64),
     Message(String),
     Finish,
@@ -42,6 +45,10 @@ impl TermHandle {
         let _ = self.tx.send(TermEvent::SetTotal(total));
     }
 
+    pub fn phase(&self, description: String, total: u64) {
+        let _ = self.tx.send(TermEvent::Phase(description, total));
+    }
+
     pub fn finish(&self) {
         let _ = self.tx.send(TermEvent::Finish);
     }
@@ -49,6 +56,12 @@ impl TermHandle {
     pub fn exit_early(&self) {
         let _ = self.tx.send(TermEvent::ExitEarly);
     }
+
+    /// A handle whose events go nowhere, for tests without a terminal.
+    #[cfg(test)]
+    pub(crate) fn detached() -> Self {
+        Self { tx: mpsc::channel().0 }
+    }
 }
 
 // --- Internal ---
@@ -65,6 +78,7 @@ struct TermController {
     pause_controller: PauseController,
     app_state_manager: Arc<AppStateManager>,
     config: Arc<Config>,
+    stats: Arc<ScanStats>,
     rt_handle: tokio::runtime::Handle,
     total: u64,
     current: u64,
@@ -79,6 +93,7 @@ pub fn spawn_term_controller(
     pause_controller: PauseController,
     app_state_manager: Arc<AppStateManager>,
     config: Arc<Config>,
+    stats: Arc<ScanStats>,
 ) -> (TermHandle, std::thread::JoinHandle<()>) {
     let (tx, rx) = mpsc::channel();
     let quiet = config.quiet || config.disable_all;
@@ -91,6 +106,7 @@ pub fn spawn_term_controller(
         pause_controller,
         app_state_manager,
         config,
+        stats,
         rt_handle,
         total: 0,
         current: 0,
@@ -160,6 +176,11 @@ impl TermController {
                     self.draw_bar();
                 }
             }
+            TermEvent::Phase(description, total) => {
+                self.handle_event(TermEvent::Message(description));
+                self.current = 0;
+                self.handle_event(TermEvent::SetTotal(total));
+            }
             TermEvent::Inc(delta) => {
                 self.current += delta;
             }
@@ -230,7 +251,7 @@ impl TermController {
                         Ok(path) => eprintln!("Results saved to: {}", path),
                         Err(e) => eprintln!("Error saving results: {}", e),
                     }
-                    if let Err(e) = state.save_resume_file("carescanner.resume") {
+                    if let Err(e) = self.stats.save_resume_file(RESUME_FILE) {
                         eprintln!("Error saving resume file: {}", e);
                     }
                     self.pause_controller.request_exit();
@@ -260,15 +281,20 @@ impl TermController {
         let filled = (BAR_WIDTH as f64 * pct / 100.0) as usize;
         let empty = BAR_WIDTH.saturating_sub(filled);
 
+        let status = self.stats.status_line()
+            .map(|s| format!("  {}", s))
+            .unwrap_or_default();
+
         let mut err = stderr().lock();
         let _ = write!(
             err,
-            "\r\x1b[2K[{}{}] {}/{} ({:.1}%)",
+            "\r\x1b[2K[{}{}] {}/{} ({:.1}%){}",
             "█".repeat(filled),
             "░".repeat(empty),
             self.current,
             self.total,
             pct,
+            status,
         );
         let _ = err.flush();
     }
@@ -1,9 +1,10 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
 use log::{info, error};
 
-use crate::appstate::AppStateManager;
+use crate::appstate::{AppStateManager, ScanStats};
 
 const HTML_PAGE: &str = r#"<!DOCTYPE html>
 <html>
@@ -23,6 +24,7 @@ const HTML_PAGE: &str = r#"<!DOCTYPE html>
   .stat-card.closed .value { color: #ff4444; }
   .stat-card.scanned .value { color: #ffaa00; }
   .stat-card.progress .value { color: #cc88ff; }
+  .stat-card.throttled .value { color: #ff8844; }
   .controls { margin: 1em 0; display: flex; gap: 1em; align-items: center; flex-wrap: wrap; }
   .controls label { color: #888; }
   .controls input, .controls select {
@@ -73,6 +75,11 @@ async function fetchData() {
   }
 }
 
+// PTR names come from whoever controls the reverse zone.
+function esc(s) {
+  return s.replace(/[&<>"']/g, c => ({'&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;'}[c]));
+}
+
 function render() {
   if (!allData) return;
   const d = allData;
@@ -83,7 +90,10 @@ function render() {
     <div class="stat-card open"><div class="value">${d.open_count}</div><div class="label">Open Ports</div></div>
     <div class="stat-card closed"><div class="value">${d.closed_count}</div><div class="label">Closed Ports</div></div>
     <div class="stat-card hosts"><div class="value">${d.host_count}</div><div class="label">Hosts</div></div>
-    <div class="stat-card progress"><div class="value">${d.progress_pct}%</div><div class="label">Progress</div></div>
+    ${d.down_count ? `<div class="stat-card closed"><div class="value">${d.down_count}</div><div class="label">Down (skipped)</div></div>` : ''}
+    <div class="stat-card progress"><div class="value">${d.progress_pct}%</div><div class="label">${d.phases > 1 ? `Phase ${d.phase}/${d.phases}` : 'Progress'}</div></div>
+    ${d.throttled_host ? `<div class="stat-card throttled"><div class="value">${d.throttled_host}</div><div class="label">Throttled (host)</div></div>` : ''}
+    ${d.throttled_subnet ? `<div class="stat-card throttled"><div class="value">${d.throttled_subnet}</div><div class="label">Throttled (subnet)</div></div>` : ''}
   `;
 
   // Filter + search
@@ -91,13 +101,15 @@ function render() {
   const search = document.getElementById('search').value.toLowerCase();
 
   let rows = [];
-  for (const [host, ports] of Object.entries(d.results)) {
+  for (const [ip, ports] of Object.entries(d.results)) {
+    const names = (d.names || {})[ip] || [];
+    const host = names.length ? `${ip} (${names.join(', ')})` : ip;
     for (const p of ports) {
       if (stateFilter === 'open' && p.state !== 'open') continue;
       if (stateFilter === 'closed' && p.state !== 'closed') continue;
       const svc = p.banner || '';
       const portStr = p.number + '/' + p.protocol;
-      if (search && !host.includes(search) && !portStr.includes(search) && !svc.toLowerCase().includes(search)) continue;
+      if (search && !host.toLowerCase().includes(search) && !portStr.includes(search) && !svc.toLowerCase().includes(search)) continue;
       rows.push({ host, port: p.number, portStr, state: p.state, svc });
     }
   }
@@ -125,7 +137,7 @@ function render() {
   </tr>`;
   for (const r of rows) {
     const cls = r.state === 'open' ? 'open' : 'closed';
-    html += `<tr><td>${r.host}</td><td>${r.portStr}</td><td class="${cls}">${r.state}</td><td class="banner">${r.svc}</td></tr>`;
+    html += `<tr><td>${esc(r.host)}</td><td>${r.portStr}</td><td class="${cls}">${r.state}</td><td class="banner">${r.svc}</td></tr>`;
   }
   html += '</table>';
   document.getElementById('results').innerHTML = html;
@@ -146,11 +158,13 @@ setInterval(fetchData, 3000);
 </html>"#;
 
 /// Start the web dashboard on the given port. Runs in the background.
+/// Progress is that of the current one of the scan's `phases`.
 pub fn spawn_dashboard(
     host: &str,
     port: u16,
-    total_targets: u64,
+    phases: usize,
     app_state_manager: Arc<AppStateManager>,
+    stats: Arc<ScanStats>,
 ) {
     let addr = format!("{}:{}", host, port);
     tokio::spawn(async move {
@@ -170,6 +184,7 @@ pub fn spawn_dashboard(
             };
 
             let asm = Arc::clone(&app_state_manager);
+            let stats = Arc::clone(&stats);
             tokio::spawn(async move {
                 let mut buf = vec![0u8; 4096];
                 let n = match tokio::io::AsyncReadExt::read(&mut stream, &mut buf).await {
@@ -190,19 +205,31 @@ pub fn spawn_dashboard(
                         .filter(|p| p.state == crate::appstate::PortState::Closed)
                         .count();
                     let scanned = open_count + closed_count;
-                    let progress_pct = if total_targets > 0 {
-                        format!("{:.1}", scanned as f64 / total_targets as f64 * 100.0)
+                    let (phase, done, total) = stats.phase_progress();
+                    let progress_pct = if total > 0 {
+                        format!("{:.1}", done.min(total) as f64 / total as f64 * 100.0)
                     } else {
                         "0.0".to_string()
                     };
+                    // Given hostnames and PTR names per host, for display and search.
+                    let names: HashMap<&String, Vec<&str>> = results.keys()
+                        .map(|host| (host, state.all_names(host)))
+                        .filter(|(_, names)| !names.is_empty())
+                        .collect();
                     let json = format!(
-                        r#"{{"scanned":{},"open_count":{},"closed_count":{},"host_count":{},"progress_pct":{},"results":{}}}"#,
+                        r#"{{"scanned":{},"open_count":{},"closed_count":{},"host_count":{},"down_count":{},"phase":{},"phases":{},"progress_pct":{},"throttled_host":{},"throttled_subnet":{},"results":{},"names":{}}}"#,
                         scanned,
                         open_count,
                         closed_count,
                         results.len(),
+                        state.down_hosts().len(),
+                        phase + 1,
+                        phases,
                         progress_pct,
+                        stats.throttled_host.load(std::sync::atomic::Ordering::Relaxed),
+                        stats.throttled_subnet.load(std::sync::atomic::Ordering::Relaxed),
                         serde_json::to_string(&results).unwrap_or_else(|_| "{}".to_string()),
+                        serde_json::to_string(&names).unwrap_or_else(|_| "{}".to_string()),
                     );
                     ("200 OK", "application/json", json)
                 } else if request.starts_with("GET /") {
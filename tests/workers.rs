@@ -0,0 +1,87 @@
+// A coordinator and two worker processes of the built binary scanning loopback.
+
+use std::net::TcpListener;
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+const BIN: &str = env!("CARGO_BIN_EXE_carescanner");
+
+/// Kills the process if the test gives up on it.
+struct Running(Option<Child>);
+
+impl Running {
+    fn spawn(command: &mut Command) -> Self {
+        Self(Some(command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap()))
+    }
+
+    fn wait(mut self, patience: Duration) -> Output {
+        let give_up = Instant::now() + patience;
+        let child = self.0.as_mut().unwrap();
+        while child.try_wait().unwrap().is_none() {
+            assert!(Instant::now() < give_up, "still running after {:?}", patience);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        self.0.take().unwrap().wait_with_output().unwrap()
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.0 {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[test]
+fn two_worker_processes_scan_every_port() {
+    let open: Vec<TcpListener> = (0..3).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let open_ports: Vec<u16> = open.iter().map(|l| l.local_addr().unwrap().port()).collect();
+    let address = {
+        let free = TcpListener::bind("127.0.0.1:0").unwrap();
+        free.local_addr().unwrap().to_string()
+    };
+    let dir = std::env::temp_dir().join(format!("carescanner-workers-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("results.json");
+
+    // Slow enough that both workers get chunks.
+    let ports = format!("{},1-100", open_ports.iter().map(u16::to_string).collect::<Vec<_>>().join(","));
+    let coordinator = Running::spawn(Command::new(BIN).env("CARESCANNER_COORDINATOR_TOKEN", "lab-token").args([
+        "-t", "127.0.0.1", "-p", &ports, "-r", "100", "--chunk-size", "5",
+        "--coordinator", &address, "-f", "json", "-o", output.to_str().unwrap(),
+    ]));
+    let workers: Vec<Running> = ["a", "b"]
+        .map(|name| Running::spawn(Command::new(BIN).env("CARESCANNER_COORDINATOR_TOKEN", "lab-token")
+            .args(["worker", "--coordinator", &address, "--name", name])))
+        .into();
+    // Without the token a worker is turned away.
+    let stranger = Running::spawn(Command::new(BIN).env_remove("CARESCANNER_COORDINATOR_TOKEN")
+        .args(["worker", "--coordinator", &address, "--name", "stranger"]));
+
+    let coordinated = coordinator.wait(Duration::from_secs(60));
+    let log = format!("{}{}", String::from_utf8_lossy(&coordinated.stdout), String::from_utf8_lossy(&coordinated.stderr));
+    assert!(coordinated.status.success(), "{}", log);
+    for name in ["a", "b"] {
+        assert!(log.contains(&format!("Worker {} (", name)), "worker {} never joined: {}", name, log);
+    }
+    for worker in workers {
+        let worked = worker.wait(Duration::from_secs(10));
+        assert!(worked.status.success(), "{}", String::from_utf8_lossy(&worked.stderr));
+    }
+    let refused = stranger.wait(Duration::from_secs(10));
+    assert!(!refused.status.success());
+    assert!(log.contains("Rejected worker") && !log.contains("Worker stranger ("), "{}", log);
+
+    let results: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    let scanned: Vec<(u64, &str)> = results["127.0.0.1"].as_array().unwrap().iter()
+        .map(|port| (port["number"].as_u64().unwrap(), port["state"].as_str().unwrap()))
+        .collect();
+    assert_eq!(scanned.len(), 100 + open_ports.len(), "every port once");
+    for port in open_ports {
+        assert!(scanned.contains(&(port as u64, "open")), "port {} not reported open", port);
+    }
+    drop(open);
+}